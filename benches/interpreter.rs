@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use naom::constants::SCRIPT_VERSION_V0;
+use naom::crypto::sign_ed25519 as sign;
+use naom::script::lang::{Script, Stack};
+
+/// Builds a signed P2PKH unlocking script
+fn p2pkh_script() -> Script {
+    let (pk, sk) = sign::gen_keypair();
+    let check_data = hex::encode(vec![0, 0, 0]);
+    let sig = sign::sign_detached(check_data.as_bytes(), &sk);
+    Script::pay2pkh(check_data, sig, pk, None)
+}
+
+/// Builds a signed 2-of-3 multisig validation script
+fn multisig_script() -> Script {
+    let (pk1, sk1) = sign::gen_keypair();
+    let (pk2, sk2) = sign::gen_keypair();
+    let (pk3, _) = sign::gen_keypair();
+    let check_data = hex::encode(vec![0, 0, 0]);
+    let sig1 = sign::sign_detached(check_data.as_bytes(), &sk1);
+    let sig2 = sign::sign_detached(check_data.as_bytes(), &sk2);
+    Script::multisig_validation(2, 3, check_data, vec![sig1, sig2], vec![pk1, pk2, pk3])
+}
+
+fn bench_p2pkh(c: &mut Criterion) {
+    let script = p2pkh_script();
+    c.bench_function("p2pkh_interpret", |b| {
+        b.iter(|| assert!(black_box(&script).interpret()))
+    });
+
+    let mut stack = Stack::new();
+    c.bench_function("p2pkh_interpret_reused_stack", |b| {
+        b.iter(|| assert!(black_box(&script).interpret_with_stack(&mut stack, SCRIPT_VERSION_V0)))
+    });
+}
+
+fn bench_multisig(c: &mut Criterion) {
+    let script = multisig_script();
+    c.bench_function("multisig_2_of_3_interpret", |b| {
+        b.iter(|| assert!(black_box(&script).interpret()))
+    });
+
+    let mut stack = Stack::new();
+    c.bench_function("multisig_2_of_3_interpret_reused_stack", |b| {
+        b.iter(|| assert!(black_box(&script).interpret_with_stack(&mut stack, SCRIPT_VERSION_V0)))
+    });
+}
+
+criterion_group!(benches, bench_p2pkh, bench_multisig);
+criterion_main!(benches);
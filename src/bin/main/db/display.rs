@@ -89,6 +89,9 @@ pub fn list_assets(db_path: String) {
                         Asset::Token(v) => ("Token", v.to_string()),
                         Asset::Data(v) => ("Data", String::from_utf8_lossy(&v.data).to_string()),
                         Asset::Receipt(v) => ("Receipt", v.to_string()),
+                        Asset::Issued(v) => ("Issued", v.amount.to_string()),
+                        Asset::Item(v) => ("Item", v.serial.to_string()),
+                        Asset::Bundle(v) => ("Bundle", format!("{:?}", v)),
                     };
 
                     let drs_root_hash = match &output.drs_block_hash {
@@ -4,16 +4,38 @@ pub const RECEIPT_DEFAULT_DRS_TX_HASH: &str = "default_drs_tx_hash";
 pub const MAX_METADATA_BYTES: usize = 800;
 pub const TX_HASH_LENGTH: usize = 32;
 
+/*------- DRUID CONSTANTS -------*/
+// Prefix of a derived DRUID
+pub const DRUID_PREFIX: &str = "DRUID0x";
+// Number of random bytes mixed into a DRUID derivation
+pub const DRUID_NONCE_LEN: usize = 8;
+// Number of bytes of the derivation hash kept in a DRUID
+pub const DRUID_HASH_LEN: usize = 16;
+// Length of a derived DRUID: the prefix, then the hex nonce and hash
+pub const DRUID_LENGTH: usize = DRUID_PREFIX.len() + 2 * (DRUID_NONCE_LEN + DRUID_HASH_LEN);
+// Maximum number of pending DRUIDs held by a DRUID pool
+pub const MAX_DRUID_POOL_SIZE: usize = 1000;
+
 /*------- ADDRESS CONSTANTS -------*/
 pub const V0_ADDRESS_LENGTH: usize = 16;
 pub const STANDARD_ADDRESS_LENGTH: usize = 64;
 // Prepending character for a P2SH address
 pub const P2SH_PREPEND: u8 = b'H';
+// Marker following the P2SH prepending character in addresses of scripts above
+// version 0, followed by the script version as two hex digits. It is not a hex
+// digit, so it cannot collide with the hash of a version 0 address
+pub const P2SH_VERSION_MARKER: u8 = b'v';
 
 /*------- NETWORK CONSTANTS --------*/
 // Current network version: Always bump immediately after a version is deployed.
-pub const NETWORK_VERSION: u32 = 5;
-pub const NETWORK_VERSION_SERIALIZED: &[u8] = b"5";
+pub const NETWORK_VERSION: u32 = 6;
+pub const NETWORK_VERSION_SERIALIZED: &[u8] = b"6";
+// Last network version whose transactions encode DDE values without expectation
+// comparisons and expiry heights
+pub const NETWORK_VERSION_LEGACY_DDE: usize = 5;
+// Last network version whose transactions allow free-form receipt metadata, which
+// is left out of the signable string of create scripts
+pub const NETWORK_VERSION_LEGACY_RECEIPT_METADATA: usize = 5;
 // Network version 0
 pub const NETWORK_VERSION_V0: u64 = 0;
 // Network version to support temporary address structure on wallet
@@ -24,12 +46,29 @@ pub const NETWORK_VERSION_TEMP: u64 = 99999;
 // Number of decimal places to divide to in display
 pub const D_DISPLAY_PLACES_U64: u64 = 25200;
 pub const D_DISPLAY_PLACES: f64 = 25200.0;
+// Maximum number of fractional digits when displaying or parsing token amounts.
+// Five digits are enough to identify every one of the 25200 units in a token
+pub const D_DISPLAY_DECIMALS: u32 = 5;
 // Number of possible tokens in existence (10 billion)
 pub const TOTAL_TOKENS: u64 = D_DISPLAY_PLACES_U64 * 10000000000;
 
 /*------- ASSET CONSTANTS -------*/
 // The value to sign/verify for receipt-based payments
 pub const RECEIPT_ACCEPT_VAL: &str = "PAYMENT_ACCEPT";
+// Schema version of structured receipt metadata
+pub const RECEIPT_METADATA_SCHEMA_VERSION: u32 = 1;
+// Maximum number of decimal places an issued token can be displayed with
+pub const MAX_ISSUED_TOKEN_DECIMALS: u8 = 18;
+// Maximum number of bytes of a chunk of a large data asset
+pub const DATA_CHUNK_SIZE: usize = 512;
+// Prefix identifying a data asset that holds a chunk manifest
+pub const DATA_MANIFEST_PREFIX: &[u8] = b"manifest:";
+// Prefix identifying a data asset sealed for a recipient
+pub const ENCRYPTED_DATA_PREFIX: &[u8] = b"encrypted:";
+// Minimum number of token units of a standard output (0.01 tokens)
+pub const DUST_TOKEN_AMOUNT: u64 = 252;
+// Minimum amount of receipts, issued tokens or data assets of a standard output
+pub const DUST_ASSET_AMOUNT: u64 = 1;
 
 /*------- BLOCK CONSTANTS --------*/
 // Maximum number of bytes that a block can contain
@@ -46,6 +85,16 @@ pub const MAX_PUB_KEYS_PER_MULTISIG: u8 = 20;
 pub const MAX_SCRIPT_SIZE: u16 = 10000;
 // Maximum number of values on script interpreter stack
 pub const MAX_STACK_SIZE: u16 = 1000;
+// Script version of outputs locked under the original rule set, where every
+// upgradable NOP slot does nothing
+pub const SCRIPT_VERSION_V0: u64 = 0;
+// Script version activating OP_NOP1 as OP_CHECKSHA3VERIFY
+pub const SCRIPT_VERSION_V1: u64 = 1;
+// Script version from which splice, hash and signature opcodes operate on the raw
+// bytes of stack items, and item sizes are counted in bytes rather than hex characters
+pub const SCRIPT_VERSION_V2: u64 = 2;
+// Highest script version that can be encoded in a P2SH address
+pub const MAX_SCRIPT_VERSION: u64 = 0xff;
 // Threshold for lock_time: below this value it is interpreted as block number,
 // otherwise as UNIX timestamp.
 pub const LOCKTIME_THRESHOLD: u32 = 500000000; // Tue Nov 5 00:53:20 1985 UTC
@@ -289,6 +338,23 @@ pub const OPCHECKMULTISIG_DESC: &str =
     "Pushes ONE onto the stack if the m-of-n multi-signature is valid, ZERO otherwise";
 pub const OPCHECKMULTISIGVERIFY_DESC: &str = "Runs OP_CHECKMULTISIG and OP_VERIFY in sequence";
 
+// upgradable nops
+pub const OPNOP1: &str = "OP_NOP1";
+pub const OPNOP2: &str = "OP_NOP2";
+pub const OPNOP3: &str = "OP_NOP3";
+pub const OPNOP4: &str = "OP_NOP4";
+pub const OPNOP5: &str = "OP_NOP5";
+pub const OPNOP6: &str = "OP_NOP6";
+pub const OPNOP7: &str = "OP_NOP7";
+pub const OPNOP8: &str = "OP_NOP8";
+pub const OPNOP9: &str = "OP_NOP9";
+pub const OPNOP10: &str = "OP_NOP10";
+pub const OPCHECKSHA3VERIFY: &str = "OP_CHECKSHA3VERIFY";
+
+pub const OPNOPX_DESC: &str = "Reserved for a future upgrade; does nothing under the active rules";
+pub const OPCHECKSHA3VERIFY_DESC: &str =
+    "Ends execution with an error if the top item is not the SHA3-256 hash of the second-to-top item";
+
 /*------- ERROR MESSAGES -------*/
 // opcodes
 pub const ERROR_EMPTY_CONDITION: &str = "Condition stack is empty";
@@ -305,8 +371,11 @@ pub const ERROR_INVALID_SIGNATURE: &str = "Signature is not valid";
 pub const ERROR_INVALID_MULTISIGNATURE: &str = "Multi-signature is not valid";
 pub const ERROR_NUM_PUBKEYS: &str = "Number of public keys provided is not correct";
 pub const ERROR_NUM_SIGNATURES: &str = "Number of signatures provided is not correct";
+pub const ERROR_HASH_MISMATCH: &str = "The top item is not the hash of the second-to-top item";
 // script
 pub const ERROR_MAX_SCRIPT_SIZE: &str = "Script size exceeds MAX_SCRIPT_SIZE-byte limit";
+pub const ERROR_LEGACY_ITEM: &str =
+    "Items held as strings are not valid in scripts with byte items";
 pub const ERROR_MAX_STACK_SIZE: &str = "Stack size exceeds MAX_STACK_SIZE limit";
 pub const ERROR_MAX_OPS_SCRIPT: &str =
     "Number of opcodes in script exceeds MAX_OPS_PER_SCRIPT limit";
@@ -211,6 +211,112 @@ pub mod secretbox_chacha20_poly1305 {
     }
 }
 
+pub mod sealedbox_x25519_chacha20_poly1305 {
+    // Anonymous sealed box: an ephemeral X25519 key agreement with the recipient, followed
+    // by `secretbox_chacha20_poly1305` under a key derived from the shared secret.
+    //
+    // The recipient's static X25519 key is derived from their Ed25519 secret key with
+    // HKDF-SHA256 under a label used for nothing else. The Ed25519 key material is only
+    // ever input to the KDF, never used as an X25519 scalar, so the derived key is
+    // independent of the signing key and owners need a single secret for both.
+    use super::secretbox_chacha20_poly1305::{self as secretbox, Nonce, NONCE_LEN};
+    use super::sign_ed25519::SecretKey;
+    use super::{deserialize_slice, sha3_256};
+    use rand::rngs::OsRng;
+    use ring::hkdf::{Salt, HKDF_SHA256};
+    use serde::{Deserialize, Serialize};
+    use std::convert::TryInto;
+    use x25519_dalek::{EphemeralSecret, SharedSecret, StaticSecret};
+
+    pub const PUBLIC_KEY_LEN: usize = 32;
+    const STATIC_KEY_SALT: &[u8] = b"naom sealedbox_x25519_chacha20_poly1305";
+    const STATIC_KEY_INFO: &[u8] = b"static x25519 key v1";
+
+    /// Public key to seal data for
+    #[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct PublicKey(
+        #[serde(serialize_with = "<[_]>::serialize")]
+        #[serde(deserialize_with = "deserialize_slice")]
+        [u8; PUBLIC_KEY_LEN],
+    );
+
+    impl PublicKey {
+        pub fn from_slice(slice: &[u8]) -> Option<Self> {
+            Some(Self(slice.try_into().ok()?))
+        }
+
+        fn to_x25519(self) -> x25519_dalek::PublicKey {
+            x25519_dalek::PublicKey::from(self.0)
+        }
+    }
+
+    impl AsRef<[u8]> for PublicKey {
+        fn as_ref(&self) -> &[u8] {
+            self.0.as_ref()
+        }
+    }
+
+    /// Gets the public key that data must be sealed for to be opened with `sk`
+    pub fn public_key_from_secret(sk: &SecretKey) -> Option<PublicKey> {
+        let private_key = static_private_key(sk)?;
+        Some(PublicKey(
+            x25519_dalek::PublicKey::from(&private_key).to_bytes(),
+        ))
+    }
+
+    pub fn seal(plain_text: Vec<u8>, pk: &PublicKey) -> Option<Vec<u8>> {
+        let private_key = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_pk = PublicKey(x25519_dalek::PublicKey::from(&private_key).to_bytes());
+        let shared = private_key.diffie_hellman(&pk.to_x25519());
+        let key = session_key(&shared, &ephemeral_pk, pk)?;
+        let nonce = secretbox::gen_nonce();
+
+        let mut sealed = ephemeral_pk.as_ref().to_vec();
+        sealed.extend_from_slice(nonce.as_ref());
+        sealed.extend(secretbox::seal(plain_text, &nonce, &key)?);
+        Some(sealed)
+    }
+
+    pub fn open(sealed: Vec<u8>, sk: &SecretKey) -> Option<Vec<u8>> {
+        if sealed.len() < PUBLIC_KEY_LEN + NONCE_LEN {
+            return None;
+        }
+        let ephemeral_pk = PublicKey::from_slice(&sealed[..PUBLIC_KEY_LEN])?;
+        let nonce = Nonce::from_slice(&sealed[PUBLIC_KEY_LEN..PUBLIC_KEY_LEN + NONCE_LEN])?;
+        let private_key = static_private_key(sk)?;
+        let pk = PublicKey(x25519_dalek::PublicKey::from(&private_key).to_bytes());
+        let shared = private_key.diffie_hellman(&ephemeral_pk.to_x25519());
+        let key = session_key(&shared, &ephemeral_pk, &pk)?;
+
+        secretbox::open(sealed[PUBLIC_KEY_LEN + NONCE_LEN..].to_vec(), &nonce, &key)
+    }
+
+    fn static_private_key(sk: &SecretKey) -> Option<StaticSecret> {
+        let prk = Salt::new(HKDF_SHA256, STATIC_KEY_SALT).extract(sk.as_ref());
+        let okm = prk.expand(&[STATIC_KEY_INFO], HKDF_SHA256).ok()?;
+        let mut scalar = [0; 32];
+        okm.fill(&mut scalar).ok()?;
+        Some(StaticSecret::from(scalar))
+    }
+
+    fn session_key(
+        shared: &SharedSecret,
+        ephemeral_pk: &PublicKey,
+        recipient_pk: &PublicKey,
+    ) -> Option<secretbox::Key> {
+        // Low order points give a shared secret independent of the private key
+        if !shared.was_contributory() {
+            return None;
+        }
+        let parts = [
+            shared.as_bytes(),
+            ephemeral_pk.as_ref(),
+            recipient_pk.as_ref(),
+        ];
+        secretbox::Key::from_slice(&sha3_256::digest_all(parts.iter().copied()))
+    }
+}
+
 pub mod pbkdf2 {
     use super::{deserialize_slice, generate_random};
     use ring::pbkdf2::{derive, PBKDF2_HMAC_SHA256};
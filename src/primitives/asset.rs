@@ -1,7 +1,20 @@
+use crate::constants::{
+    DUST_ASSET_AMOUNT, DUST_TOKEN_AMOUNT, D_DISPLAY_DECIMALS, D_DISPLAY_PLACES_U64,
+    ENCRYPTED_DATA_PREFIX, MAX_ISSUED_TOKEN_DECIMALS, MAX_METADATA_BYTES,
+    RECEIPT_METADATA_SCHEMA_VERSION, TOTAL_TOKENS,
+};
+use crate::crypto::sealedbox_x25519_chacha20_poly1305 as sealedbox;
+use crate::crypto::sign_ed25519::{PublicKey, SecretKey};
 use crate::primitives::transaction::OutPoint;
-use crate::utils::{add_btreemap, format_for_display};
+use crate::utils::format_for_display;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt, iter, mem::size_of, ops};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    mem::size_of,
+    ops,
+    str::FromStr,
+};
 
 /// A structure representing the amount of tokens in an instance
 #[derive(Deserialize, Serialize, Default, Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
@@ -14,31 +27,97 @@ impl fmt::Display for TokenAmount {
     }
 }
 
-impl ops::Add for TokenAmount {
-    type Output = Self;
+/// Errors raised when parsing a `TokenAmount` from a decimal string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenAmountParseError {
+    InvalidFormat,
+    TooManyFractionalDigits,
+    ExceedsTotalTokens,
+    Inexact,
+}
 
-    fn add(self, other: Self) -> Self {
-        Self(self.0 + other.0)
+impl fmt::Display for TokenAmountParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "Invalid token amount format"),
+            Self::TooManyFractionalDigits => write!(
+                f,
+                "Token amount has more than {D_DISPLAY_DECIMALS} fractional digits"
+            ),
+            Self::ExceedsTotalTokens => write!(f, "Token amount exceeds total tokens"),
+            Self::Inexact => write!(f, "Token amount is not a whole number of token units"),
+        }
     }
 }
 
-impl ops::AddAssign for TokenAmount {
-    fn add_assign(&mut self, other: Self) {
-        self.0 += other.0;
+impl FromStr for TokenAmount {
+    type Err = TokenAmountParseError;
+
+    /// Parses a decimal token amount such as "12.5" into base units. Amounts are
+    /// only accepted if they are exactly the display of an amount of units, so
+    /// that no amount is rounded away: "0.00004" is one unit, while "0.00001"
+    /// lies between units and is rejected
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (whole, fraction) = match s.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (s, None),
+        };
+        let is_digits = |d: &str| !d.is_empty() && d.bytes().all(|b| b.is_ascii_digit());
+        if !is_digits(whole) || matches!(fraction, Some(f) if !is_digits(f)) {
+            return Err(TokenAmountParseError::InvalidFormat);
+        }
+
+        let fraction = fraction.unwrap_or_default();
+        let decimals = D_DISPLAY_DECIMALS as usize;
+        if fraction.len() > decimals {
+            return Err(TokenAmountParseError::TooManyFractionalDigits);
+        }
+        let scale = 10_u64.pow(D_DISPLAY_DECIMALS);
+        let fraction_digits: u64 = format!("{fraction:0<decimals$}")
+            .parse()
+            .map_err(|_| TokenAmountParseError::InvalidFormat)?;
+        let fraction_units = (fraction_digits * D_DISPLAY_PLACES_U64 + scale / 2) / scale;
+
+        let whole = whole
+            .parse::<u64>()
+            .map_err(|_| TokenAmountParseError::ExceedsTotalTokens)?;
+        let amount = whole
+            .checked_mul(D_DISPLAY_PLACES_U64)
+            .and_then(|w| w.checked_add(fraction_units))
+            .filter(|units| *units <= TOTAL_TOKENS)
+            .map(Self)
+            .ok_or(TokenAmountParseError::ExceedsTotalTokens)?;
+
+        let fraction = fraction.trim_end_matches('0');
+        let canonical = match fraction {
+            "" => whole.to_string(),
+            fraction => format!("{whole}.{fraction}"),
+        };
+        if amount.to_string() != canonical {
+            return Err(TokenAmountParseError::Inexact);
+        }
+        Ok(amount)
     }
 }
 
-impl ops::Sub for TokenAmount {
-    type Output = Self;
+/// Serde helpers to (de)serialize a `TokenAmount` as a decimal string such as "12.5",
+/// for use with `#[serde(with = "token_amount_decimal")]`
+pub mod token_amount_decimal {
+    use super::TokenAmount;
+    use serde::{de, Deserialize, Deserializer, Serializer};
 
-    fn sub(self, other: Self) -> Self {
-        Self(self.0 - other.0)
+    pub fn serialize<S: Serializer>(
+        amount: &TokenAmount,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.to_string())
     }
-}
 
-impl ops::SubAssign for TokenAmount {
-    fn sub_assign(&mut self, other: Self) {
-        self.0 -= other.0;
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<TokenAmount, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
     }
 }
 
@@ -56,23 +135,30 @@ impl ops::DivAssign<u64> for TokenAmount {
     }
 }
 
-impl ops::Mul<u64> for TokenAmount {
-    type Output = Self;
+impl TokenAmount {
+    /// Adds two amounts, returning `None` on overflow
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
 
-    fn mul(self, rhs: u64) -> Self {
-        Self(self.0 * rhs)
+    /// Subtracts `rhs` from `self`, returning `None` on underflow
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
     }
-}
 
-impl ops::MulAssign<u64> for TokenAmount {
-    fn mul_assign(&mut self, rhs: u64) {
-        self.0 *= rhs;
+    /// Multiplies the amount, returning `None` on overflow
+    pub fn checked_mul(self, rhs: u64) -> Option<Self> {
+        self.0.checked_mul(rhs).map(Self)
     }
-}
 
-impl iter::Sum for TokenAmount {
-    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
-        iter.fold(Self::default(), |r, l| r + l)
+    /// Adds two amounts, saturating at `u64::MAX`
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    /// Subtracts `rhs` from `self`, saturating at zero
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
     }
 }
 
@@ -94,6 +180,317 @@ impl ReceiptAsset {
     }
 }
 
+impl ReceiptAsset {
+    /// Decodes the structured metadata of the receipt, if any
+    pub fn structured_metadata(&self) -> Result<Option<ReceiptMetadata>, ReceiptMetadataError> {
+        self.metadata
+            .as_deref()
+            .map(ReceiptMetadata::decode)
+            .transpose()
+    }
+}
+
+/// Supply of a receipt, as seen in created and burned outputs.
+/// Also used for the supply of other assets, see `update_asset_supply`
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ReceiptSupply {
+    pub created: u64,
+    pub burned: u64,
+}
+
+impl ReceiptSupply {
+    /// Amount of the receipt that has been created and not burned
+    pub fn outstanding(&self) -> u64 {
+        self.created.saturating_sub(self.burned)
+    }
+}
+
+/// Errors raised when decoding or validating receipt metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReceiptMetadataError {
+    Malformed,
+    NonCanonical,
+    UnsupportedSchemaVersion(u32),
+    EmptyName,
+    InvalidMediaHash,
+    EmptyAttributeKey,
+    TooLarge,
+}
+
+impl fmt::Display for ReceiptMetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "Receipt metadata is malformed"),
+            Self::NonCanonical => write!(f, "Receipt metadata is not canonically encoded"),
+            Self::UnsupportedSchemaVersion(v) => {
+                write!(f, "Unsupported receipt metadata schema version: {v}")
+            }
+            Self::EmptyName => write!(f, "Receipt metadata name is empty"),
+            Self::InvalidMediaHash => write!(f, "Receipt metadata media hash is not SHA3-256"),
+            Self::EmptyAttributeKey => write!(f, "Receipt metadata has an empty attribute key"),
+            Self::TooLarge => write!(f, "Receipt metadata is too large"),
+        }
+    }
+}
+
+/// Structured receipt metadata.
+///
+/// Metadata is carried in `ReceiptAsset::metadata` in its canonical encoding:
+/// compact JSON with fields in declaration order and attributes sorted by key.
+/// Only the canonical encoding decodes, so every piece of metadata has exactly
+/// one signable representation
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(deny_unknown_fields)]
+pub struct ReceiptMetadata {
+    pub schema_version: u32,
+    pub name: String,
+    pub description: String,
+    pub media_hash: Option<String>, /* Hex encoded SHA3-256 hash of the media */
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl ReceiptMetadata {
+    /// Creates metadata of the current schema version without media or attributes
+    ///
+    /// ### Arguments
+    ///
+    /// * `name`        - Name of the receipt
+    /// * `description` - Description of the receipt
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            schema_version: RECEIPT_METADATA_SCHEMA_VERSION,
+            name,
+            description,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the canonical encoding of the metadata
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Decodes and validates canonically encoded metadata
+    ///
+    /// ### Arguments
+    ///
+    /// * `encoded` - Canonical encoding of the metadata
+    pub fn decode(encoded: &str) -> Result<Self, ReceiptMetadataError> {
+        let metadata: Self =
+            serde_json::from_str(encoded).map_err(|_| ReceiptMetadataError::Malformed)?;
+        if metadata.encode() != encoded {
+            return Err(ReceiptMetadataError::NonCanonical);
+        }
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    /// Checks the schema version, the required fields and the encoded size
+    pub fn validate(&self) -> Result<(), ReceiptMetadataError> {
+        if self.schema_version != RECEIPT_METADATA_SCHEMA_VERSION {
+            return Err(ReceiptMetadataError::UnsupportedSchemaVersion(
+                self.schema_version,
+            ));
+        }
+        if self.name.is_empty() {
+            return Err(ReceiptMetadataError::EmptyName);
+        }
+        if let Some(hash) = &self.media_hash {
+            let is_lower_hex = |c: char| c.is_ascii_digit() || ('a'..='f').contains(&c);
+            if hash.len() != 64 || !hash.chars().all(is_lower_hex) {
+                return Err(ReceiptMetadataError::InvalidMediaHash);
+            }
+        }
+        if self.attributes.keys().any(|k| k.is_empty()) {
+            return Err(ReceiptMetadataError::EmptyAttributeKey);
+        }
+        if self.encode().len() > MAX_METADATA_BYTES {
+            return Err(ReceiptMetadataError::TooLarge);
+        }
+        Ok(())
+    }
+}
+
+/// Supply rule of an issued token
+///
+/// * `Fixed`   - The amount created at genesis is the entire supply
+/// * `Max`     - The issuer may re-issue up to the given total supply
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
+pub enum SupplyRule {
+    Fixed,
+    Max(u64),
+}
+
+/// Definition of an issued token, set once by its genesis transaction
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+pub struct IssuanceDefinition {
+    pub issuer: PublicKey,
+    pub decimals: u8,
+    pub supply: SupplyRule,
+}
+
+impl IssuanceDefinition {
+    /// Checks whether `issued` units in total are allowed by the supply rule
+    ///
+    /// ### Arguments
+    ///
+    /// * `issued`          - Total amount issued including the new issuance
+    /// * `is_genesis`      - Whether the amount is issued by the genesis transaction
+    pub fn allows_supply(&self, issued: u64, is_genesis: bool) -> bool {
+        match self.supply {
+            SupplyRule::Fixed => is_genesis,
+            SupplyRule::Max(max) => issued <= max,
+        }
+    }
+
+    /// Checks that the definition itself is well formed
+    pub fn is_valid(&self) -> bool {
+        self.decimals <= MAX_ISSUED_TOKEN_DECIMALS
+    }
+}
+
+/// Issued token asset struct
+///
+/// Like receipts, the genesis output has a `None` `genesis_hash`, which is set to
+/// the genesis transaction hash once spent. Only the genesis output carries the
+/// `definition`
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+pub struct IssuedAsset {
+    pub amount: u64,
+    pub genesis_hash: Option<String>,
+    pub definition: Option<IssuanceDefinition>,
+}
+
+impl IssuedAsset {
+    pub fn new(
+        amount: u64,
+        genesis_hash: Option<String>,
+        definition: Option<IssuanceDefinition>,
+    ) -> Self {
+        Self {
+            amount,
+            genesis_hash,
+            definition,
+        }
+    }
+}
+
+/// Definition of a non-fungible item collection, set once by its genesis transaction
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+pub struct CollectionDefinition {
+    pub creator: PublicKey,
+    pub max_items: Option<u64>,
+}
+
+impl CollectionDefinition {
+    /// Checks whether an item with the given serial may exist in the collection
+    ///
+    /// ### Arguments
+    ///
+    /// * `serial`  - Serial of the item
+    pub fn allows_serial(&self, serial: u64) -> bool {
+        match self.max_items {
+            Some(max_items) => serial < max_items,
+            None => true,
+        }
+    }
+}
+
+/// Non-fungible item asset struct. Items are indivisible and identified by the
+/// pair of `collection_hash` and `serial`
+///
+/// The genesis output of a collection is its item with serial 0, which carries the
+/// `definition` and has a `None` `collection_hash` until spent, when it is set to the
+/// genesis transaction hash. `metadata` is only present when an item is minted
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+pub struct ItemAsset {
+    pub collection_hash: Option<String>,
+    pub serial: u64,
+    pub metadata: Option<String>,
+    pub definition: Option<CollectionDefinition>,
+}
+
+impl ItemAsset {
+    pub fn new(
+        collection_hash: Option<String>,
+        serial: u64,
+        metadata: Option<String>,
+        definition: Option<CollectionDefinition>,
+    ) -> Self {
+        Self {
+            collection_hash,
+            serial,
+            metadata,
+            definition,
+        }
+    }
+
+    /// Returns whether `self` and `other` are the same item, ignoring mint-only fields
+    ///
+    /// ### Arguments
+    ///
+    /// * `other`   - Item to compare against
+    pub fn is_same_item(&self, other: &ItemAsset) -> bool {
+        self.collection_hash.is_some()
+            && self.collection_hash == other.collection_hash
+            && self.serial == other.serial
+    }
+
+    /// Parses `metadata` as structured receipt metadata, if any
+    pub fn structured_metadata(&self) -> Result<Option<ReceiptMetadata>, ReceiptMetadataError> {
+        self.metadata
+            .as_deref()
+            .map(ReceiptMetadata::decode)
+            .transpose()
+    }
+}
+
+/// Bundle of tokens and receipts held together by a single output
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+pub struct AssetBundle {
+    pub tokens: TokenAmount,
+    pub receipts: BTreeMap<String, u64>, /* `drs_tx_hash` - amount */
+}
+
+impl AssetBundle {
+    pub fn new(tokens: TokenAmount, receipts: BTreeMap<String, u64>) -> Self {
+        Self { tokens, receipts }
+    }
+
+    /// Returns whether the bundle holds no tokens or receipts
+    pub fn is_empty(&self) -> bool {
+        self.tokens == TokenAmount(0) && self.receipts.values().all(|amount| *amount == 0)
+    }
+
+    /// Gets the individual assets held by the bundle
+    pub fn components(&self) -> impl Iterator<Item = Asset> + '_ {
+        let tokens = (self.tokens != TokenAmount(0)).then_some(Asset::Token(self.tokens));
+        tokens.into_iter().chain(
+            self.receipts.iter().map(|(drs_tx_hash, amount)| {
+                Asset::receipt(*amount, Some(drs_tx_hash.clone()), None)
+            }),
+        )
+    }
+
+    /// Gets the amount the bundle holds of the same kind of asset as `asset`,
+    /// as an asset of that kind
+    ///
+    /// ### Arguments
+    ///
+    /// * `asset`   - Asset to get the matching component of
+    pub fn component_like(&self, asset: &Asset) -> Option<Asset> {
+        match asset {
+            Asset::Token(_) => Some(Asset::Token(self.tokens)),
+            Asset::Receipt(receipt) => {
+                let drs_tx_hash = receipt.drs_tx_hash.as_ref()?;
+                let amount = self.receipts.get(drs_tx_hash).copied().unwrap_or(0);
+                Some(Asset::receipt(amount, Some(drs_tx_hash.clone()), None))
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Data asset struct
 #[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct DataAsset {
@@ -101,16 +498,70 @@ pub struct DataAsset {
     pub amount: u64,
 }
 
+impl DataAsset {
+    /// Creates a data asset whose payload is sealed so only the recipient can read it
+    ///
+    /// ### Arguments
+    ///
+    /// * `plain_text`  - Payload to seal
+    /// * `recipient`   - Sealed box public key of the recipient
+    /// * `amount`      - Amount of the asset
+    pub fn encrypted(
+        plain_text: Vec<u8>,
+        recipient: &sealedbox::PublicKey,
+        amount: u64,
+    ) -> Option<Self> {
+        let mut data = ENCRYPTED_DATA_PREFIX.to_vec();
+        data.extend(sealedbox::seal(plain_text, recipient)?);
+        Some(Self { data, amount })
+    }
+
+    /// Returns whether the payload is sealed for a recipient
+    pub fn is_encrypted(&self) -> bool {
+        self.data.starts_with(ENCRYPTED_DATA_PREFIX)
+    }
+
+    /// Opens a sealed payload with the owner's secret key
+    ///
+    /// ### Arguments
+    ///
+    /// * `secret_key`  - Secret key of the recipient the payload was sealed for
+    pub fn decrypt(&self, secret_key: &SecretKey) -> Option<Vec<u8>> {
+        let sealed = self.data.strip_prefix(ENCRYPTED_DATA_PREFIX)?;
+        sealedbox::open(sealed.to_vec(), secret_key)
+    }
+
+    /// Seals the payload again for a new owner, e.g. when transferring the asset
+    ///
+    /// ### Arguments
+    ///
+    /// * `secret_key`  - Secret key of the current owner
+    /// * `recipient`   - Sealed box public key of the new owner
+    pub fn reencrypt(
+        &self,
+        secret_key: &SecretKey,
+        recipient: &sealedbox::PublicKey,
+    ) -> Option<Self> {
+        Self::encrypted(self.decrypt(secret_key)?, recipient, self.amount)
+    }
+}
+
 /// Asset struct
 ///
 /// * `Token`   - An asset struct representation of the ZNT token
 /// * `Data`    - A data asset
 /// * `Receipt` - A receipt for a payment. The value indicates the number of receipt assets
+/// * `Issued`  - A fungible token issued under the control of its issuer
+/// * `Item`    - An indivisible, uniquely numbered item of a collection
+/// * `Bundle`  - Tokens and receipts held together by a single output
 #[derive(Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
 pub enum Asset {
     Token(TokenAmount),
     Data(DataAsset),
     Receipt(ReceiptAsset),
+    Issued(IssuedAsset),
+    Item(ItemAsset),
+    Bundle(AssetBundle),
 }
 
 impl Default for Asset {
@@ -128,6 +579,18 @@ impl Asset {
                 receipt_asset.drs_tx_hash = Some(&out_point.t_hash).cloned();
             }
         }
+        if let Asset::Issued(ref mut issued_asset) = self {
+            if issued_asset.genesis_hash.is_none() {
+                issued_asset.genesis_hash = Some(out_point.t_hash.clone());
+                issued_asset.definition = None;
+            }
+        }
+        if let Asset::Item(ref mut item_asset) = self {
+            if item_asset.collection_hash.is_none() {
+                item_asset.collection_hash = Some(out_point.t_hash.clone());
+                item_asset.definition = None;
+            }
+        }
         self
     }
 
@@ -137,6 +600,25 @@ impl Asset {
             Asset::Token(_) => None,
             Asset::Data(_) => None, /* TODO: This will have to change */
             Asset::Receipt(receipt) => receipt.drs_tx_hash.as_ref(),
+            Asset::Issued(_) => None,
+            Asset::Item(_) => None,
+            Asset::Bundle(_) => None,
+        }
+    }
+
+    /// Get optional `genesis_hash` value for an `Issued` asset
+    pub fn get_genesis_hash(&self) -> Option<&String> {
+        match self {
+            Asset::Issued(issued) => issued.genesis_hash.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Get optional `collection_hash` value for an `Item` asset
+    pub fn get_collection_hash(&self) -> Option<&String> {
+        match self {
+            Asset::Item(item) => item.collection_hash.as_ref(),
+            _ => None,
         }
     }
 
@@ -145,6 +627,9 @@ impl Asset {
             Asset::Token(_) => None,
             Asset::Data(_) => None,
             Asset::Receipt(receipt) => receipt.metadata.as_ref(),
+            Asset::Issued(_) => None,
+            Asset::Item(item) => item.metadata.as_ref(),
+            Asset::Bundle(_) => None,
         }
     }
 
@@ -153,6 +638,11 @@ impl Asset {
             Asset::Token(_) => size_of::<TokenAmount>(),
             Asset::Data(d) => d.data.len(),
             Asset::Receipt(_) => size_of::<u64>(),
+            Asset::Issued(_) => size_of::<u64>(),
+            Asset::Item(_) => size_of::<u64>(),
+            Asset::Bundle(bundle) => {
+                size_of::<TokenAmount>() + bundle.receipts.len() * size_of::<u64>()
+            }
         }
     }
 
@@ -164,13 +654,48 @@ impl Asset {
         Asset::Receipt(ReceiptAsset::new(amount, drs_tx_hash, metadata))
     }
 
+    pub fn issued(
+        amount: u64,
+        genesis_hash: Option<String>,
+        definition: Option<IssuanceDefinition>,
+    ) -> Self {
+        Asset::Issued(IssuedAsset::new(amount, genesis_hash, definition))
+    }
+
+    pub fn bundle(tokens: TokenAmount, receipts: BTreeMap<String, u64>) -> Self {
+        Asset::Bundle(AssetBundle::new(tokens, receipts))
+    }
+
+    pub fn item(
+        collection_hash: Option<String>,
+        serial: u64,
+        metadata: Option<String>,
+        definition: Option<CollectionDefinition>,
+    ) -> Self {
+        Asset::Item(ItemAsset::new(
+            collection_hash,
+            serial,
+            metadata,
+            definition,
+        ))
+    }
+
+    pub fn receipt_with_metadata(
+        amount: u64,
+        drs_tx_hash: Option<String>,
+        metadata: &ReceiptMetadata,
+    ) -> Self {
+        Asset::receipt(amount, drs_tx_hash, Some(metadata.encode()))
+    }
+
     /// Add an asset of the same variant to `self` asset.
     /// TODO: Add handling for `Data` asset variant. Will return false when `Data` asset is presented.
     ///
     /// ### Note
     ///
     /// This function will return false for `Receipt` assets
-    /// getting added together that do not have the same `drs_tx_hash`
+    /// getting added together that do not have the same `drs_tx_hash`,
+    /// or if the sum would overflow, leaving `self` unchanged
     ///
     /// ### Arguments
     ///
@@ -178,14 +703,46 @@ impl Asset {
     pub fn add_assign(&mut self, rhs: &Self) -> bool {
         match (self, rhs) {
             (Asset::Token(lhs_tokens), Asset::Token(rhs_tokens)) => {
-                *lhs_tokens += *rhs_tokens;
+                match lhs_tokens.checked_add(*rhs_tokens) {
+                    Some(sum) => *lhs_tokens = sum,
+                    None => return false,
+                }
                 true
             }
             (Asset::Receipt(lhs_receipts), Asset::Receipt(rhs_receipts)) => {
                 if lhs_receipts.drs_tx_hash != rhs_receipts.drs_tx_hash {
                     return false;
                 }
-                lhs_receipts.amount += rhs_receipts.amount;
+                match lhs_receipts.amount.checked_add(rhs_receipts.amount) {
+                    Some(sum) => lhs_receipts.amount = sum,
+                    None => return false,
+                }
+                true
+            }
+            (Asset::Issued(lhs_issued), Asset::Issued(rhs_issued)) => {
+                if lhs_issued.genesis_hash != rhs_issued.genesis_hash {
+                    return false;
+                }
+                match lhs_issued.amount.checked_add(rhs_issued.amount) {
+                    Some(sum) => lhs_issued.amount = sum,
+                    None => return false,
+                }
+                true
+            }
+            (Asset::Bundle(lhs_bundle), Asset::Bundle(rhs_bundle)) => {
+                let mut sum = lhs_bundle.clone();
+                sum.tokens = match sum.tokens.checked_add(rhs_bundle.tokens) {
+                    Some(tokens) => tokens,
+                    None => return false,
+                };
+                for (drs_tx_hash, rhs_amount) in &rhs_bundle.receipts {
+                    let amount = sum.receipts.entry(drs_tx_hash.clone()).or_default();
+                    *amount = match amount.checked_add(*rhs_amount) {
+                        Some(amount) => amount,
+                        None => return false,
+                    };
+                }
+                *lhs_bundle = sum;
                 true
             }
             _ => false,
@@ -209,6 +766,15 @@ impl Asset {
                 }
                 Some(lhs_receipt.amount >= rhs_receipt.amount)
             }
+            (Asset::Issued(lhs_issued), Asset::Issued(rhs_issued)) => {
+                if lhs_issued.genesis_hash != rhs_issued.genesis_hash {
+                    return None;
+                }
+                Some(lhs_issued.amount >= rhs_issued.amount)
+            }
+            (Asset::Item(lhs_item), Asset::Item(rhs_item)) => {
+                lhs_item.is_same_item(rhs_item).then_some(true)
+            }
             _ => None,
         }
     }
@@ -224,7 +790,7 @@ impl Asset {
         match (&self, &rhs) {
             (Asset::Token(lhs_tokens), Asset::Token(rhs_tokens)) => {
                 if lhs_tokens > rhs_tokens {
-                    Some(Asset::Token(*lhs_tokens - *rhs_tokens))
+                    lhs_tokens.checked_sub(*rhs_tokens).map(Asset::Token)
                 } else {
                     None
                 }
@@ -242,6 +808,19 @@ impl Asset {
                     None
                 }
             }
+            (Asset::Issued(lhs_issued), Asset::Issued(rhs_issued)) => {
+                if lhs_issued.amount > rhs_issued.amount
+                    && lhs_issued.genesis_hash == rhs_issued.genesis_hash
+                {
+                    Some(Asset::issued(
+                        lhs_issued.amount - rhs_issued.amount,
+                        lhs_issued.genesis_hash.clone(),
+                        None,
+                    ))
+                } else {
+                    None
+                }
+            }
             _ => None,
         }
     }
@@ -269,6 +848,10 @@ impl Asset {
                 receipt.drs_tx_hash.clone(),
                 receipt.metadata.clone(),
             ),
+            Self::Issued(issued) => {
+                Self::issued(Default::default(), issued.genesis_hash.clone(), None)
+            }
+            Self::Bundle(_) => Self::Bundle(Default::default()),
             _ => panic!("Cannot create default of asset type: {:?}", asset_type),
         }
     }
@@ -277,10 +860,26 @@ impl Asset {
         matches!(self, Asset::Token(_))
     }
 
+    pub fn is_data(&self) -> bool {
+        matches!(self, Asset::Data(_))
+    }
+
     pub fn is_receipt(&self) -> bool {
         matches!(self, Asset::Receipt(_))
     }
 
+    pub fn is_issued(&self) -> bool {
+        matches!(self, Asset::Issued(_))
+    }
+
+    pub fn is_item(&self) -> bool {
+        matches!(self, Asset::Item(_))
+    }
+
+    pub fn is_bundle(&self) -> bool {
+        matches!(self, Asset::Bundle(_))
+    }
+
     pub fn is_empty(&self) -> bool {
         match self {
             Asset::Data(d) => d.data.is_empty(),
@@ -301,6 +900,87 @@ impl Asset {
             _ => 0,
         }
     }
+
+    pub fn issued_amount(&self) -> u64 {
+        match self {
+            Asset::Issued(v) => v.amount,
+            _ => 0,
+        }
+    }
+}
+
+/// Minimum amount, per asset type, an output must hold to be relayed.
+/// Outputs below it are dust: valid, but too small to be worth a UTXO entry.
+/// The default values are the standard thresholds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DustPolicy {
+    pub min_tokens: TokenAmount,
+    pub min_receipt: u64,
+    pub min_issued: u64,
+    pub min_data: u64,
+}
+
+impl Default for DustPolicy {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl DustPolicy {
+    /// Creates the thresholds enforced when relaying transactions
+    pub fn standard() -> Self {
+        Self {
+            min_tokens: TokenAmount(DUST_TOKEN_AMOUNT),
+            min_receipt: DUST_ASSET_AMOUNT,
+            min_issued: DUST_ASSET_AMOUNT,
+            min_data: DUST_ASSET_AMOUNT,
+        }
+    }
+
+    /// Checks whether an asset is below the threshold of its type.
+    /// Items are never dust, and a bundle is dust only if all of its components are
+    ///
+    /// ### Arguments
+    ///
+    /// * `asset`   - Asset to check
+    pub fn is_dust(&self, asset: &Asset) -> bool {
+        match asset {
+            Asset::Token(tokens) => *tokens < self.min_tokens,
+            Asset::Receipt(receipt) => receipt.amount < self.min_receipt,
+            Asset::Issued(issued) => issued.amount < self.min_issued,
+            Asset::Data(data) => data.amount < self.min_data,
+            Asset::Item(_) => false,
+            Asset::Bundle(bundle) => bundle.components().all(|c| self.is_dust(&c)),
+        }
+    }
+}
+
+/// Errors raised when updating a running total of assets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetValuesError {
+    TokenOverflow,
+    TokenUnderflow,
+    ReceiptOverflow(String),
+    ReceiptUnderflow(String),
+    IssuedOverflow(String),
+    IssuedUnderflow(String),
+    DuplicateItem(String, u64),
+    MissingItem(String, u64),
+}
+
+impl fmt::Display for AssetValuesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TokenOverflow => write!(f, "Token amount overflow"),
+            Self::TokenUnderflow => write!(f, "Token amount underflow"),
+            Self::ReceiptOverflow(hash) => write!(f, "Receipt amount overflow for {hash}"),
+            Self::ReceiptUnderflow(hash) => write!(f, "Receipt amount underflow for {hash}"),
+            Self::IssuedOverflow(hash) => write!(f, "Issued token amount overflow for {hash}"),
+            Self::IssuedUnderflow(hash) => write!(f, "Issued token amount underflow for {hash}"),
+            Self::DuplicateItem(hash, serial) => write!(f, "Duplicate item {serial} of {hash}"),
+            Self::MissingItem(hash, serial) => write!(f, "Missing item {serial} of {hash}"),
+        }
+    }
 }
 
 /// `AssetValue` struct used to represent the a running total of `Token` and `Receipt` assets
@@ -309,18 +989,22 @@ pub struct AssetValues {
     pub tokens: TokenAmount,
     // Note: Receipts from create transactions will have `drs_tx_hash` = `t_hash`
     pub receipts: BTreeMap<String, u64>, /* `drs_tx_hash` - amount */
-}
-
-impl ops::AddAssign for AssetValues {
-    fn add_assign(&mut self, rhs: Self) {
-        self.tokens += rhs.tokens;
-        add_btreemap(&mut self.receipts, rhs.receipts);
-    }
+    // Note: Issued tokens from genesis transactions will have `genesis_hash` = `t_hash`
+    #[serde(default)]
+    pub issued: BTreeMap<String, u64>, /* `genesis_hash` - amount */
+    // Note: Items from collection genesis transactions will have `collection_hash` = `t_hash`
+    #[serde(default)]
+    pub items: BTreeMap<String, BTreeSet<u64>>, /* `collection_hash` - serials */
 }
 
 impl AssetValues {
     pub fn new(tokens: TokenAmount, receipts: BTreeMap<String, u64>) -> Self {
-        Self { tokens, receipts }
+        Self {
+            tokens,
+            receipts,
+            issued: Default::default(),
+            items: Default::default(),
+        }
     }
 
     pub fn token_u64(tokens: u64) -> Self {
@@ -336,7 +1020,10 @@ impl AssetValues {
     }
 
     pub fn is_equal(&self, rhs: &AssetValues) -> bool {
-        self.tokens == rhs.tokens && self.receipts == rhs.receipts
+        self.tokens == rhs.tokens
+            && self.receipts == rhs.receipts
+            && self.issued == rhs.issued
+            && self.items == rhs.items
     }
 
     // See if the running total is enough for a required `Asset` amount
@@ -352,38 +1039,456 @@ impl AssetValues {
                     false
                 }
             }
+            Asset::Issued(issued) => match &issued.genesis_hash {
+                Some(genesis_hash) => matches!(
+                    self.issued.get(genesis_hash),
+                    Some(amount) if *amount >= issued.amount
+                ),
+                None => false,
+            },
+            Asset::Item(item) => match &item.collection_hash {
+                Some(collection_hash) => matches!(
+                    self.items.get(collection_hash),
+                    Some(serials) if serials.contains(&item.serial)
+                ),
+                None => false,
+            },
+            Asset::Bundle(bundle) => bundle.components().all(|c| self.has_enough(&c)),
             _ => false,
         }
     }
 
-    /// Add the `rhs` parameter to `self`
-    pub fn update_add(&mut self, rhs: &Asset) {
+    /// Add the `rhs` parameter to `self`, leaving `self` unchanged on overflow
+    pub fn update_add(&mut self, rhs: &Asset) -> Result<(), AssetValuesError> {
         match rhs {
-            Asset::Token(tokens) => self.tokens += *tokens,
+            Asset::Token(tokens) => {
+                self.tokens = self
+                    .tokens
+                    .checked_add(*tokens)
+                    .ok_or(AssetValuesError::TokenOverflow)?;
+            }
             Asset::Receipt(receipts) => {
                 if let Some(drs_tx_hash) = &receipts.drs_tx_hash {
-                    self.receipts
-                        .entry(drs_tx_hash.clone())
-                        .and_modify(|amount| *amount += receipts.amount)
-                        .or_insert(receipts.amount);
+                    let amount = self.receipts.get(drs_tx_hash).copied().unwrap_or(0);
+                    let sum = amount
+                        .checked_add(receipts.amount)
+                        .ok_or_else(|| AssetValuesError::ReceiptOverflow(drs_tx_hash.clone()))?;
+                    self.receipts.insert(drs_tx_hash.clone(), sum);
+                }
+            }
+            Asset::Issued(issued) => {
+                if let Some(genesis_hash) = &issued.genesis_hash {
+                    let amount = self.issued.get(genesis_hash).copied().unwrap_or(0);
+                    let sum = amount
+                        .checked_add(issued.amount)
+                        .ok_or_else(|| AssetValuesError::IssuedOverflow(genesis_hash.clone()))?;
+                    self.issued.insert(genesis_hash.clone(), sum);
+                }
+            }
+            Asset::Item(item) => {
+                if let Some(collection_hash) = &item.collection_hash {
+                    let serials = self.items.entry(collection_hash.clone()).or_default();
+                    if !serials.insert(item.serial) {
+                        return Err(AssetValuesError::DuplicateItem(
+                            collection_hash.clone(),
+                            item.serial,
+                        ));
+                    }
+                }
+            }
+            Asset::Bundle(bundle) => {
+                let mut updated = self.clone();
+                for component in bundle.components() {
+                    updated.update_add(&component)?;
                 }
+                *self = updated;
             }
             _ => {}
         }
+        Ok(())
     }
 
-    // Subtract the `rhs` parameter from `self`
-    pub fn update_sub(&mut self, rhs: &Asset) {
+    /// Subtract the `rhs` parameter from `self`, leaving `self` unchanged on underflow.
+    /// Receipts and issued tokens not held by `self` count as an amount of zero,
+    /// while items not held by `self` are an error
+    pub fn update_sub(&mut self, rhs: &Asset) -> Result<(), AssetValuesError> {
         match rhs {
-            Asset::Token(tokens) => self.tokens -= *tokens,
+            Asset::Token(tokens) => {
+                self.tokens = self
+                    .tokens
+                    .checked_sub(*tokens)
+                    .ok_or(AssetValuesError::TokenUnderflow)?;
+            }
             Asset::Receipt(receipts) => {
-                receipts.drs_tx_hash.as_ref().and_then(|drs_tx_hash| {
-                    self.receipts
-                        .get_mut(drs_tx_hash)
-                        .map(|amount| *amount -= receipts.amount)
-                });
+                if let Some(drs_tx_hash) = &receipts.drs_tx_hash {
+                    let amount = self.receipts.get(drs_tx_hash).copied().unwrap_or(0);
+                    let diff = amount
+                        .checked_sub(receipts.amount)
+                        .ok_or_else(|| AssetValuesError::ReceiptUnderflow(drs_tx_hash.clone()))?;
+                    if let Some(amount) = self.receipts.get_mut(drs_tx_hash) {
+                        *amount = diff;
+                    }
+                }
+            }
+            Asset::Issued(issued) => {
+                if let Some(genesis_hash) = &issued.genesis_hash {
+                    let amount = self.issued.get(genesis_hash).copied().unwrap_or(0);
+                    let diff = amount
+                        .checked_sub(issued.amount)
+                        .ok_or_else(|| AssetValuesError::IssuedUnderflow(genesis_hash.clone()))?;
+                    if let Some(amount) = self.issued.get_mut(genesis_hash) {
+                        *amount = diff;
+                    }
+                }
+            }
+            Asset::Item(item) => {
+                if let Some(collection_hash) = &item.collection_hash {
+                    let missing =
+                        || AssetValuesError::MissingItem(collection_hash.clone(), item.serial);
+                    let serials = self.items.get_mut(collection_hash).ok_or_else(missing)?;
+                    if !serials.remove(&item.serial) {
+                        return Err(missing());
+                    }
+                    if serials.is_empty() {
+                        self.items.remove(collection_hash);
+                    }
+                }
+            }
+            Asset::Bundle(bundle) => {
+                let mut updated = self.clone();
+                for component in bundle.components() {
+                    updated.update_sub(&component)?;
+                }
+                *self = updated;
             }
             _ => {}
         }
+        Ok(())
+    }
+
+    /// Converts the running total into the assets holding it, skipping zero amounts
+    pub fn to_assets(&self) -> Vec<Asset> {
+        let tokens = (self.tokens != TokenAmount(0)).then_some(Asset::Token(self.tokens));
+        let receipts = self
+            .receipts
+            .iter()
+            .filter(|(_, amount)| **amount != 0)
+            .map(|(hash, amount)| Asset::receipt(*amount, Some(hash.clone()), None));
+        let issued = self
+            .issued
+            .iter()
+            .filter(|(_, amount)| **amount != 0)
+            .map(|(hash, amount)| Asset::issued(*amount, Some(hash.clone()), None));
+        let items = self.items.iter().flat_map(|(hash, serials)| {
+            serials
+                .iter()
+                .map(move |serial| Asset::item(Some(hash.clone()), *serial, None, None))
+        });
+
+        tokens
+            .into_iter()
+            .chain(receipts)
+            .chain(issued)
+            .chain(items)
+            .collect()
+    }
+
+    /// Compares `self` as the inputs of a transaction against its `outputs`,
+    /// reporting the input and output totals of every asset in either
+    ///
+    /// ### Arguments
+    ///
+    /// * `outputs` - Running total of the transaction outputs
+    pub fn balance_diff(&self, outputs: &AssetValues) -> BalanceDiff {
+        let mut diff = BalanceDiff::default();
+        let mut add = |key: AssetKey, input: u64, output: u64| {
+            let balance = diff.0.entry(key).or_default();
+            balance.input += input;
+            balance.output += output;
+        };
+
+        add(AssetKey::Token, self.tokens.0, 0);
+        add(AssetKey::Token, 0, outputs.tokens.0);
+        for (hash, amount) in &self.receipts {
+            add(AssetKey::Receipt(hash.clone()), *amount, 0);
+        }
+        for (hash, amount) in &outputs.receipts {
+            add(AssetKey::Receipt(hash.clone()), 0, *amount);
+        }
+        for (hash, amount) in &self.issued {
+            add(AssetKey::Issued(hash.clone()), *amount, 0);
+        }
+        for (hash, amount) in &outputs.issued {
+            add(AssetKey::Issued(hash.clone()), 0, *amount);
+        }
+        for (hash, serials) in &self.items {
+            for serial in serials {
+                add(AssetKey::Item(hash.clone(), *serial), 1, 0);
+            }
+        }
+        for (hash, serials) in &outputs.items {
+            for serial in serials {
+                add(AssetKey::Item(hash.clone(), *serial), 0, 1);
+            }
+        }
+        diff
+    }
+}
+
+/// Identifies an asset whose amounts are balanced between inputs and outputs
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AssetKey {
+    Token,
+    Receipt(String),
+    Issued(String),
+    Item(String, u64),
+}
+
+impl fmt::Display for AssetKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Token => write!(f, "Token"),
+            Self::Receipt(hash) => write!(f, "Receipt {hash}"),
+            Self::Issued(hash) => write!(f, "Issued {hash}"),
+            Self::Item(hash, serial) => write!(f, "Item {serial} of {hash}"),
+        }
+    }
+}
+
+/// Input and output totals of a single asset
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetBalance {
+    pub input: u64,
+    pub output: u64,
+}
+
+impl AssetBalance {
+    pub fn is_balanced(&self) -> bool {
+        self.input == self.output
+    }
+
+    /// Amount of the inputs not spent by the outputs
+    pub fn surplus(&self) -> u64 {
+        self.input.saturating_sub(self.output)
+    }
+
+    /// Amount of the outputs not covered by the inputs
+    pub fn deficit(&self) -> u64 {
+        self.output.saturating_sub(self.input)
+    }
+}
+
+/// Per-asset comparison of the inputs and outputs of a transaction
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceDiff(pub BTreeMap<AssetKey, AssetBalance>);
+
+impl BalanceDiff {
+    pub fn is_balanced(&self) -> bool {
+        self.0.values().all(AssetBalance::is_balanced)
+    }
+
+    /// Gets the balance of a single asset, which is zero for assets not involved
+    pub fn get(&self, key: &AssetKey) -> AssetBalance {
+        self.0.get(key).copied().unwrap_or_default()
+    }
+
+    /// Iterates over the assets whose inputs and outputs differ
+    pub fn unbalanced(&self) -> impl Iterator<Item = (&AssetKey, &AssetBalance)> {
+        self.0.iter().filter(|(_, balance)| !balance.is_balanced())
+    }
+}
+
+impl fmt::Display for BalanceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut unbalanced = self.unbalanced().peekable();
+        if unbalanced.peek().is_none() {
+            return write!(f, "Balanced");
+        }
+        for (index, (key, balance)) in unbalanced.enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{key}: in {} out {}", balance.input, balance.output)?;
+            if balance.surplus() > 0 {
+                write!(f, " (surplus {})", balance.surplus())?;
+            } else {
+                write!(f, " (deficit {})", balance.deficit())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Checks that receipt metadata has a single canonical encoding
+    fn test_receipt_metadata_canonical_encoding() {
+        let mut metadata = ReceiptMetadata::new("Ticket".to_owned(), "Row A".to_owned());
+        metadata.media_hash = Some("ab".repeat(32));
+        metadata
+            .attributes
+            .insert("seat".to_owned(), "12".to_owned());
+        metadata
+            .attributes
+            .insert("gate".to_owned(), "3".to_owned());
+
+        let encoded = metadata.encode();
+        assert_eq!(
+            encoded,
+            format!(
+                "{{\"schema_version\":1,\"name\":\"Ticket\",\"description\":\"Row A\",\
+                 \"media_hash\":\"{}\",\"attributes\":{{\"gate\":\"3\",\"seat\":\"12\"}}}}",
+                "ab".repeat(32)
+            )
+        );
+        assert_eq!(ReceiptMetadata::decode(&encoded), Ok(metadata.clone()));
+
+        let receipt = ReceiptAsset::new(1, None, Some(encoded.clone()));
+        assert_eq!(receipt.structured_metadata(), Ok(Some(metadata)));
+        assert_eq!(
+            ReceiptAsset::new(1, None, None).structured_metadata(),
+            Ok(None)
+        );
+
+        let spaced = encoded.replacen(':', ": ", 1);
+        assert_eq!(
+            ReceiptMetadata::decode(&spaced),
+            Err(ReceiptMetadataError::NonCanonical)
+        );
+        assert_eq!(
+            ReceiptMetadata::decode("{\"name\":\"Ticket\"}"),
+            Err(ReceiptMetadataError::Malformed)
+        );
+    }
+
+    #[test]
+    /// Checks validation of receipt metadata fields
+    fn test_receipt_metadata_validation() {
+        let valid = ReceiptMetadata::new("Ticket".to_owned(), String::new());
+        assert_eq!(valid.validate(), Ok(()));
+
+        let check = |f: &dyn Fn(&mut ReceiptMetadata)| {
+            let mut metadata = valid.clone();
+            f(&mut metadata);
+            ReceiptMetadata::decode(&metadata.encode()).unwrap_err()
+        };
+        assert_eq!(
+            check(&|m| m.schema_version = 2),
+            ReceiptMetadataError::UnsupportedSchemaVersion(2)
+        );
+        assert_eq!(check(&|m| m.name.clear()), ReceiptMetadataError::EmptyName);
+        assert_eq!(
+            check(&|m| m.media_hash = Some("AB".repeat(32))),
+            ReceiptMetadataError::InvalidMediaHash
+        );
+        assert_eq!(
+            check(&|m| {
+                m.attributes.insert(String::new(), "x".to_owned());
+            }),
+            ReceiptMetadataError::EmptyAttributeKey
+        );
+        assert_eq!(
+            check(&|m| m.description = "x".repeat(MAX_METADATA_BYTES)),
+            ReceiptMetadataError::TooLarge
+        );
+    }
+
+    #[test]
+    /// Checks that amounts are displayed with integer arithmetic
+    fn test_token_amount_display() {
+        let display = |units: u64| TokenAmount(units).to_string();
+        assert_eq!(display(0), "0");
+        assert_eq!(display(25200), "1");
+        assert_eq!(display(315000), "12.5");
+        assert_eq!(display(1), "0.00004");
+        assert_eq!(display(25199), "0.99996");
+        assert_eq!(display(TOTAL_TOKENS), "10000000000");
+        assert_eq!(display(TOTAL_TOKENS - 1), "9999999999.99996");
+        assert_eq!(display(u64::MAX), "732013653718633.0006");
+    }
+
+    #[test]
+    /// Checks that amount arithmetic reports overflow rather than panicking or wrapping
+    fn test_token_amount_checked_arithmetic() {
+        let max = TokenAmount(u64::MAX);
+        assert_eq!(max.checked_add(TokenAmount(1)), None);
+        assert_eq!(TokenAmount(0).checked_sub(TokenAmount(1)), None);
+        assert_eq!(max.checked_mul(2), None);
+        assert_eq!(
+            TokenAmount(2).checked_add(TokenAmount(3)),
+            Some(TokenAmount(5))
+        );
+        assert_eq!(
+            Asset::Token(TokenAmount(5)).get_excess(&Asset::Token(TokenAmount(3))),
+            Some(Asset::Token(TokenAmount(2)))
+        );
+        let mut values = AssetValues::token_u64(u64::MAX);
+        assert_eq!(
+            values.update_add(&Asset::token_u64(1)),
+            Err(AssetValuesError::TokenOverflow)
+        );
+        assert_eq!(values, AssetValues::token_u64(u64::MAX));
+    }
+
+    #[test]
+    /// Checks parsing of decimal strings and that displayed amounts parse back exactly
+    fn test_token_amount_from_str() {
+        let parse = |s: &str| s.parse::<TokenAmount>();
+        assert_eq!(parse("12.5"), Ok(TokenAmount(315000)));
+        assert_eq!(parse("12"), Ok(TokenAmount(302400)));
+        assert_eq!(parse("0.00004"), Ok(TokenAmount(1)));
+        assert_eq!(parse("10000000000"), Ok(TokenAmount(TOTAL_TOKENS)));
+        assert_eq!(parse("012.50"), Ok(TokenAmount(315000)));
+
+        let err = |s: &str| parse(s).unwrap_err();
+        assert_eq!(
+            err("0.000001"),
+            TokenAmountParseError::TooManyFractionalDigits
+        );
+        assert_eq!(
+            err("10000000000.00004"),
+            TokenAmountParseError::ExceedsTotalTokens
+        );
+        assert_eq!(
+            err("99999999999999999999"),
+            TokenAmountParseError::ExceedsTotalTokens
+        );
+        for inexact in &["0.00001", "0.00002", "1.00003", "0.99999"] {
+            assert_eq!(err(inexact), TokenAmountParseError::Inexact);
+        }
+        for invalid in &["", ".5", "12.", "-1", "1.2.3", "1e5", " 1", "1,5"] {
+            assert_eq!(err(invalid), TokenAmountParseError::InvalidFormat);
+        }
+
+        let samples = (0..2 * D_DISPLAY_PLACES_U64).chain(TOTAL_TOKENS - 50000..=TOTAL_TOKENS);
+        for units in samples {
+            assert_eq!(
+                parse(&TokenAmount(units).to_string()),
+                Ok(TokenAmount(units))
+            );
+        }
+    }
+
+    #[test]
+    /// Checks the decimal string serde helpers
+    fn test_token_amount_decimal_serde() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Payment {
+            #[serde(with = "token_amount_decimal")]
+            amount: TokenAmount,
+        }
+
+        let payment = Payment {
+            amount: TokenAmount(315000),
+        };
+        let bytes = bincode::serialize(&payment).unwrap();
+        assert_eq!(bytes, bincode::serialize("12.5").unwrap());
+        assert_eq!(bincode::deserialize::<Payment>(&bytes).unwrap(), payment);
+
+        let bytes = bincode::serialize("0.000001").unwrap();
+        assert!(bincode::deserialize::<Payment>(&bytes).is_err());
     }
 }
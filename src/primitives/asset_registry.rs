@@ -0,0 +1,435 @@
+use crate::crypto::sign_ed25519::PublicKey;
+use crate::primitives::asset::{Asset, ReceiptSupply};
+use crate::primitives::block::Block;
+use crate::primitives::transaction::Transaction;
+use crate::script::StackEntry;
+use crate::utils::transaction_utils::{construct_address, update_asset_supply};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Kind of asset recorded in the registry
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RegisteredAssetKind {
+    Receipt,
+    Data,
+    Issued,
+    Collection,
+}
+
+/// Genesis record of a single asset. The initial amount is the amount created by
+/// the genesis transaction, a single item for collections
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct AssetRecord {
+    pub kind: RegisteredAssetKind,
+    pub genesis_tx: String,
+    pub block_num: u64,
+    pub creator: String,
+    pub initial_amount: u64,
+    pub metadata: Option<String>,
+}
+
+/// Errors raised when applying a block to the registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetRegistryError {
+    MissingTransaction(String),
+    DuplicateGenesis(String),
+}
+
+impl fmt::Display for AssetRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingTransaction(hash) => write!(f, "Missing transaction {hash}"),
+            Self::DuplicateGenesis(hash) => write!(f, "Asset {hash} is already registered"),
+        }
+    }
+}
+
+/// Registry of asset genesis transactions and their supply, fed by validated blocks in order
+///
+/// Assets are keyed by their genesis transaction hash, which is the `drs_tx_hash`
+/// of receipts, the `genesis_hash` of issued tokens and the `collection_hash` of items.
+/// Receipts created against a shared `drs_tx_hash` have no genesis of their own and
+/// are not recorded. Supply is accounted with `update_asset_supply`
+///
+/// Blocks cannot be reverted: on a chain reorganization the registry must be
+/// rebuilt by applying the blocks of the new chain to an empty registry
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct AssetRegistry {
+    assets: BTreeMap<String, AssetRecord>,
+    by_creator: BTreeMap<String, BTreeSet<String>>,
+    supply: BTreeMap<String, ReceiptSupply>,
+}
+
+impl AssetRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records the asset genesis transactions of a validated block and the supply
+    /// created and burned by its transactions. The registry is left unchanged on error
+    ///
+    /// ### Arguments
+    ///
+    /// * `block`   - Validated block to apply
+    /// * `txs`     - Transactions of the block, keyed by transaction hash
+    pub fn apply_block(
+        &mut self,
+        block: &Block,
+        txs: &BTreeMap<String, Transaction>,
+    ) -> Result<(), AssetRegistryError> {
+        let block_txs = block
+            .transactions
+            .iter()
+            .map(|hash| match txs.get(hash) {
+                Some(tx) => Ok((hash, tx)),
+                None => Err(AssetRegistryError::MissingTransaction(hash.clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut genesis_records = Vec::new();
+        for (hash, tx) in &block_txs {
+            if let Some(record) = genesis_record(hash, tx, block.header.b_num) {
+                let is_duplicate = self.assets.contains_key(*hash)
+                    || genesis_records.iter().any(|(h, _)| h == hash);
+                if is_duplicate {
+                    return Err(AssetRegistryError::DuplicateGenesis((*hash).clone()));
+                }
+                genesis_records.push((*hash, record));
+            }
+        }
+
+        for (hash, record) in genesis_records {
+            self.by_creator
+                .entry(record.creator.clone())
+                .or_default()
+                .insert(hash.clone());
+            self.assets.insert(hash.clone(), record);
+        }
+
+        for (hash, tx) in block_txs {
+            update_asset_supply(&mut self.supply, hash, tx);
+        }
+
+        Ok(())
+    }
+
+    /// Gets the record of an asset by its genesis transaction hash
+    ///
+    /// ### Arguments
+    ///
+    /// * `hash`    - Genesis transaction hash, or `drs_tx_hash` for receipts
+    pub fn get(&self, hash: &str) -> Option<&AssetRecord> {
+        self.assets.get(hash)
+    }
+
+    /// Gets the supply of an asset by its genesis transaction hash.
+    /// Receipts sharing a `drs_tx_hash` are accounted even though they are not recorded
+    ///
+    /// ### Arguments
+    ///
+    /// * `hash`    - Genesis transaction hash, or `drs_tx_hash` for receipts
+    pub fn supply(&self, hash: &str) -> Option<&ReceiptSupply> {
+        self.supply.get(hash)
+    }
+
+    /// Gets the records of all assets created by an address
+    ///
+    /// ### Arguments
+    ///
+    /// * `creator` - Address of the creator
+    pub fn get_by_creator<'a>(
+        &'a self,
+        creator: &str,
+    ) -> impl Iterator<Item = (&'a String, &'a AssetRecord)> + 'a {
+        self.by_creator
+            .get(creator)
+            .into_iter()
+            .flatten()
+            .filter_map(move |hash| self.assets.get_key_value(hash))
+    }
+
+    /// Iterates over all registered assets
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &AssetRecord)> {
+        self.assets.iter()
+    }
+}
+
+/// Builds the registry record for a transaction creating a new asset
+///
+/// ### Arguments
+///
+/// * `hash`        - Hash of the transaction
+/// * `tx`          - Transaction to build the record for
+/// * `block_num`   - Number of the block containing the transaction
+fn genesis_record(hash: &str, tx: &Transaction, block_num: u64) -> Option<AssetRecord> {
+    if !tx.is_create_tx() {
+        return None;
+    }
+
+    let (kind, initial_amount, metadata) = match tx.get_create_asset()? {
+        Asset::Receipt(receipt) if receipt.drs_tx_hash.is_none() => (
+            RegisteredAssetKind::Receipt,
+            receipt.amount,
+            receipt.metadata.clone(),
+        ),
+        Asset::Data(data) => (RegisteredAssetKind::Data, data.amount, None),
+        Asset::Issued(issued) if issued.genesis_hash.is_none() => {
+            (RegisteredAssetKind::Issued, issued.amount, None)
+        }
+        Asset::Item(item) if item.collection_hash.is_none() => {
+            (RegisteredAssetKind::Collection, 1, item.metadata.clone())
+        }
+        _ => return None,
+    };
+
+    Some(AssetRecord {
+        kind,
+        genesis_tx: hash.to_owned(),
+        block_num,
+        creator: construct_address(create_tx_signer(tx)?),
+        initial_amount,
+        metadata,
+    })
+}
+
+/// Gets the public key that signed a create transaction
+///
+/// ### Arguments
+///
+/// * `tx`  - Create transaction
+fn create_tx_signer(tx: &Transaction) -> Option<&PublicKey> {
+    tx.inputs[0]
+        .script_signature
+        .stack
+        .iter()
+        .find_map(|entry| match entry {
+            StackEntry::PubKey(pk) => Some(pk),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::primitives::asset::{AssetBundle, ReceiptMetadata, SupplyRule, TokenAmount};
+    use crate::primitives::transaction::{DrsTxHashSpec, OutPoint, TxConstructor, TxOut};
+    use crate::utils::transaction_utils::{
+        construct_burn_address, construct_collection_create_tx, construct_create_tx,
+        construct_issued_token_create_tx, construct_item_mint_tx, construct_payment_tx_ins,
+        construct_receipt_burn_tx, construct_receipt_create_tx, construct_reissuance_tx,
+        construct_tx_core, construct_tx_hash, construct_tx_in_signable_hash,
+    };
+
+    fn block_of(b_num: u64, txs: &[&Transaction]) -> (Block, BTreeMap<String, Transaction>) {
+        let mut block = Block::new();
+        block.header.b_num = b_num;
+        let txs: BTreeMap<String, Transaction> = txs
+            .iter()
+            .map(|tx| (construct_tx_hash(tx), (*tx).clone()))
+            .collect();
+        block.transactions = txs.keys().cloned().collect();
+        (block, txs)
+    }
+
+    #[test]
+    /// Checks that genesis transactions and burns from blocks are recorded
+    fn test_asset_registry_apply_block() {
+        //
+        // Arrange
+        //
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, other_sk) = sign::gen_keypair();
+        let metadata = ReceiptMetadata::new("Voucher".to_owned(), String::new()).encode();
+        let receipt = construct_receipt_create_tx(
+            0,
+            pk,
+            &sk,
+            10,
+            DrsTxHashSpec::Create,
+            Some(metadata.clone()),
+        );
+        let receipt_hash = construct_tx_hash(&receipt);
+        let data = construct_create_tx(0, b"data".to_vec(), other_pk, &other_sk, 1);
+        let data_hash = construct_tx_hash(&data);
+        let shared = construct_receipt_create_tx(0, pk, &sk, 5, DrsTxHashSpec::Default, None);
+
+        let previous_out = OutPoint::new(receipt_hash.clone(), 0);
+        let signable_hash = construct_tx_in_signable_hash(&previous_out);
+        let tx_ins = construct_payment_tx_ins(vec![TxConstructor {
+            previous_out,
+            signatures: vec![sign::sign_detached(signable_hash.as_bytes(), &sk)],
+            pub_keys: vec![pk],
+            address_version: None,
+        }]);
+        let change = Asset::receipt(6, Some(receipt_hash.clone()), None);
+        let change_tx_outs = vec![TxOut::new_asset(construct_address(&pk), change)];
+        let burn = construct_receipt_burn_tx(tx_ins, receipt_hash.clone(), 4, change_tx_outs);
+
+        let mut registry = AssetRegistry::new();
+
+        //
+        // Act
+        //
+        let (block_1, txs_1) = block_of(1, &[&receipt, &data, &shared]);
+        let (block_2, txs_2) = block_of(2, &[&burn]);
+        let first = registry.apply_block(&block_1, &txs_1);
+        let second = registry.apply_block(&block_2, &txs_2);
+        let replayed = registry.apply_block(&block_1, &txs_1);
+        let missing = registry.apply_block(&block_2, &BTreeMap::new());
+
+        //
+        // Assert
+        //
+        assert_eq!(first, Ok(()));
+        assert_eq!(second, Ok(()));
+        assert!(matches!(
+            replayed,
+            Err(AssetRegistryError::DuplicateGenesis(_))
+        ));
+        assert_eq!(
+            missing,
+            Err(AssetRegistryError::MissingTransaction(construct_tx_hash(
+                &burn
+            )))
+        );
+
+        let record = registry.get(&receipt_hash).unwrap();
+        assert_eq!(record.kind, RegisteredAssetKind::Receipt);
+        assert_eq!(record.block_num, 1);
+        assert_eq!(record.creator, construct_address(&pk));
+        assert_eq!(record.initial_amount, 10);
+        assert_eq!(record.metadata, Some(metadata));
+        let supply = registry.supply(&receipt_hash).unwrap();
+        assert_eq!((supply.created, supply.burned), (10, 4));
+        assert_eq!(supply.outstanding(), 6);
+        assert_eq!(registry.iter().count(), 2);
+        assert_eq!(
+            registry
+                .supply(&DrsTxHashSpec::Default.get_drs_tx_hash().unwrap())
+                .map(|supply| supply.created),
+            Some(5)
+        );
+        assert_eq!(registry.supply(&data_hash).unwrap().created, 1);
+
+        let by_creator: Vec<&String> = registry
+            .get_by_creator(&construct_address(&other_pk))
+            .map(|(hash, _)| hash)
+            .collect();
+        assert_eq!(by_creator, vec![&data_hash]);
+        let record = registry.get(&data_hash).unwrap();
+        assert_eq!(record.kind, RegisteredAssetKind::Data);
+        assert_eq!(record.initial_amount, 1);
+    }
+
+    #[test]
+    /// Checks that receipts burned within a bundle are accounted as burned
+    fn test_asset_registry_bundle_burn() {
+        //
+        // Arrange
+        //
+        let (pk, sk) = sign::gen_keypair();
+        let receipt = construct_receipt_create_tx(0, pk, &sk, 10, DrsTxHashSpec::Create, None);
+        let receipt_hash = construct_tx_hash(&receipt);
+
+        let previous_out = OutPoint::new(receipt_hash.clone(), 0);
+        let signable_hash = construct_tx_in_signable_hash(&previous_out);
+        let tx_ins = construct_payment_tx_ins(vec![TxConstructor {
+            previous_out,
+            signatures: vec![sign::sign_detached(signable_hash.as_bytes(), &sk)],
+            pub_keys: vec![pk],
+            address_version: None,
+        }]);
+        let bundle = AssetBundle::new(TokenAmount(0), BTreeMap::from([(receipt_hash.clone(), 4)]));
+        let tx_out = TxOut::new_bundle(construct_burn_address(), bundle);
+        let burn = construct_tx_core(tx_ins, vec![tx_out]);
+
+        let mut registry = AssetRegistry::new();
+
+        //
+        // Act
+        //
+        let (block_1, txs_1) = block_of(1, &[&receipt]);
+        let (block_2, txs_2) = block_of(2, &[&burn]);
+        let first = registry.apply_block(&block_1, &txs_1);
+        let second = registry.apply_block(&block_2, &txs_2);
+
+        //
+        // Assert
+        //
+        assert_eq!((first, second), (Ok(()), Ok(())));
+        let supply = registry.supply(&receipt_hash).unwrap();
+        assert_eq!((supply.created, supply.burned), (10, 4));
+        assert_eq!(supply.outstanding(), 6);
+    }
+
+    #[test]
+    /// Checks that issued tokens and item collections are recorded with their supply
+    fn test_asset_registry_issued_and_items() {
+        //
+        // Arrange
+        //
+        let (pk, sk) = sign::gen_keypair();
+        let issued = construct_issued_token_create_tx(0, pk, &sk, 100, 2, SupplyRule::Max(500));
+        let issued_hash = construct_tx_hash(&issued);
+        let reissued = construct_reissuance_tx(0, issued_hash.clone(), pk, &sk, 50);
+        let collection = construct_collection_create_tx(0, pk, &sk, Some(10), None);
+        let collection_hash = construct_tx_hash(&collection);
+        let minted = construct_item_mint_tx(0, collection_hash.clone(), 1, pk, &sk, None);
+
+        let burn_of = |previous_out: OutPoint, value: Asset| {
+            let signable_hash = construct_tx_in_signable_hash(&previous_out);
+            let tx_ins = construct_payment_tx_ins(vec![TxConstructor {
+                previous_out,
+                signatures: vec![sign::sign_detached(signable_hash.as_bytes(), &sk)],
+                pub_keys: vec![pk],
+                address_version: None,
+            }]);
+            let tx_out = TxOut::new_asset(construct_burn_address(), value);
+            construct_tx_core(tx_ins, vec![tx_out])
+        };
+        let issued_burn = burn_of(
+            OutPoint::new(issued_hash.clone(), 0),
+            Asset::issued(30, Some(issued_hash.clone()), None),
+        );
+        let item_burn = burn_of(
+            OutPoint::new(construct_tx_hash(&minted), 0),
+            Asset::item(Some(collection_hash.clone()), 1, None, None),
+        );
+
+        let mut registry = AssetRegistry::new();
+
+        //
+        // Act
+        //
+        let (block_1, txs_1) = block_of(1, &[&issued, &collection]);
+        let (block_2, txs_2) = block_of(2, &[&reissued, &minted]);
+        let (block_3, txs_3) = block_of(3, &[&issued_burn, &item_burn]);
+        let applied: Vec<_> = [(block_1, txs_1), (block_2, txs_2), (block_3, txs_3)]
+            .iter()
+            .map(|(block, txs)| registry.apply_block(block, txs))
+            .collect();
+
+        //
+        // Assert
+        //
+        assert_eq!(applied, vec![Ok(()), Ok(()), Ok(())]);
+        assert_eq!(registry.iter().count(), 2);
+        let record = registry.get(&issued_hash).unwrap();
+        assert_eq!(record.kind, RegisteredAssetKind::Issued);
+        assert_eq!(record.initial_amount, 100);
+        let record = registry.get(&collection_hash).unwrap();
+        assert_eq!(record.kind, RegisteredAssetKind::Collection);
+        assert_eq!(record.initial_amount, 1);
+
+        let issued_supply = registry.supply(&issued_hash).unwrap();
+        assert_eq!((issued_supply.created, issued_supply.burned), (150, 30));
+        let collection_supply = registry.supply(&collection_hash).unwrap();
+        assert_eq!(
+            (collection_supply.created, collection_supply.burned),
+            (2, 1)
+        );
+        assert_eq!(collection_supply.outstanding(), 1);
+    }
+}
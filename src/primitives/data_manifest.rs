@@ -0,0 +1,275 @@
+use crate::constants::DATA_MANIFEST_PREFIX;
+use crate::crypto::sha3_256;
+use crate::primitives::asset::DataAsset;
+use bincode::{deserialize, serialize};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Errors raised when decoding a manifest or assembling its payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataManifestError {
+    Malformed,
+    InvalidRootHash,
+    MissingChunk(usize),
+    InvalidChunk(usize),
+    InvalidLength,
+}
+
+impl fmt::Display for DataManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "Data manifest is malformed"),
+            Self::InvalidRootHash => write!(f, "Data manifest root hash does not match"),
+            Self::MissingChunk(index) => write!(f, "Data chunk {index} is missing"),
+            Self::InvalidChunk(index) => write!(f, "Data chunk {index} does not match its hash"),
+            Self::InvalidLength => write!(f, "Assembled data length does not match"),
+        }
+    }
+}
+
+/// Manifest tying together the chunks of a payload too large for a single `DataAsset`.
+/// Each chunk is identified by its SHA3 hash, and the root hash commits to the
+/// payload length and the ordered chunk hashes
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct DataManifest {
+    pub total_len: u64,
+    pub chunk_hashes: Vec<String>,
+    pub root_hash: String,
+}
+
+impl DataManifest {
+    /// Builds the manifest of ordered chunks
+    ///
+    /// ### Arguments
+    ///
+    /// * `chunks`  - Chunks of the payload, in order
+    pub fn from_chunks(chunks: &[Vec<u8>]) -> Self {
+        let total_len = chunks.iter().map(|c| c.len() as u64).sum();
+        let chunk_hashes: Vec<String> = chunks.iter().map(|c| hash_chunk(c)).collect();
+        let root_hash = construct_root_hash(total_len, &chunk_hashes);
+
+        Self {
+            total_len,
+            chunk_hashes,
+            root_hash,
+        }
+    }
+
+    /// Splits a payload into chunks of at most `chunk_size` bytes and builds their manifest
+    ///
+    /// ### Arguments
+    ///
+    /// * `data`        - Payload to split
+    /// * `chunk_size`  - Maximum number of bytes per chunk
+    pub fn split(data: &[u8], chunk_size: usize) -> (Self, Vec<Vec<u8>>) {
+        let chunks: Vec<Vec<u8>> = data.chunks(chunk_size.max(1)).map(<[u8]>::to_vec).collect();
+        (Self::from_chunks(&chunks), chunks)
+    }
+
+    /// Checks that the root hash commits to the length and chunk hashes
+    pub fn is_valid(&self) -> bool {
+        self.root_hash == construct_root_hash(self.total_len, &self.chunk_hashes)
+    }
+
+    /// Checks whether a chunk matches the hash at the given index
+    ///
+    /// ### Arguments
+    ///
+    /// * `index`   - Index of the chunk
+    /// * `chunk`   - Chunk to verify
+    pub fn verify_chunk(&self, index: usize, chunk: &[u8]) -> bool {
+        self.chunk_hashes.get(index) == Some(&hash_chunk(chunk))
+    }
+
+    /// Assembles the payload from chunks given in order, verifying each of them
+    ///
+    /// ### Arguments
+    ///
+    /// * `chunks`  - Chunks of the payload, in order
+    pub fn assemble(&self, chunks: &[Vec<u8>]) -> Result<Vec<u8>, DataManifestError> {
+        if !self.is_valid() {
+            return Err(DataManifestError::InvalidRootHash);
+        }
+
+        // `total_len` is untrusted, so never reserve more than the chunks can fill
+        let chunks_len: u64 = chunks
+            .iter()
+            .take(self.chunk_hashes.len())
+            .map(|chunk| chunk.len() as u64)
+            .sum();
+        let mut data = Vec::with_capacity(self.total_len.min(chunks_len) as usize);
+        for index in 0..self.chunk_hashes.len() {
+            let chunk = chunks
+                .get(index)
+                .ok_or(DataManifestError::MissingChunk(index))?;
+            if !self.verify_chunk(index, chunk) {
+                return Err(DataManifestError::InvalidChunk(index));
+            }
+            data.extend_from_slice(chunk);
+        }
+
+        if chunks.len() != self.chunk_hashes.len() || data.len() as u64 != self.total_len {
+            return Err(DataManifestError::InvalidLength);
+        }
+        Ok(data)
+    }
+
+    /// Assembles the payload from data assets in any order, such as the outputs
+    /// of the chunk create transactions. Assets not part of the payload are ignored
+    ///
+    /// ### Arguments
+    ///
+    /// * `assets`  - Data assets holding the chunks
+    pub fn assemble_from_assets<'a>(
+        &self,
+        assets: impl Iterator<Item = &'a DataAsset>,
+    ) -> Result<Vec<u8>, DataManifestError> {
+        let mut chunks: Vec<Option<Vec<u8>>> = vec![None; self.chunk_hashes.len()];
+        for asset in assets {
+            let hash = hash_chunk(&asset.data);
+            for (index, chunk_hash) in self.chunk_hashes.iter().enumerate() {
+                if *chunk_hash == hash && chunks[index].is_none() {
+                    chunks[index] = Some(asset.data.clone());
+                }
+            }
+        }
+
+        let chunks = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| chunk.ok_or(DataManifestError::MissingChunk(index)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.assemble(&chunks)
+    }
+
+    /// Encodes the manifest to be stored as the data of a `DataAsset`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = DATA_MANIFEST_PREFIX.to_vec();
+        data.extend(serialize(self).unwrap_or_default());
+        data
+    }
+
+    /// Decodes a manifest stored as the data of a `DataAsset`
+    ///
+    /// ### Arguments
+    ///
+    /// * `data`    - Encoded manifest
+    pub fn decode(data: &[u8]) -> Result<Self, DataManifestError> {
+        let encoded = data
+            .strip_prefix(DATA_MANIFEST_PREFIX)
+            .ok_or(DataManifestError::Malformed)?;
+        let manifest: Self = deserialize(encoded).map_err(|_| DataManifestError::Malformed)?;
+        if !manifest.is_valid() {
+            return Err(DataManifestError::InvalidRootHash);
+        }
+        Ok(manifest)
+    }
+}
+
+/// Hashes a single chunk
+///
+/// ### Arguments
+///
+/// * `chunk`   - Chunk to hash
+fn hash_chunk(chunk: &[u8]) -> String {
+    hex::encode(sha3_256::digest(chunk))
+}
+
+/// Constructs the root hash over the payload length and ordered chunk hashes
+///
+/// ### Arguments
+///
+/// * `total_len`       - Length of the payload
+/// * `chunk_hashes`    - Hashes of the chunks, in order
+fn construct_root_hash(total_len: u64, chunk_hashes: &[String]) -> String {
+    let len_bytes = total_len.to_le_bytes();
+    let parts = std::iter::once(&len_bytes[..]).chain(chunk_hashes.iter().map(|h| h.as_bytes()));
+    hex::encode(sha3_256::digest_all(parts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Checks that a payload is split, encoded and assembled back from its chunks
+    fn test_data_manifest_split_and_assemble() {
+        let data: Vec<u8> = (0..2500_u32).map(|i| (i % 251) as u8).collect();
+        let (manifest, chunks) = DataManifest::split(&data, 1000);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(manifest.total_len, 2500);
+        assert!(manifest.is_valid());
+        assert!(manifest.verify_chunk(2, &chunks[2]));
+        assert!(!manifest.verify_chunk(1, &chunks[2]));
+        assert_eq!(manifest.assemble(&chunks), Ok(data.clone()));
+        assert_eq!(
+            DataManifest::decode(&manifest.encode()),
+            Ok(manifest.clone())
+        );
+
+        // Chunks can be found in any order among other assets
+        let assets: Vec<DataAsset> = chunks
+            .iter()
+            .rev()
+            .chain(std::iter::once(&b"other".to_vec()))
+            .map(|chunk| DataAsset {
+                data: chunk.clone(),
+                amount: 1,
+            })
+            .collect();
+        assert_eq!(manifest.assemble_from_assets(assets.iter()), Ok(data));
+        assert_eq!(
+            manifest.assemble_from_assets(assets.iter().skip(1)),
+            Err(DataManifestError::MissingChunk(2))
+        );
+    }
+
+    #[test]
+    /// Checks that tampered chunks and manifests are rejected
+    fn test_data_manifest_rejects_tampering() {
+        let (manifest, mut chunks) = DataManifest::split(b"some confidential document", 8);
+
+        let mut reordered = chunks.clone();
+        reordered.swap(0, 1);
+        assert_eq!(
+            manifest.assemble(&reordered),
+            Err(DataManifestError::InvalidChunk(0))
+        );
+        assert_eq!(
+            manifest.assemble(&chunks[..2]),
+            Err(DataManifestError::MissingChunk(2))
+        );
+
+        let mut tampered = manifest.clone();
+        tampered.chunk_hashes.swap(0, 1);
+        assert_eq!(
+            tampered.assemble(&chunks),
+            Err(DataManifestError::InvalidRootHash)
+        );
+        assert_eq!(
+            DataManifest::decode(&tampered.encode()),
+            Err(DataManifestError::InvalidRootHash)
+        );
+        assert_eq!(
+            DataManifest::decode(b"not a manifest"),
+            Err(DataManifestError::Malformed)
+        );
+
+        let oversized = DataManifest {
+            total_len: u64::MAX,
+            root_hash: construct_root_hash(u64::MAX, &manifest.chunk_hashes),
+            ..manifest.clone()
+        };
+        assert_eq!(
+            oversized.assemble(&chunks),
+            Err(DataManifestError::InvalidLength)
+        );
+
+        chunks.push(b"extra".to_vec());
+        assert_eq!(
+            manifest.assemble(&chunks),
+            Err(DataManifestError::InvalidLength)
+        );
+    }
+}
@@ -1,26 +1,83 @@
-use crate::primitives::asset::Asset;
+use crate::constants::{
+    DRUID_HASH_LEN, DRUID_LENGTH, DRUID_NONCE_LEN, DRUID_PREFIX, NETWORK_VERSION_LEGACY_DDE,
+};
+use crate::crypto::{generate_random, sha3_256};
+use crate::primitives::asset::{Asset, AssetValuesError};
+use crate::primitives::transaction::{OutPoint, Transaction, TxIn};
+use crate::utils::druid_utils::DruidValidationError;
+use bincode::serialize;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
 
-/// The expectation to be met in a specific DRUID transaction
+/// How an output's asset is compared to the asset of an expectation.
+/// Variants are ordered from the most to the least constrained
+#[derive(Default, Clone, Copy, Debug, Ord, Eq, PartialEq, Serialize, Deserialize, PartialOrd)]
+pub enum ExpectationComparison {
+    /// The output must hold exactly the expected asset
+    #[default]
+    Exact,
+    /// The output must hold at most the expected amount of the asset
+    AtMost,
+    /// The output must hold at least the expected amount of the asset
+    AtLeast,
+    /// The output may hold any amount of the asset
+    Any,
+}
+
+/// The expectation to be met in a specific DRUID transaction.
+///
+/// The comparison is only encoded in transactions after `NETWORK_VERSION_LEGACY_DDE`,
+/// earlier ones keep the layout without it and can only hold exact expectations
 #[derive(Default, Clone, Debug, Ord, Eq, PartialEq, Serialize, Deserialize, PartialOrd)]
 pub struct DruidExpectation {
     pub from: String,
     pub to: String,
     pub asset: Asset,
+    #[serde(default)]
+    pub comparison: ExpectationComparison,
+}
+
+impl DruidExpectation {
+    /// Checks whether an asset satisfies the expected asset under the comparison.
+    /// Amounts can only be compared for tokens, receipts and issued tokens of the
+    /// same DRS, and items of the same collection, so other assets must match exactly
+    ///
+    /// ### Arguments
+    ///
+    /// * `asset`   - Asset to compare to the expected one
+    pub fn is_met_by(&self, asset: &Asset) -> bool {
+        match self.comparison {
+            ExpectationComparison::Exact => *asset == self.asset,
+            ExpectationComparison::AtMost => self.asset.is_greater_or_equal_to(asset) == Some(true),
+            ExpectationComparison::AtLeast => {
+                asset.is_greater_or_equal_to(&self.asset) == Some(true)
+            }
+            ExpectationComparison::Any => asset.is_greater_or_equal_to(&self.asset).is_some(),
+        }
+    }
 }
 
 /// A structure to hold DDE-specific content in a transaction
 ///
-/// `druid`                 - DRUID to match on
+/// `druid`                 - DRUID to match on, derived as a `Druid`
 /// `participants`          - Participants in trade
 /// `expect_value`          - The value expected by another party for this tx
 /// `expect_value_amount`   - The amount of the asset expected by another party for this tx
 /// `expect_address`        - The address the other party is expected to pay to
+/// `expiry_height`         - Block height from which the DRUID transactions are no longer valid together
+///
+/// Transactions up to `NETWORK_VERSION_LEGACY_DDE` encode these values without
+/// expectation comparisons and expiry heights, see `is_encodable_in`. The serde
+/// defaults only apply to self-describing formats such as JSON
 #[derive(Default, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct DdeValues {
     pub druid: String,
     pub participants: usize,
     pub expectations: Vec<DruidExpectation>,
+    #[serde(default)]
+    pub expiry_height: Option<u64>,
 }
 
 impl DdeValues {
@@ -28,4 +85,278 @@ impl DdeValues {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Checks whether the DRUID has expired at a block height
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_height`    - Height of the block the transactions would be included in
+    pub fn is_expired_at(&self, block_height: u64) -> bool {
+        matches!(self.expiry_height, Some(expiry) if block_height >= expiry)
+    }
+
+    /// Checks whether the DRUID is derived from the expectations and their senders,
+    /// who are the participants of a complete trade
+    pub fn has_derived_druid(&self) -> bool {
+        let participants: BTreeSet<&String> = self.expectations.iter().map(|e| &e.from).collect();
+        matches!(
+            self.druid.parse::<Druid>(),
+            Ok(druid) if druid.is_derived_from(participants, &self.expectations)
+        )
+    }
+
+    /// Checks whether the values can be encoded in a transaction of the given version
+    ///
+    /// ### Arguments
+    ///
+    /// * `version` - Network version of the transaction
+    pub fn is_encodable_in(&self, version: usize) -> bool {
+        version > NETWORK_VERSION_LEGACY_DDE || self.to_legacy().is_some()
+    }
+
+    /// Converts to the legacy layout, unless a comparison or expiry height is set
+    pub(crate) fn to_legacy(&self) -> Option<LegacyDdeValues> {
+        let expectations = self
+            .expectations
+            .iter()
+            .map(|e| {
+                (e.comparison == ExpectationComparison::Exact).then(|| LegacyDruidExpectation {
+                    from: e.from.clone(),
+                    to: e.to.clone(),
+                    asset: e.asset.clone(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        self.expiry_height.is_none().then_some(LegacyDdeValues {
+            druid: self.druid.clone(),
+            participants: self.participants,
+            expectations,
+        })
+    }
+}
+
+/// Layout of `DruidExpectation` in transactions up to `NETWORK_VERSION_LEGACY_DDE`
+#[derive(Serialize, Deserialize)]
+struct LegacyDruidExpectation {
+    from: String,
+    to: String,
+    asset: Asset,
+}
+
+/// Layout of `DdeValues` in transactions up to `NETWORK_VERSION_LEGACY_DDE`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LegacyDdeValues {
+    druid: String,
+    participants: usize,
+    expectations: Vec<LegacyDruidExpectation>,
+}
+
+impl From<LegacyDdeValues> for DdeValues {
+    fn from(legacy: LegacyDdeValues) -> Self {
+        let expectations = legacy
+            .expectations
+            .into_iter()
+            .map(|e| DruidExpectation {
+                from: e.from,
+                to: e.to,
+                asset: e.asset,
+                comparison: ExpectationComparison::Exact,
+            })
+            .collect();
+
+        Self {
+            druid: legacy.druid,
+            participants: legacy.participants,
+            expectations,
+            expiry_height: None,
+        }
+    }
+}
+
+/// Errors raised when parsing a DRUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DruidError {
+    InvalidPrefix,
+    InvalidLength,
+    InvalidHex,
+}
+
+impl fmt::Display for DruidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidPrefix => write!(f, "DRUID must start with {DRUID_PREFIX}"),
+            Self::InvalidLength => write!(f, "DRUID must be {DRUID_LENGTH} characters long"),
+            Self::InvalidHex => write!(f, "DRUID nonce and hash must be lowercase hex"),
+        }
+    }
+}
+
+/// A DRUID derived from the participants of a trade, its expectations and a
+/// random nonce, so that unrelated trades cannot collide on the same value.
+/// It is written as `DRUID_PREFIX`, then the lowercase hex nonce and truncated derivation hash
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Druid {
+    nonce: [u8; DRUID_NONCE_LEN],
+    hash: [u8; DRUID_HASH_LEN],
+}
+
+impl Druid {
+    /// Derives the DRUID of a trade with a random nonce
+    ///
+    /// ### Arguments
+    ///
+    /// * `participants`    - Input addresses of the participants' transactions
+    /// * `expectations`    - Expectations of all participants
+    pub fn generate<'a>(
+        participants: impl IntoIterator<Item = &'a String>,
+        expectations: impl IntoIterator<Item = &'a DruidExpectation>,
+    ) -> Self {
+        Self::derive(participants, expectations, generate_random())
+    }
+
+    /// Derives the DRUID of a trade. Participants and expectations may be given in
+    /// any order and with duplicates
+    ///
+    /// ### Arguments
+    ///
+    /// * `participants`    - Input addresses of the participants' transactions
+    /// * `expectations`    - Expectations of all participants
+    /// * `nonce`           - Nonce to mix into the derivation
+    pub fn derive<'a>(
+        participants: impl IntoIterator<Item = &'a String>,
+        expectations: impl IntoIterator<Item = &'a DruidExpectation>,
+        nonce: [u8; DRUID_NONCE_LEN],
+    ) -> Self {
+        let participants: BTreeSet<&String> = participants.into_iter().collect();
+        let expectations: BTreeSet<&DruidExpectation> = expectations.into_iter().collect();
+        let bytes = serialize(&(nonce, participants, expectations)).unwrap_or_default();
+
+        let mut hash = [0; DRUID_HASH_LEN];
+        hash.copy_from_slice(&sha3_256::digest(&bytes)[..DRUID_HASH_LEN]);
+        Self { nonce, hash }
+    }
+
+    /// Checks whether this DRUID was derived from the given trade
+    ///
+    /// ### Arguments
+    ///
+    /// * `participants`    - Input addresses of the participants' transactions
+    /// * `expectations`    - Expectations of all participants
+    pub fn is_derived_from<'a>(
+        &self,
+        participants: impl IntoIterator<Item = &'a String>,
+        expectations: impl IntoIterator<Item = &'a DruidExpectation>,
+    ) -> bool {
+        *self == Self::derive(participants, expectations, self.nonce)
+    }
+
+    /// Nonce mixed into the derivation
+    pub fn nonce(&self) -> &[u8; DRUID_NONCE_LEN] {
+        &self.nonce
+    }
+}
+
+impl fmt::Display for Druid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            DRUID_PREFIX,
+            hex::encode(self.nonce),
+            hex::encode(self.hash)
+        )
+    }
+}
+
+impl FromStr for Druid {
+    type Err = DruidError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let encoded = s
+            .strip_prefix(DRUID_PREFIX)
+            .ok_or(DruidError::InvalidPrefix)?;
+        if s.len() != DRUID_LENGTH {
+            return Err(DruidError::InvalidLength);
+        }
+
+        // Only the lowercase encoding is accepted, so each DRUID has a single string form
+        if encoded.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Err(DruidError::InvalidHex);
+        }
+        let bytes = hex::decode(encoded).map_err(|_| DruidError::InvalidHex)?;
+        let (nonce_bytes, hash_bytes) = bytes.split_at(DRUID_NONCE_LEN);
+        let mut nonce = [0; DRUID_NONCE_LEN];
+        let mut hash = [0; DRUID_HASH_LEN];
+        nonce.copy_from_slice(nonce_bytes);
+        hash.copy_from_slice(hash_bytes);
+        Ok(Self { nonce, hash })
+    }
+}
+
+/// A participant of an N-party DDE trade
+#[derive(Default, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DdeParty {
+    pub tx_ins: Vec<TxIn>,
+    pub change_address: Option<String>,
+}
+
+/// An asset sent by a party of a DDE trade, given by its index, to an address
+#[derive(Default, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DdeFlow {
+    pub from: usize,
+    pub to: String,
+    pub asset: Asset,
+}
+
+/// A DDE trade ready to be submitted: its DRUID, the expectations of all parties
+/// and one transaction per party, in the order the parties were given
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DdeTrade {
+    pub druid: String,
+    pub expectations: Vec<DruidExpectation>,
+    pub transactions: Vec<Transaction>,
+}
+
+/// Errors raised when building a DDE trade. Party errors hold the party's index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DdeTradeError {
+    TooFewParties,
+    UnknownParty(usize),
+    NoInputs(usize),
+    NoFlows(usize),
+    MissingInput(usize, OutPoint),
+    InsufficientInputs(usize, AssetValuesError),
+    MissingChangeAddress(usize),
+    UnsupportedAsset(usize),
+    Unbalanced(usize),
+    Unsatisfiable(DruidValidationError),
+}
+
+impl fmt::Display for DdeTradeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooFewParties => write!(f, "A trade needs at least two parties"),
+            Self::UnknownParty(i) => write!(f, "Flow sent by unknown party {i}"),
+            Self::NoInputs(i) => write!(f, "Party {i} commits no inputs"),
+            Self::NoFlows(i) => write!(f, "Party {i} sends no assets"),
+            Self::MissingInput(i, out_point) => {
+                write!(f, "Input {out_point:?} of party {i} is not in the UTXO set")
+            }
+            Self::InsufficientInputs(i, e) => {
+                write!(f, "Inputs of party {i} are insufficient: {e}")
+            }
+            Self::MissingChangeAddress(i) => {
+                write!(f, "Party {i} has change but no change address")
+            }
+            Self::UnsupportedAsset(i) => {
+                write!(
+                    f,
+                    "Party {i} sends or commits data assets, which cannot be traded"
+                )
+            }
+            Self::Unbalanced(i) => write!(f, "Transaction of party {i} is unbalanced"),
+            Self::Unsatisfiable(e) => write!(f, "Trade cannot complete: {e}"),
+        }
+    }
 }
@@ -0,0 +1,555 @@
+use crate::constants::MAX_DRUID_POOL_SIZE;
+use crate::primitives::druid::DdeValues;
+use crate::primitives::transaction::{OutPoint, Transaction, TxOut};
+use crate::utils::druid_utils::{
+    druid_expectations_are_met, druid_expectations_report, DruidReport,
+};
+use crate::utils::script_utils::tx_is_valid;
+use crate::utils::transaction_utils::{construct_tx_hash, construct_tx_ins_address};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Errors raised when adding a transaction to the DRUID pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DruidPoolError {
+    NotDde,
+    Expired,
+    InvalidDruid,
+    UnexpectedParticipant(String),
+    InvalidTransaction,
+    Duplicate,
+    DuplicateParticipant(String),
+    Full,
+    ConflictingValues,
+    ConflictingInputs(String),
+    PoolFull,
+}
+
+impl fmt::Display for DruidPoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotDde => write!(f, "Transaction is not a DDE transaction"),
+            Self::Expired => write!(f, "DRUID has expired"),
+            Self::InvalidDruid => write!(f, "DRUID is not derived from its expectations"),
+            Self::UnexpectedParticipant(address) => {
+                write!(f, "Sender {address} is not expected by the DRUID")
+            }
+            Self::InvalidTransaction => write!(f, "Transaction is not valid"),
+            Self::Duplicate => write!(f, "Transaction is already pooled"),
+            Self::DuplicateParticipant(address) => {
+                write!(f, "Participant {address} already has a pooled transaction")
+            }
+            Self::Full => write!(f, "All participants of the DRUID are already pooled"),
+            Self::ConflictingValues => {
+                write!(
+                    f,
+                    "Transaction declares different DRUID values to the pooled ones"
+                )
+            }
+            Self::ConflictingInputs(druid) => {
+                write!(f, "Transaction inputs are already spent in DRUID {druid}")
+            }
+            Self::PoolFull => write!(f, "DRUID pool is full"),
+        }
+    }
+}
+
+/// Status of a pending trade in the DRUID pool
+///
+/// `participants`  - Number of participants declared by the trade
+/// `received`      - Number of participant transactions pooled so far
+/// `expiry_height` - Block height from which the trade is evicted
+/// `report`        - Status of each expectation and the participants yet to arrive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTradeStatus {
+    pub participants: usize,
+    pub received: usize,
+    pub expiry_height: Option<u64>,
+    pub report: DruidReport,
+}
+
+/// Pooled transactions of a single DRUID, keyed by transaction hash
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+struct DruidPoolEntry {
+    druid_info: DdeValues,
+    txs: BTreeMap<String, Transaction>,
+}
+
+/// Pool holding DDE transactions until every participant of their DRUID has arrived
+/// and the DRUID's expectations are met, at which point the whole set is released
+/// for block inclusion
+///
+/// Each DRUID must be derived from its expectations and takes the declared values of
+/// its first transaction. Every transaction must be valid against the UTXO set and
+/// sent by the sender of an expectation. Later transactions must declare the same
+/// values and come from a new participant, until all declared participants have arrived.
+///
+/// Inputs are claimed on a first-seen basis: a transaction spending inputs already
+/// pooled under a DRUID is rejected, as signatures only commit to the spent outpoints
+/// and can be replayed by anyone. Claimed inputs are released by `evict_spent` and
+/// `evict_expired`
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct DruidPool {
+    entries: BTreeMap<String, DruidPoolEntry>,
+    spent: BTreeMap<OutPoint, String>,
+    max_druids: usize,
+}
+
+impl Default for DruidPool {
+    fn default() -> Self {
+        Self::with_max_druids(MAX_DRUID_POOL_SIZE)
+    }
+}
+
+impl DruidPool {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a pool holding at most `max_druids` pending DRUIDs
+    ///
+    /// ### Arguments
+    ///
+    /// * `max_druids`  - Maximum number of pending DRUIDs
+    pub fn with_max_druids(max_druids: usize) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            spent: BTreeMap::new(),
+            max_druids,
+        }
+    }
+
+    /// Adds a DDE transaction to the pool, under its DRUID
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`              - DDE transaction to add
+    /// * `block_height`    - Height of the next block
+    /// * `is_in_utxo`      - UTXO view to validate the transaction against
+    pub fn add_tx<'a>(
+        &mut self,
+        tx: Transaction,
+        block_height: u64,
+        is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+    ) -> Result<(), DruidPoolError> {
+        let druid_info = tx.druid_info.as_ref().ok_or(DruidPoolError::NotDde)?;
+        if druid_info.is_expired_at(block_height) {
+            return Err(DruidPoolError::Expired);
+        }
+        if !druid_info.has_derived_druid() {
+            return Err(DruidPoolError::InvalidDruid);
+        }
+
+        let sender = construct_tx_ins_address(&tx.inputs);
+        if !druid_info.expectations.iter().any(|e| e.from == sender) {
+            return Err(DruidPoolError::UnexpectedParticipant(sender));
+        }
+
+        let entry = self.entries.get(&druid_info.druid);
+        if let Some(entry) = entry {
+            if entry.druid_info != *druid_info {
+                return Err(DruidPoolError::ConflictingValues);
+            }
+
+            let tx_hash = construct_tx_hash(&tx);
+            if entry.txs.contains_key(&tx_hash) {
+                return Err(DruidPoolError::Duplicate);
+            }
+
+            let is_pooled_sender = entry
+                .txs
+                .values()
+                .any(|pooled| construct_tx_ins_address(&pooled.inputs) == sender);
+            if is_pooled_sender {
+                return Err(DruidPoolError::DuplicateParticipant(sender));
+            }
+
+            if entry.txs.len() >= entry.druid_info.participants {
+                return Err(DruidPoolError::Full);
+            }
+        }
+
+        let conflict = tx
+            .inputs
+            .iter()
+            .filter_map(|tx_in| tx_in.previous_out.as_ref())
+            .find_map(|out_point| self.spent.get(out_point));
+        if let Some(druid) = conflict {
+            return Err(DruidPoolError::ConflictingInputs(druid.clone()));
+        }
+
+        if entry.is_none() && self.entries.len() >= self.max_druids {
+            return Err(DruidPoolError::PoolFull);
+        }
+
+        if !tx_is_valid(&tx, is_in_utxo) {
+            return Err(DruidPoolError::InvalidTransaction);
+        }
+
+        let druid = druid_info.druid.clone();
+        let entry = self
+            .entries
+            .entry(druid.clone())
+            .or_insert_with(|| DruidPoolEntry {
+                druid_info: druid_info.clone(),
+                txs: BTreeMap::new(),
+            });
+        for out_point in tx.inputs.iter().filter_map(|i| i.previous_out.as_ref()) {
+            self.spent.insert(out_point.clone(), druid.clone());
+        }
+        entry.txs.insert(construct_tx_hash(&tx), tx);
+
+        Ok(())
+    }
+
+    /// Whether every participant of a DRUID has arrived, its expectations are met
+    /// and it has not expired
+    ///
+    /// ### Arguments
+    ///
+    /// * `druid`           - DRUID to check
+    /// * `block_height`    - Height of the block the transactions would be included in
+    pub fn is_complete(&self, druid: &str, block_height: u64) -> bool {
+        matches!(
+            self.entries.get(druid),
+            Some(entry) if druid_expectations_are_met(druid, entry.txs.values(), block_height)
+        )
+    }
+
+    /// Removes and returns the transactions of every complete DRUID, after evicting
+    /// the expired ones. Each DRUID's transactions are released together or not at all
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_height`    - Height of the block the transactions would be included in
+    pub fn take_complete(&mut self, block_height: u64) -> BTreeMap<String, Vec<Transaction>> {
+        self.evict_expired(block_height);
+
+        let complete: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|druid| self.is_complete(druid, block_height))
+            .cloned()
+            .collect();
+
+        complete
+            .into_iter()
+            .filter_map(|druid| {
+                let entry = self.remove_entry(&druid)?;
+                Some((druid, entry.txs.into_values().collect()))
+            })
+            .collect()
+    }
+
+    /// Evicts the DRUIDs expired at a block height, returning them
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_height`    - Height of the next block
+    pub fn evict_expired(&mut self, block_height: u64) -> Vec<String> {
+        self.evict_where(|entry| entry.druid_info.is_expired_at(block_height))
+    }
+
+    /// Evicts the DRUIDs with a transaction spending an input no longer unspent,
+    /// as it conflicts with a transaction included in a block, returning them
+    ///
+    /// ### Arguments
+    ///
+    /// * `is_in_utxo`  - Function checking whether an output is unspent
+    pub fn evict_spent(&mut self, is_in_utxo: impl Fn(&OutPoint) -> bool) -> Vec<String> {
+        self.evict_where(|entry| {
+            entry
+                .txs
+                .values()
+                .flat_map(|tx| tx.inputs.iter())
+                .filter_map(|tx_in| tx_in.previous_out.as_ref())
+                .any(|out_point| !is_in_utxo(out_point))
+        })
+    }
+
+    /// Gets the status of a pending DRUID
+    ///
+    /// ### Arguments
+    ///
+    /// * `druid`   - DRUID to get the status of
+    pub fn status(&self, druid: &str) -> Option<PendingTradeStatus> {
+        self.entries.get(druid).map(|entry| PendingTradeStatus {
+            participants: entry.druid_info.participants,
+            received: entry.txs.len(),
+            expiry_height: entry.druid_info.expiry_height,
+            report: druid_expectations_report(druid, entry.txs.values()),
+        })
+    }
+
+    /// Iterates over the pending DRUIDs
+    pub fn druids(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Number of pending DRUIDs
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no DRUID is pending
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Evicts the DRUIDs whose entry matches a predicate, returning them
+    ///
+    /// ### Arguments
+    ///
+    /// * `predicate`   - Predicate selecting the entries to evict
+    fn evict_where(&mut self, predicate: impl Fn(&DruidPoolEntry) -> bool) -> Vec<String> {
+        let evicted: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| predicate(entry))
+            .map(|(druid, _)| druid.clone())
+            .collect();
+
+        for druid in &evicted {
+            self.remove_entry(druid);
+        }
+        evicted
+    }
+
+    /// Removes a DRUID's entry along with its spent inputs
+    ///
+    /// ### Arguments
+    ///
+    /// * `druid`   - DRUID to remove
+    fn remove_entry(&mut self, druid: &str) -> Option<DruidPoolEntry> {
+        let entry = self.entries.remove(druid)?;
+        self.spent.retain(|_, spent_druid| spent_druid != druid);
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::primitives::asset::{Asset, TokenAmount};
+    use crate::primitives::druid::{DdeFlow, DdeParty};
+    use crate::primitives::transaction::TxConstructor;
+    use crate::utils::transaction_utils::{
+        construct_address, construct_dde_trade, construct_payment_tx_ins,
+        construct_tx_in_signable_hash,
+    };
+
+    /// Util function to create parties each committing an input holding 10 tokens
+    fn create_parties(
+        names: &[&str],
+        utxo: &mut BTreeMap<OutPoint, TxOut>,
+    ) -> Vec<(DdeParty, String)> {
+        names
+            .iter()
+            .map(|name| {
+                let (pk, sk) = sign::gen_keypair();
+                let address = construct_address(&pk);
+                let previous_out = OutPoint::new(format!("{name}_tx"), 0);
+                let signable_hash = construct_tx_in_signable_hash(&previous_out);
+                utxo.insert(
+                    previous_out.clone(),
+                    TxOut::new_token_amount(address.clone(), TokenAmount(10)),
+                );
+                let tx_ins = construct_payment_tx_ins(vec![TxConstructor {
+                    previous_out,
+                    signatures: vec![sign::sign_detached(signable_hash.as_bytes(), &sk)],
+                    pub_keys: vec![pk],
+                    address_version: None,
+                }]);
+                let party = DdeParty {
+                    tx_ins,
+                    change_address: Some(address.clone()),
+                };
+                (party, address)
+            })
+            .collect()
+    }
+
+    /// Util function to create a trade where each party pays the next one 10 tokens,
+    /// in a ring, returning its derived DRUID and transactions
+    fn create_trade_txs(
+        parties: &[(DdeParty, String)],
+        expiry_height: Option<u64>,
+        utxo: &BTreeMap<OutPoint, TxOut>,
+    ) -> (String, Vec<Transaction>) {
+        let flows: Vec<DdeFlow> = (0..parties.len())
+            .map(|i| DdeFlow {
+                from: i,
+                to: parties[(i + 1) % parties.len()].1.clone(),
+                asset: Asset::token_u64(10),
+            })
+            .collect();
+        let parties: Vec<DdeParty> = parties.iter().map(|(party, _)| party.clone()).collect();
+        let trade = construct_dde_trade(&parties, &flows, expiry_height, |o| utxo.get(o)).unwrap();
+        (trade.druid, trade.transactions)
+    }
+
+    #[test]
+    /// Checks that a DRUID is only released once all its participants have arrived
+    fn test_druid_pool_releases_complete_sets() {
+        let mut utxo = BTreeMap::new();
+        let parties = create_parties(&["alice", "bob", "carol"], &mut utxo);
+        let (druid, txs) = create_trade_txs(&parties, None, &utxo);
+        let mut pool = DruidPool::new();
+
+        pool.add_tx(txs[0].clone(), 1, |o| utxo.get(o)).unwrap();
+        pool.add_tx(txs[1].clone(), 1, |o| utxo.get(o)).unwrap();
+        let pending = pool.status(&druid).unwrap();
+
+        assert!(!pool.is_complete(&druid, 1));
+        assert!(pool.take_complete(1).is_empty());
+        assert_eq!((pending.participants, pending.received), (3, 2));
+        assert_eq!(pending.report.missing_participants.len(), 1);
+
+        pool.add_tx(txs[2].clone(), 1, |o| utxo.get(o)).unwrap();
+        let mut released = pool.take_complete(1);
+        let mut released_txs = released.remove(&druid).unwrap();
+        let mut expected_txs = txs;
+        released_txs.sort_by_key(construct_tx_hash);
+        expected_txs.sort_by_key(construct_tx_hash);
+
+        assert_eq!(released_txs, expected_txs);
+        assert!(released.is_empty());
+        assert!(pool.is_empty());
+        assert_eq!(pool.status(&druid), None);
+    }
+
+    #[test]
+    /// Checks that invalid, unexpected and conflicting transactions are rejected
+    fn test_druid_pool_rejects_invalid_txs() {
+        let mut utxo = BTreeMap::new();
+        let parties = create_parties(&["alice", "bob", "carol"], &mut utxo);
+        let others = create_parties(&["dave", "erin"], &mut utxo);
+        let (druid, txs) = create_trade_txs(&parties, None, &utxo);
+        let (_, other_txs) = create_trade_txs(&others, None, &utxo);
+        let mut pool = DruidPool::with_max_druids(1);
+        let add =
+            |pool: &mut DruidPool, tx: &Transaction| pool.add_tx(tx.clone(), 1, |o| utxo.get(o));
+        add(&mut pool, &txs[0]).unwrap();
+
+        let mut underived = txs[1].clone();
+        underived.druid_info.as_mut().unwrap().expectations.pop();
+        let mut unexpected = other_txs[0].clone();
+        unexpected.druid_info = txs[1].druid_info.clone();
+        let unexpected_sender = construct_tx_ins_address(&unexpected.inputs);
+        let mut conflicting_values = txs[1].clone();
+        conflicting_values.druid_info.as_mut().unwrap().participants = 2;
+        let mut same_participant = txs[0].clone();
+        same_participant.outputs[0].value = Asset::token_u64(9);
+
+        assert_eq!(
+            add(&mut pool, &Transaction::new()),
+            Err(DruidPoolError::NotDde)
+        );
+        assert_eq!(
+            add(&mut pool, &underived),
+            Err(DruidPoolError::InvalidDruid)
+        );
+        assert_eq!(
+            add(&mut pool, &unexpected),
+            Err(DruidPoolError::UnexpectedParticipant(unexpected_sender))
+        );
+        assert_eq!(
+            pool.add_tx(txs[1].clone(), 1, |_| None),
+            Err(DruidPoolError::InvalidTransaction)
+        );
+        assert_eq!(add(&mut pool, &txs[0]), Err(DruidPoolError::Duplicate));
+        assert_eq!(
+            add(&mut pool, &conflicting_values),
+            Err(DruidPoolError::ConflictingValues)
+        );
+        assert!(matches!(
+            add(&mut pool, &same_participant),
+            Err(DruidPoolError::DuplicateParticipant(_))
+        ));
+        assert_eq!(add(&mut pool, &other_txs[0]), Err(DruidPoolError::PoolFull));
+        assert_eq!(pool.status(&druid).unwrap().received, 1);
+
+        // Participants beyond the declared number are not pooled
+        let mut pool = DruidPool::new();
+        let undercounted: Vec<Transaction> = txs
+            .iter()
+            .cloned()
+            .map(|mut tx| {
+                tx.druid_info.as_mut().unwrap().participants = 2;
+                tx
+            })
+            .collect();
+        add(&mut pool, &undercounted[0]).unwrap();
+        add(&mut pool, &undercounted[1]).unwrap();
+
+        assert_eq!(add(&mut pool, &undercounted[2]), Err(DruidPoolError::Full));
+        assert!(!pool.is_complete(&druid, 1));
+    }
+
+    #[test]
+    /// Checks that a transaction re-committing pooled inputs is rejected, keeping the
+    /// first DRUID to claim them until it is evicted
+    fn test_druid_pool_keeps_first_seen_inputs() {
+        let mut utxo = BTreeMap::new();
+        let parties = create_parties(&["alice", "bob", "carol"], &mut utxo);
+        let mut new_parties = create_parties(&["dave"], &mut utxo);
+        new_parties.insert(0, parties[0].clone());
+        let (druid, txs) = create_trade_txs(&parties, None, &utxo);
+        let (new_druid, new_txs) = create_trade_txs(&new_parties, None, &utxo);
+        let mut pool = DruidPool::new();
+        let add =
+            |pool: &mut DruidPool, tx: &Transaction| pool.add_tx(tx.clone(), 1, |o| utxo.get(o));
+
+        add(&mut pool, &txs[0]).unwrap();
+        add(&mut pool, &txs[1]).unwrap();
+
+        assert_eq!(
+            add(&mut pool, &new_txs[0]),
+            Err(DruidPoolError::ConflictingInputs(druid.clone()))
+        );
+        assert_eq!(pool.status(&druid).unwrap().received, 2);
+        assert_eq!(pool.status(&new_druid), None);
+
+        // Once Bob's input is spent elsewhere, the first DRUID is evicted and
+        // Alice's input can be claimed again
+        let bob_out = txs[1].inputs[0].previous_out.clone().unwrap();
+        assert_eq!(
+            pool.evict_spent(|out_point| *out_point != bob_out),
+            vec![druid.clone()]
+        );
+        add(&mut pool, &new_txs[0]).unwrap();
+        assert_eq!(pool.status(&new_druid).unwrap().received, 1);
+    }
+
+    #[test]
+    /// Checks that expired and spent DRUIDs are evicted with their inputs
+    fn test_druid_pool_evicts_expired_and_spent() {
+        let mut utxo = BTreeMap::new();
+        let expiring_parties = create_parties(&["alice", "bob", "carol"], &mut utxo);
+        let spent_parties = create_parties(&["dave", "erin", "frank"], &mut utxo);
+        let (expiring_druid, expiring) = create_trade_txs(&expiring_parties, Some(10), &utxo);
+        let (spent_druid, spent) = create_trade_txs(&spent_parties, None, &utxo);
+        let mut pool = DruidPool::new();
+        pool.add_tx(expiring[0].clone(), 1, |o| utxo.get(o))
+            .unwrap();
+        pool.add_tx(spent[0].clone(), 1, |o| utxo.get(o)).unwrap();
+        let spent_out = spent[0].inputs[0].previous_out.clone().unwrap();
+
+        assert_eq!(
+            pool.add_tx(expiring[1].clone(), 10, |o| utxo.get(o)),
+            Err(DruidPoolError::Expired)
+        );
+        assert!(pool.evict_expired(9).is_empty());
+        assert_eq!(pool.evict_expired(10), vec![expiring_druid]);
+        assert_eq!(
+            pool.evict_spent(|out_point| *out_point != spent_out),
+            vec![spent_druid]
+        );
+        assert!(pool.is_empty());
+
+        // Inputs of evicted DRUIDs can be pooled again
+        assert_eq!(pool.add_tx(spent[0].clone(), 1, |o| utxo.get(o)), Ok(()));
+    }
+}
@@ -1,4 +1,8 @@
 pub mod asset;
+pub mod asset_registry;
 pub mod block;
+pub mod data_manifest;
 pub mod druid;
+pub mod druid_pool;
+pub mod rb_payment;
 pub mod transaction;
@@ -0,0 +1,594 @@
+use crate::primitives::asset::{Asset, TokenAmount};
+use crate::primitives::druid::{Druid, DruidExpectation};
+use crate::primitives::transaction::{Transaction, TxIn, TxOut};
+use crate::utils::druid_utils::{
+    druid_expectations_report, validate_druid_txs_at, DruidValidationError, ExpectationStatus,
+};
+use crate::utils::transaction_utils::{
+    construct_dde_refund_tx, construct_rb_payments_send_tx, construct_rb_receive_payment_tx,
+    construct_tx_ins_address,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Terms of a receipt-based payment, sent by the receiver of the payment to the sender.
+/// The receiver commits the inputs holding the receipt it returns as proof of payment
+///
+/// `receiver_from`     - Address of the receiver's committed inputs
+/// `receiver_address`  - Address the tokens are to be paid to
+/// `amount`            - Amount of tokens to be paid
+/// `drs_tx_hash`       - DRS transaction hash of the receipt returned for the payment
+/// `expiry_height`     - Block height from which the payment can no longer complete
+#[derive(Default, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RbPaymentRequest {
+    pub receiver_from: String,
+    pub receiver_address: String,
+    pub amount: TokenAmount,
+    pub drs_tx_hash: Option<String>,
+    pub expiry_height: Option<u64>,
+}
+
+/// State of a receipt-based payment, serialized between the two wallets as the
+/// payment progresses. Each side validates the other's transaction before moving on
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RbPaymentState {
+    /// The receiver has requested a payment
+    Requested(RbPaymentRequest),
+    /// The sender has built its half, paying the tokens
+    SenderHalfBuilt {
+        request: RbPaymentRequest,
+        sender_address: String,
+        send_tx: Transaction,
+    },
+    /// The receiver has validated the sender's half and built its own, returning the receipt
+    ReceiverHalfBuilt {
+        request: RbPaymentRequest,
+        sender_address: String,
+        send_tx: Transaction,
+        receive_tx: Transaction,
+    },
+    /// The sender has validated both halves together, which can now be submitted
+    Matched {
+        request: RbPaymentRequest,
+        send_tx: Transaction,
+        receive_tx: Transaction,
+    },
+    /// The payment expired before completing. Built halves can be refunded
+    Expired {
+        request: RbPaymentRequest,
+        send_tx: Option<Transaction>,
+        receive_tx: Option<Transaction>,
+    },
+}
+
+/// Errors raised on a receipt-based payment transition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RbPaymentError {
+    InvalidTransition,
+    InvalidRequest,
+    Expired,
+    WrongInputs,
+    MismatchedHalf,
+    UnmetExpectation(Box<DruidExpectation>),
+    Unmatched(DruidValidationError),
+}
+
+impl fmt::Display for RbPaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidTransition => write!(f, "Transition is not valid in this state"),
+            Self::InvalidRequest => write!(f, "Payment request is not valid"),
+            Self::Expired => write!(f, "Payment has expired"),
+            Self::WrongInputs => write!(f, "Inputs do not match the payment request"),
+            Self::MismatchedHalf => write!(f, "Transaction does not match the agreed payment"),
+            Self::UnmetExpectation(e) => {
+                write!(f, "Expectation {} -> {} is not met", e.from, e.to)
+            }
+            Self::Unmatched(e) => write!(f, "Payment halves do not match: {e}"),
+        }
+    }
+}
+
+impl RbPaymentState {
+    /// Creates the request of a receipt-based payment
+    ///
+    /// ### Arguments
+    ///
+    /// * `request` - Terms of the payment
+    pub fn request(request: RbPaymentRequest) -> Result<Self, RbPaymentError> {
+        if request.amount == TokenAmount(0) || request.receiver_from.is_empty() {
+            return Err(RbPaymentError::InvalidRequest);
+        }
+        Ok(Self::Requested(request))
+    }
+
+    /// Terms of the payment
+    pub fn get_request(&self) -> &RbPaymentRequest {
+        match self {
+            Self::Requested(request)
+            | Self::SenderHalfBuilt { request, .. }
+            | Self::ReceiverHalfBuilt { request, .. }
+            | Self::Matched { request, .. }
+            | Self::Expired { request, .. } => request,
+        }
+    }
+
+    /// Builds the sender's half, paying the requested tokens. The DRUID is derived
+    /// from both parties' inputs and the agreed expectations
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_ins`          - Sender's inputs, holding at least the requested amount
+    /// * `tx_outs`         - Sender's change outputs
+    /// * `sender_address`  - Address the receipt is to be returned to
+    /// * `block_height`    - Current block height
+    pub fn build_sender_half(
+        &self,
+        tx_ins: Vec<TxIn>,
+        tx_outs: Vec<TxOut>,
+        sender_address: String,
+        block_height: u64,
+    ) -> Result<Self, RbPaymentError> {
+        let request = match self {
+            Self::Requested(request) => request,
+            _ => return Err(RbPaymentError::InvalidTransition),
+        };
+        check_unexpired(request, block_height)?;
+
+        let sender_from = construct_tx_ins_address(&tx_ins);
+        let expectations = agreed_expectations(request, &sender_from, &sender_address);
+        let druid = Druid::generate(&[sender_from, request.receiver_from.clone()], &expectations);
+
+        let mut send_tx = construct_rb_payments_send_tx(
+            tx_ins,
+            tx_outs,
+            request.receiver_address.clone(),
+            request.amount,
+            0,
+            druid.to_string(),
+            expectations,
+        );
+        set_expiry_height(&mut send_tx, request.expiry_height);
+
+        Ok(Self::SenderHalfBuilt {
+            request: request.clone(),
+            sender_address,
+            send_tx,
+        })
+    }
+
+    /// Validates the sender's half against the agreed expectations and builds the
+    /// receiver's half, returning the receipt
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_ins`          - Receiver's inputs, committed in the request
+    /// * `tx_outs`         - Receiver's change outputs
+    /// * `block_height`    - Current block height
+    pub fn build_receiver_half(
+        &self,
+        tx_ins: Vec<TxIn>,
+        tx_outs: Vec<TxOut>,
+        block_height: u64,
+    ) -> Result<Self, RbPaymentError> {
+        let (request, sender_address, send_tx) = match self {
+            Self::SenderHalfBuilt {
+                request,
+                sender_address,
+                send_tx,
+            } => (request, sender_address, send_tx),
+            _ => return Err(RbPaymentError::InvalidTransition),
+        };
+        check_unexpired(request, block_height)?;
+        if construct_tx_ins_address(&tx_ins) != request.receiver_from {
+            return Err(RbPaymentError::WrongInputs);
+        }
+
+        let sender_from = construct_tx_ins_address(&send_tx.inputs);
+        let expectations = agreed_expectations(request, &sender_from, sender_address);
+        let druid = check_half(send_tx, request, &expectations)?;
+        let participants = [sender_from, request.receiver_from.clone()];
+        if !matches!(druid.parse::<Druid>(), Ok(d) if d.is_derived_from(&participants, &expectations))
+        {
+            return Err(RbPaymentError::MismatchedHalf);
+        }
+        check_expectation_met(&druid, send_tx, &expectations[0])?;
+
+        let mut receive_tx = construct_rb_receive_payment_tx(
+            tx_ins,
+            tx_outs,
+            sender_address.clone(),
+            0,
+            druid,
+            expectations,
+            request.drs_tx_hash.clone(),
+        );
+        set_expiry_height(&mut receive_tx, request.expiry_height);
+
+        Ok(Self::ReceiverHalfBuilt {
+            request: request.clone(),
+            sender_address: sender_address.clone(),
+            send_tx: send_tx.clone(),
+            receive_tx,
+        })
+    }
+
+    /// Validates the receiver's half against the agreed expectations and both
+    /// halves together, after which they can be submitted
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_height`    - Current block height
+    pub fn match_halves(&self, block_height: u64) -> Result<Self, RbPaymentError> {
+        let (request, sender_address, send_tx, receive_tx) = match self {
+            Self::ReceiverHalfBuilt {
+                request,
+                sender_address,
+                send_tx,
+                receive_tx,
+            } => (request, sender_address, send_tx, receive_tx),
+            _ => return Err(RbPaymentError::InvalidTransition),
+        };
+        check_unexpired(request, block_height)?;
+        if construct_tx_ins_address(&receive_tx.inputs) != request.receiver_from
+            || receive_tx.druid_info != send_tx.druid_info
+        {
+            return Err(RbPaymentError::MismatchedHalf);
+        }
+
+        let sender_from = construct_tx_ins_address(&send_tx.inputs);
+        let expectations = agreed_expectations(request, &sender_from, sender_address);
+        let druid = check_half(receive_tx, request, &expectations)?;
+        check_expectation_met(&druid, receive_tx, &expectations[1])?;
+        validate_druid_txs_at(&druid, [send_tx, receive_tx].iter().copied(), block_height)
+            .map_err(RbPaymentError::Unmatched)?;
+
+        Ok(Self::Matched {
+            request: request.clone(),
+            send_tx: send_tx.clone(),
+            receive_tx: receive_tx.clone(),
+        })
+    }
+
+    /// Moves an unfinished payment to `Expired` once its expiry height is reached.
+    /// Other payments are returned unchanged
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_height`    - Current block height
+    pub fn expire(&self, block_height: u64) -> Self {
+        let request = self.get_request();
+        if matches!(self, Self::Matched { .. } | Self::Expired { .. })
+            || check_unexpired(request, block_height).is_ok()
+        {
+            return self.clone();
+        }
+
+        let (send_tx, receive_tx) = match self {
+            Self::SenderHalfBuilt { send_tx, .. } => (Some(send_tx.clone()), None),
+            Self::ReceiverHalfBuilt {
+                send_tx,
+                receive_tx,
+                ..
+            } => (Some(send_tx.clone()), Some(receive_tx.clone())),
+            _ => (None, None),
+        };
+        Self::Expired {
+            request: request.clone(),
+            send_tx,
+            receive_tx,
+        }
+    }
+
+    /// Constructs the refunds of the sender's and receiver's halves of an expired
+    /// payment, if built, each returning the committed inputs to the address they
+    /// spent from, as in `construct_dde_refund_tx`
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_height`    - Height of the block the refunds would be included in
+    pub fn refund_txs(&self, block_height: u64) -> (Option<Transaction>, Option<Transaction>) {
+        match self {
+            Self::Expired {
+                send_tx,
+                receive_tx,
+                ..
+            } => (
+                send_tx
+                    .as_ref()
+                    .and_then(|tx| construct_dde_refund_tx(tx, block_height)),
+                receive_tx
+                    .as_ref()
+                    .and_then(|tx| construct_dde_refund_tx(tx, block_height)),
+            ),
+            _ => (None, None),
+        }
+    }
+}
+
+/// Builds the expectations both parties agree on: the sender pays the tokens to the
+/// receiver, and the receiver returns the receipt to the sender
+///
+/// ### Arguments
+///
+/// * `request`         - Terms of the payment
+/// * `sender_from`     - Address of the sender's inputs
+/// * `sender_address`  - Address the receipt is to be returned to
+fn agreed_expectations(
+    request: &RbPaymentRequest,
+    sender_from: &str,
+    sender_address: &str,
+) -> Vec<DruidExpectation> {
+    vec![
+        DruidExpectation {
+            from: sender_from.to_owned(),
+            to: request.receiver_address.clone(),
+            asset: Asset::Token(request.amount),
+            ..Default::default()
+        },
+        DruidExpectation {
+            from: request.receiver_from.clone(),
+            to: sender_address.to_owned(),
+            asset: Asset::receipt(1, request.drs_tx_hash.clone(), None),
+            ..Default::default()
+        },
+    ]
+}
+
+/// Checks that a half declares the agreed participants, expectations and expiry,
+/// returning its DRUID
+///
+/// ### Arguments
+///
+/// * `tx`              - Half to check
+/// * `request`         - Terms of the payment
+/// * `expectations`    - Agreed expectations
+fn check_half(
+    tx: &Transaction,
+    request: &RbPaymentRequest,
+    expectations: &[DruidExpectation],
+) -> Result<String, RbPaymentError> {
+    let druid_info = tx
+        .druid_info
+        .as_ref()
+        .ok_or(RbPaymentError::MismatchedHalf)?;
+    let declared: BTreeSet<&DruidExpectation> = druid_info.expectations.iter().collect();
+    let agreed: BTreeSet<&DruidExpectation> = expectations.iter().collect();
+
+    if druid_info.participants != 2
+        || declared != agreed
+        || druid_info.expiry_height != request.expiry_height
+    {
+        return Err(RbPaymentError::MismatchedHalf);
+    }
+    Ok(druid_info.druid.clone())
+}
+
+/// Checks that a half meets the expectation its party is responsible for
+///
+/// ### Arguments
+///
+/// * `druid`       - DRUID of the payment
+/// * `tx`          - Half to check
+/// * `expectation` - Expectation the half must meet
+fn check_expectation_met(
+    druid: &str,
+    tx: &Transaction,
+    expectation: &DruidExpectation,
+) -> Result<(), RbPaymentError> {
+    let report = druid_expectations_report(druid, std::iter::once(tx));
+    let is_met = report
+        .expectations
+        .iter()
+        .any(|r| r.expectation == *expectation && r.status == ExpectationStatus::Met);
+
+    if !is_met {
+        return Err(RbPaymentError::UnmetExpectation(Box::new(
+            expectation.clone(),
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the payment has not expired
+///
+/// ### Arguments
+///
+/// * `request`         - Terms of the payment
+/// * `block_height`    - Current block height
+fn check_unexpired(request: &RbPaymentRequest, block_height: u64) -> Result<(), RbPaymentError> {
+    match request.expiry_height {
+        Some(expiry) if block_height >= expiry => Err(RbPaymentError::Expired),
+        _ => Ok(()),
+    }
+}
+
+/// Sets the expiry height of a half
+///
+/// ### Arguments
+///
+/// * `tx`              - Half to set the expiry of
+/// * `expiry_height`   - Expiry height of the payment
+fn set_expiry_height(tx: &mut Transaction, expiry_height: Option<u64>) {
+    if let Some(druid_info) = &mut tx.druid_info {
+        druid_info.expiry_height = expiry_height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::primitives::transaction::{OutPoint, TxConstructor};
+    use crate::script::StackEntry;
+    use crate::utils::druid_utils::druid_is_valid;
+    use crate::utils::transaction_utils::{
+        construct_address, construct_payment_tx_ins, construct_tx_in_signable_hash,
+    };
+
+    /// Util function to create signed inputs spending a single output
+    fn create_tx_ins(hash: &str) -> Vec<TxIn> {
+        let (pk, sk) = sign::gen_keypair();
+        let previous_out = OutPoint::new(hash.to_owned(), 0);
+        let signable_hash = construct_tx_in_signable_hash(&previous_out);
+        construct_payment_tx_ins(vec![TxConstructor {
+            previous_out,
+            signatures: vec![sign::sign_detached(signable_hash.as_bytes(), &sk)],
+            pub_keys: vec![pk],
+            address_version: None,
+        }])
+    }
+
+    #[test]
+    /// Checks a receipt-based payment through every state, serialized between wallets
+    fn test_rb_payment_state_machine() {
+        //
+        // Arrange
+        //
+        let sender_ins = create_tx_ins("sender_tx");
+        let receiver_ins = create_tx_ins("receiver_tx");
+        let sender_address = construct_address(&sign::gen_keypair().0);
+        let request = RbPaymentRequest {
+            receiver_from: construct_tx_ins_address(&receiver_ins),
+            receiver_address: construct_address(&sign::gen_keypair().0),
+            amount: TokenAmount(25),
+            drs_tx_hash: Some("drs_tx_hash".to_owned()),
+            expiry_height: Some(100),
+        };
+        let transfer = |state: &RbPaymentState| {
+            let bytes = bincode::serialize(state).unwrap();
+            bincode::deserialize::<RbPaymentState>(&bytes).unwrap()
+        };
+
+        //
+        // Act
+        //
+        let requested = RbPaymentState::request(request.clone()).unwrap();
+        let sender_half = requested
+            .build_sender_half(sender_ins.clone(), Vec::new(), sender_address.clone(), 10)
+            .unwrap();
+        let receiver_half = transfer(&sender_half)
+            .build_receiver_half(receiver_ins.clone(), Vec::new(), 11)
+            .unwrap();
+        let matched = transfer(&receiver_half).match_halves(12).unwrap();
+
+        //
+        // Assert
+        //
+        let (send_tx, receive_tx) = match &matched {
+            RbPaymentState::Matched {
+                send_tx,
+                receive_tx,
+                ..
+            } => (send_tx, receive_tx),
+            _ => panic!("Payment is not matched"),
+        };
+        let druid = &send_tx.druid_info.as_ref().unwrap().druid;
+        assert!(druid_is_valid(druid, [send_tx, receive_tx].iter().copied()));
+        assert_eq!(matched.get_request(), &request);
+        assert_eq!(matched.expire(200), matched);
+
+        // Transitions are only valid from their own state
+        assert_eq!(
+            requested.match_halves(12),
+            Err(RbPaymentError::InvalidTransition)
+        );
+        assert_eq!(
+            RbPaymentState::request(RbPaymentRequest::default()),
+            Err(RbPaymentError::InvalidRequest)
+        );
+    }
+
+    #[test]
+    /// Checks that each side rejects a half not matching the agreed payment
+    fn test_rb_payment_rejects_mismatched_halves() {
+        let sender_ins = create_tx_ins("sender_tx");
+        let receiver_ins = create_tx_ins("receiver_tx");
+        let request = RbPaymentRequest {
+            receiver_from: construct_tx_ins_address(&receiver_ins),
+            receiver_address: construct_address(&sign::gen_keypair().0),
+            amount: TokenAmount(25),
+            drs_tx_hash: Some("drs_tx_hash".to_owned()),
+            expiry_height: Some(100),
+        };
+        let requested = RbPaymentState::request(request).unwrap();
+        let sender_half = requested
+            .build_sender_half(sender_ins, Vec::new(), "sender".to_owned(), 10)
+            .unwrap();
+
+        // The sender underpays
+        let mut underpaid = sender_half.clone();
+        if let RbPaymentState::SenderHalfBuilt { send_tx, .. } = &mut underpaid {
+            send_tx.outputs[0].value = Asset::token_u64(24);
+        }
+        // The receiver returns the wrong receipt
+        let mut wrong_receipt = sender_half
+            .build_receiver_half(receiver_ins.clone(), Vec::new(), 11)
+            .unwrap();
+        if let RbPaymentState::ReceiverHalfBuilt { receive_tx, .. } = &mut wrong_receipt {
+            receive_tx.outputs[0].value = Asset::receipt(1, Some("other".to_owned()), None);
+        }
+
+        assert!(matches!(
+            underpaid.build_receiver_half(receiver_ins.clone(), Vec::new(), 11),
+            Err(RbPaymentError::UnmetExpectation(e)) if e.asset == Asset::token_u64(25)
+        ));
+        assert_eq!(
+            sender_half.build_receiver_half(create_tx_ins("other_tx"), Vec::new(), 11),
+            Err(RbPaymentError::WrongInputs)
+        );
+        assert!(matches!(
+            wrong_receipt.match_halves(12),
+            Err(RbPaymentError::UnmetExpectation(_))
+        ));
+        assert_eq!(
+            sender_half.build_receiver_half(receiver_ins, Vec::new(), 100),
+            Err(RbPaymentError::Expired)
+        );
+    }
+
+    #[test]
+    /// Checks that an expired payment refunds the halves built so far
+    fn test_rb_payment_expiry_refund() {
+        let sender_ins = create_tx_ins("sender_tx");
+        let receiver_ins = create_tx_ins("receiver_tx");
+        let request = RbPaymentRequest {
+            receiver_from: construct_tx_ins_address(&receiver_ins),
+            receiver_address: construct_address(&sign::gen_keypair().0),
+            amount: TokenAmount(25),
+            drs_tx_hash: Some("drs_tx_hash".to_owned()),
+            expiry_height: Some(100),
+        };
+        let sender_half = RbPaymentState::request(request)
+            .unwrap()
+            .build_sender_half(sender_ins.clone(), Vec::new(), "sender".to_owned(), 10)
+            .unwrap();
+
+        let expired = sender_half.expire(100);
+        let (send_refund, receive_refund) = expired.refund_txs(100);
+
+        assert_eq!(sender_half.expire(99), sender_half);
+        assert!(matches!(
+            &expired,
+            RbPaymentState::Expired {
+                send_tx: Some(_),
+                receive_tx: None,
+                ..
+            }
+        ));
+        let send_refund = send_refund.unwrap();
+        let sender_address = match &sender_ins[0].script_signature.stack[5] {
+            StackEntry::PubKeyHash(hash) => Some(hex::encode(hash)),
+            _ => None,
+        };
+        assert_eq!(send_refund.inputs, sender_ins);
+        assert!(send_refund
+            .outputs
+            .iter()
+            .all(|tx_out| tx_out.script_public_key == sender_address));
+        assert!(sender_address.is_some());
+        assert_eq!(receive_refund, None);
+        assert_eq!(sender_half.refund_txs(100), (None, None));
+    }
+}
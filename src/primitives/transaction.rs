@@ -2,15 +2,18 @@
 use crate::constants::*;
 use crate::crypto::sign_ed25519::{PublicKey, Signature};
 use crate::primitives::{
-    asset::{Asset, ReceiptAsset, TokenAmount},
-    druid::{DdeValues, DruidExpectation},
+    asset::{Asset, AssetBundle, IssuedAsset, ItemAsset, ReceiptAsset, TokenAmount},
+    druid::{DdeValues, DruidExpectation, LegacyDdeValues},
 };
 use crate::script::lang::Script;
 use crate::script::{OpCodes, StackEntry};
 use crate::utils::is_valid_amount;
 use bincode::serialize;
 use bytes::Bytes;
-use serde::{Deserialize, Serialize};
+use serde::de::{self, value::MapAccessDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeStruct};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DrsTxHashSpec {
@@ -107,6 +110,10 @@ impl TxIn {
 /// An output of a transaction. It contains the public key that the next input
 /// must be able to sign with to claim it. It also contains the block hash for the
 /// potential DRS if this is a data asset transaction
+///
+/// The script version whose rule set applies when a P2SH output is spent is part of
+/// its address, so outputs serialize as they did before script versions existed.
+/// P2PKH outputs have no script version: their fixed script always runs under version 0
 #[derive(Default, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TxOut {
     pub value: Asset,
@@ -140,11 +147,45 @@ impl TxOut {
         }
     }
 
+    /// Creates a new TxOut instance for an `Issued` asset
+    ///
+    /// **NOTE:** Only create transactions may have `Issued` assets that have a `None` `genesis_hash`
+    pub fn new_issued_amount(to_address: String, issued: IssuedAsset) -> TxOut {
+        TxOut {
+            value: Asset::Issued(issued),
+            script_public_key: Some(to_address),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new TxOut instance for an `Item` asset
+    ///
+    /// **NOTE:** Only create transactions may have `Item` assets that have a `None` `collection_hash`
+    pub fn new_item(to_address: String, item: ItemAsset) -> TxOut {
+        TxOut {
+            value: Asset::Item(item),
+            script_public_key: Some(to_address),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new TxOut instance for a `Bundle` of tokens and receipts
+    pub fn new_bundle(to_address: String, bundle: AssetBundle) -> TxOut {
+        TxOut {
+            value: Asset::Bundle(bundle),
+            script_public_key: Some(to_address),
+            ..Default::default()
+        }
+    }
+
     //TODO: Add handling for `Data' asset variant
     pub fn new_asset(to_address: String, asset: Asset) -> TxOut {
         match asset {
             Asset::Token(amount) => TxOut::new_token_amount(to_address, amount),
             Asset::Receipt(receipt) => TxOut::new_receipt_amount(to_address, receipt),
+            Asset::Issued(issued) => TxOut::new_issued_amount(to_address, issued),
+            Asset::Item(item) => TxOut::new_item(to_address, item),
+            Asset::Bundle(bundle) => TxOut::new_bundle(to_address, bundle),
             _ => panic!("Cannot create TxOut for asset of type {:?}", asset),
         }
     }
@@ -162,7 +203,10 @@ impl TxOut {
 
 /// The basic transaction that is broadcasted on the network and contained in
 /// blocks. A transaction can contain multiple inputs and outputs.
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+///
+/// The layout of `druid_info` depends on `version`, so that transactions up to
+/// `NETWORK_VERSION_LEGACY_DDE` keep their serialization and hash
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Transaction {
     pub inputs: Vec<TxIn>,
     pub outputs: Vec<TxOut>,
@@ -170,6 +214,85 @@ pub struct Transaction {
     pub druid_info: Option<DdeValues>,
 }
 
+/// Fields of a `Transaction` read from self-describing formats, where fields missing
+/// from legacy DDE values are filled in by default
+#[derive(Deserialize)]
+struct TransactionFields {
+    inputs: Vec<TxIn>,
+    outputs: Vec<TxOut>,
+    version: usize,
+    druid_info: Option<DdeValues>,
+}
+
+const TRANSACTION_FIELDS: &[&str] = &["inputs", "outputs", "version", "druid_info"];
+
+impl Serialize for Transaction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Transaction", TRANSACTION_FIELDS.len())?;
+        state.serialize_field("inputs", &self.inputs)?;
+        state.serialize_field("outputs", &self.outputs)?;
+        state.serialize_field("version", &self.version)?;
+        if self.version <= NETWORK_VERSION_LEGACY_DDE {
+            let druid_info = match &self.druid_info {
+                Some(druid_info) => Some(druid_info.to_legacy().ok_or_else(|| {
+                    ser::Error::custom("DDE values are not encodable in the transaction version")
+                })?),
+                None => None,
+            };
+            state.serialize_field("druid_info", &druid_info)?;
+        } else {
+            state.serialize_field("druid_info", &self.druid_info)?;
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Transaction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Transaction", TRANSACTION_FIELDS, TransactionVisitor)
+    }
+}
+
+struct TransactionVisitor;
+
+impl<'de> Visitor<'de> for TransactionVisitor {
+    type Value = Transaction;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "struct Transaction")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Transaction, A::Error> {
+        let missing = |index| de::Error::invalid_length(index, &"struct Transaction");
+        let inputs = seq.next_element()?.ok_or_else(|| missing(0))?;
+        let outputs = seq.next_element()?.ok_or_else(|| missing(1))?;
+        let version: usize = seq.next_element()?.ok_or_else(|| missing(2))?;
+        let druid_info = if version <= NETWORK_VERSION_LEGACY_DDE {
+            let legacy: Option<LegacyDdeValues> = seq.next_element()?.ok_or_else(|| missing(3))?;
+            legacy.map(DdeValues::from)
+        } else {
+            seq.next_element()?.ok_or_else(|| missing(3))?
+        };
+
+        Ok(Transaction {
+            inputs,
+            outputs,
+            version,
+            druid_info,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Transaction, A::Error> {
+        let fields = TransactionFields::deserialize(MapAccessDeserializer::new(map))?;
+        Ok(Transaction {
+            inputs: fields.inputs,
+            outputs: fields.outputs,
+            version: fields.version,
+            druid_info: fields.druid_info,
+        })
+    }
+}
+
 impl Default for Transaction {
     fn default() -> Self {
         Self::new()
@@ -197,7 +320,7 @@ impl Transaction {
     }
 
     /// Gets the create asset assigned to this transaction, if it exists
-    fn get_create_asset(&self) -> Option<&Asset> {
+    pub fn get_create_asset(&self) -> Option<&Asset> {
         let is_create = self.inputs.len() == 1
             && self.inputs[0].previous_out.is_none()
             && self.outputs.len() == 1;
@@ -219,6 +342,20 @@ impl Transaction {
             .unwrap_or_default()
     }
 
+    /// Returns whether current transaction re-issues an existing issued token
+    pub fn is_reissuance_tx(&self) -> bool {
+        self.get_create_asset()
+            .map(|a| a.get_genesis_hash().is_some())
+            .unwrap_or_default()
+    }
+
+    /// Returns whether current transaction mints an item into an existing collection
+    pub fn is_item_mint_tx(&self) -> bool {
+        self.get_create_asset()
+            .map(|a| a.get_collection_hash().is_some())
+            .unwrap_or_default()
+    }
+
     /// Returns whether current transaction is a P2SH tx
     pub fn is_p2sh_tx(&self) -> bool {
         if self.outputs.len() != 1 {
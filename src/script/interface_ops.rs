@@ -561,7 +561,8 @@ pub fn op_ifdup(stack: &mut Stack) -> bool {
     trace(op, desc);
     match stack.last() {
         Some(x) => {
-            if x != StackEntry::Num(ZERO) {
+            if *x != StackEntry::Num(ZERO) {
+                let x = x.clone();
                 stack.push(x);
             }
         }
@@ -617,7 +618,10 @@ pub fn op_dup(stack: &mut Stack) -> bool {
     let (op, desc) = (OPDUP, OPDUP_DESC);
     trace(op, desc);
     match stack.last() {
-        Some(x) => stack.push(x),
+        Some(x) => {
+            let x = x.clone();
+            stack.push(x)
+        }
         _ => {
             error_num_items(op);
             return false;
@@ -720,8 +724,7 @@ pub fn op_roll(stack: &mut Stack) -> bool {
         error_item_index(op);
         return false;
     }
-    let x = stack.main_stack[len - ONE - n].clone();
-    stack.main_stack.remove(len - ONE - n);
+    let x = stack.main_stack.remove(len - ONE - n);
     stack.push(x)
 }
 
@@ -790,6 +793,9 @@ pub fn op_tuck(stack: &mut Stack) -> bool {
 ///
 /// Example: OP_CAT([s1, s2]) -> [s1s2]
 ///
+/// Info: From SCRIPT_VERSION_V2 items are concatenated as raw bytes. Older scripts
+///       concatenate the strings the items are held as
+///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
@@ -797,7 +803,8 @@ pub fn op_cat(stack: &mut Stack) -> bool {
     let (op, desc) = (OPCAT, OPCAT_DESC);
     trace(op, desc);
     let s2 = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -808,7 +815,8 @@ pub fn op_cat(stack: &mut Stack) -> bool {
         }
     };
     let s1 = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -818,18 +826,21 @@ pub fn op_cat(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if s1.len() + s2.len() > MAX_SCRIPT_ITEM_SIZE as usize {
+    if s1.len() + s2.len() > stack.limits.max_script_item_size {
         error_item_size(op);
         return false;
     }
-    let cat = [s1, s2].join("");
-    stack.push(StackEntry::Bytes(cat))
+    let cat = [s1, s2].concat();
+    push_content(stack, op, cat)
 }
 
 /// OP_SUBSTR: Extracts a substring from the third-to-top item on the stack
 ///
 /// Example: OP_SUBSTR([s, n1, n2]) -> [s[n1..n1+n2-1]]
 ///
+/// Info: From SCRIPT_VERSION_V2 indices are in bytes. Older scripts index the
+///       strings the items are held as
+///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
@@ -859,7 +870,8 @@ pub fn op_substr(stack: &mut Stack) -> bool {
         }
     };
     let s = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -881,8 +893,8 @@ pub fn op_substr(stack: &mut Stack) -> bool {
         error_item_index(op);
         return false;
     }
-    let substr = s[n1..n1 + n2].to_string();
-    stack.push(StackEntry::Bytes(substr))
+    let substr = s[n1..n1 + n2].to_vec();
+    push_content(stack, op, substr)
 }
 
 /// OP_LEFT: Extracts a left substring from the second-to-top item on the stack
@@ -908,7 +920,8 @@ pub fn op_left(stack: &mut Stack) -> bool {
         }
     };
     let s = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -919,10 +932,10 @@ pub fn op_left(stack: &mut Stack) -> bool {
         }
     };
     if n >= s.len() {
-        stack.push(StackEntry::Bytes(s))
+        push_content(stack, op, s)
     } else {
-        let left = s[..n].to_string();
-        stack.push(StackEntry::Bytes(left))
+        let left = s[..n].to_vec();
+        push_content(stack, op, left)
     }
 }
 
@@ -949,7 +962,8 @@ pub fn op_right(stack: &mut Stack) -> bool {
         }
     };
     let s = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -960,10 +974,10 @@ pub fn op_right(stack: &mut Stack) -> bool {
         }
     };
     if n >= s.len() {
-        stack.push(StackEntry::Bytes("".to_string()))
+        push_content(stack, op, Vec::new())
     } else {
-        let right = s[n..].to_string();
-        stack.push(StackEntry::Bytes(right))
+        let right = s[n..].to_vec();
+        push_content(stack, op, right)
     }
 }
 
@@ -971,14 +985,19 @@ pub fn op_right(stack: &mut Stack) -> bool {
 ///
 /// Example: OP_SIZE([s]) -> [s, len(s)]
 ///
+/// Info: From SCRIPT_VERSION_V2 this is the number of raw bytes. Older scripts
+///       measure the string the item is held as
+///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
 pub fn op_size(stack: &mut Stack) -> bool {
     let (op, desc) = (OPSIZE, OPSIZE_DESC);
     trace(op, desc);
-    let s = match stack.last() {
-        Some(StackEntry::Bytes(s)) => s,
+    let len = match stack.last() {
+        Some(entry @ (StackEntry::Bytes(_) | StackEntry::LegacyBytes(_))) => {
+            entry.size(stack.has_byte_items())
+        }
         Some(_) => {
             error_item_type(op);
             return false;
@@ -988,7 +1007,7 @@ pub fn op_size(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    stack.push(StackEntry::Num(s.len()))
+    stack.push(StackEntry::Num(len))
 }
 
 /*---- BITWISE LOGIC OPS ----*/
@@ -2136,6 +2155,10 @@ pub fn op_within(stack: &mut Stack) -> bool {
 ///
 /// Example: OP_SHA3([x]) -> [SHA3-256(x)]
 ///
+/// Info: From SCRIPT_VERSION_V2 the raw bytes of items are hashed. Older scripts
+///       hash the string an item is held as and push the hex string of the hash,
+///       which is held as the same `Bytes` item as the raw hash
+///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
@@ -2145,7 +2168,8 @@ pub fn op_sha3(stack: &mut Stack) -> bool {
     let data = match stack.pop() {
         Some(StackEntry::Signature(sig)) => sig.as_ref().to_owned(),
         Some(StackEntry::PubKey(pk)) => pk.as_ref().to_owned(),
-        Some(StackEntry::PubKeyHash(s)) | Some(StackEntry::Bytes(s)) => s.as_bytes().to_owned(),
+        Some(StackEntry::PubKeyHash(s)) | Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyPubKeyHash(s)) | Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -2155,7 +2179,7 @@ pub fn op_sha3(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    let hash = hex::encode(sha3_256::digest(&data));
+    let hash = sha3_256::digest(&data).to_vec();
     stack.push(StackEntry::Bytes(hash))
 }
 
@@ -2181,7 +2205,7 @@ pub fn op_hash256(stack: &mut Stack) -> bool {
         }
     };
     let addr = construct_address(&pk);
-    stack.push(StackEntry::PubKeyHash(addr))
+    stack.push(StackEntry::pub_key_hash_from_text(addr))
 }
 
 /// OP_HASH256_V0: Creates v0 address from public key and pushes it onto the stack
@@ -2210,7 +2234,7 @@ pub fn op_hash256_v0(stack: &mut Stack) -> bool {
         }
     };
     let addr_v0 = construct_address_v0(&pk);
-    stack.push(StackEntry::PubKeyHash(addr_v0))
+    stack.push(StackEntry::pub_key_hash_from_text(addr_v0))
 }
 
 /// OP_HASH256_TEMP: Creates temporary address from public key and pushes it onto the stack
@@ -2239,7 +2263,7 @@ pub fn op_hash256_temp(stack: &mut Stack) -> bool {
         }
     };
     let addr_temp = construct_address_temp(&pk);
-    stack.push(StackEntry::PubKeyHash(addr_temp))
+    stack.push(StackEntry::pub_key_hash_from_text(addr_temp))
 }
 
 /// OP_CHECKSIG: Pushes ONE onto the stack if the signature is valid, ZERO otherwise
@@ -2247,7 +2271,9 @@ pub fn op_hash256_temp(stack: &mut Stack) -> bool {
 /// Example: OP_CHECKSIG([msg, sig, pk]) -> [1] if Verify(sig, msg, pk) == 1
 ///          OP_CHECKSIG([msg, sig, pk]) -> [0] if Verify(sig, msg, pk) == 0
 ///
-/// Info: It allows signature verification on arbitrary messsages, not only transactions
+/// Info: It allows signature verification on arbitrary messsages, not only transactions.
+///       From SCRIPT_VERSION_V2 the signature is over the raw bytes of the message.
+///       Older scripts check it against the string the message is held as
 ///
 /// ### Arguments
 ///
@@ -2278,7 +2304,8 @@ pub fn op_checksig(stack: &mut Stack) -> bool {
         }
     };
     let msg = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -2288,7 +2315,7 @@ pub fn op_checksig(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if (!sign::verify_detached(&sig, msg.as_bytes(), &pk)) {
+    if (!sign::verify_detached(&sig, &msg, &pk)) {
         stack.push(StackEntry::Num(ZERO))
     } else {
         stack.push(StackEntry::Num(ONE))
@@ -2329,7 +2356,8 @@ pub fn op_checksigverify(stack: &mut Stack) -> bool {
         }
     };
     let msg = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -2339,7 +2367,7 @@ pub fn op_checksigverify(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if (!sign::verify_detached(&sig, msg.as_bytes(), &pk)) {
+    if (!sign::verify_detached(&sig, &msg, &pk)) {
         error_invalid_signature(op);
         return false;
     }
@@ -2351,7 +2379,8 @@ pub fn op_checksigverify(stack: &mut Stack) -> bool {
 /// Example: OP_CHECKMULTISIG([msg, sig1, sig2, m, pk1, pk2, pk3, n]) -> [1] if Verify(sig1, sig2, msg, pk1, pk2, pk3) == 1
 ///          OP_CHECKMULTISIG([msg, sig1, sig2, m, pk1, pk2, pk3, n]) -> [0] if Verify(sig1, sig2, msg, pk1, pk2, pk3) == 0
 ///
-/// Info: It allows multi-signature verification on arbitrary messsages, not only transactions
+/// Info: It allows multi-signature verification on arbitrary messsages, not only transactions.
+///       The message is read as by OP_CHECKSIG
 ///       Ordering of signatures and public keys is not relevant
 ///
 /// ### Arguments
@@ -2371,7 +2400,7 @@ pub fn op_checkmultisig(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if n > MAX_PUB_KEYS_PER_MULTISIG as usize {
+    if n > stack.limits.max_pub_keys_per_multisig {
         error_num_pubkeys(op);
         return false;
     }
@@ -2411,7 +2440,8 @@ pub fn op_checkmultisig(stack: &mut Stack) -> bool {
         return false;
     }
     let msg = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -2450,7 +2480,7 @@ pub fn op_checkmultisigverify(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if n > MAX_PUB_KEYS_PER_MULTISIG as usize {
+    if n > stack.limits.max_pub_keys_per_multisig {
         error_num_pubkeys(op);
         return false;
     }
@@ -2486,7 +2516,8 @@ pub fn op_checkmultisigverify(stack: &mut Stack) -> bool {
         }
     }
     let msg = match stack.pop() {
-        Some(StackEntry::Bytes(s)) => s,
+        Some(StackEntry::Bytes(s)) => stack.item_content(s),
+        Some(StackEntry::LegacyBytes(s)) => s.into_bytes(),
         Some(_) => {
             error_item_type(op);
             return false;
@@ -2503,6 +2534,98 @@ pub fn op_checkmultisigverify(stack: &mut Stack) -> bool {
     true
 }
 
+/*---- UPGRADABLE NOP OPS ----*/
+
+/// OP_NOP1-OP_NOP10: Reserved for a future upgrade; does nothing under the active rules
+///
+/// Example: OP_NOP1([x]) -> [x]
+///
+/// ### Arguments
+///
+/// * `stack`  - mutable reference to the stack
+/// * `nop`    - the upgradable NOP being executed
+pub fn op_nopx(stack: &mut Stack, nop: &OpCodes) -> bool {
+    let op = match nop {
+        OpCodes::OP_NOP1 => OPNOP1,
+        OpCodes::OP_NOP2 => OPNOP2,
+        OpCodes::OP_NOP3 => OPNOP3,
+        OpCodes::OP_NOP4 => OPNOP4,
+        OpCodes::OP_NOP5 => OPNOP5,
+        OpCodes::OP_NOP6 => OPNOP6,
+        OpCodes::OP_NOP7 => OPNOP7,
+        OpCodes::OP_NOP8 => OPNOP8,
+        OpCodes::OP_NOP9 => OPNOP9,
+        OpCodes::OP_NOP10 => OPNOP10,
+        _ => {
+            error_item_type(OPNOP);
+            return false;
+        }
+    };
+    trace(op, OPNOPX_DESC);
+    true
+}
+
+/// OP_CHECKSHA3VERIFY: Ends execution with an error if the top item is not the SHA3-256 hash of the second-to-top item
+///
+/// Example: OP_CHECKSHA3VERIFY([x, h]) -> [x, h] if SHA3-256(x) == h
+///          OP_CHECKSHA3VERIFY([x, h]) -> fail   if SHA3-256(x) != h
+///
+/// Info: Occupies the OP_NOP1 slot from SCRIPT_VERSION_V1 onwards. It leaves the stack
+///       untouched so that nodes still treating OP_NOP1 as a no-op reach the same result.
+///       The second-to-top item is hashed as by OP_SHA3
+///
+/// ### Arguments
+///
+/// * `stack`  - mutable reference to the stack
+pub fn op_checksha3verify(stack: &mut Stack) -> bool {
+    let (op, desc) = (OPCHECKSHA3VERIFY, OPCHECKSHA3VERIFY_DESC);
+    trace(op, desc);
+    let len = stack.main_stack.len();
+    if len < TWO {
+        error_num_items(op);
+        return false;
+    }
+    let hash = match &stack.main_stack[len - ONE] {
+        h @ (StackEntry::Bytes(_) | StackEntry::LegacyBytes(_)) => h,
+        _ => {
+            error_item_type(op);
+            return false;
+        }
+    };
+    let data = match &stack.main_stack[len - TWO] {
+        StackEntry::Signature(sig) => sig.as_ref().to_owned(),
+        StackEntry::PubKey(pk) => pk.as_ref().to_owned(),
+        StackEntry::PubKeyHash(s) | StackEntry::Bytes(s) => stack.item_content(s.clone()),
+        StackEntry::LegacyPubKeyHash(s) | StackEntry::LegacyBytes(s) => s.as_bytes().to_owned(),
+        _ => {
+            error_item_type(op);
+            return false;
+        }
+    };
+    if &StackEntry::Bytes(sha3_256::digest(&data).to_vec()) != hash {
+        error_hash_mismatch(op);
+        return false;
+    }
+    true
+}
+
+/// Pushes content produced by a splice opcode onto the stack as a `Bytes` item
+///
+/// ### Arguments
+///
+/// * `stack`   - mutable reference to the stack
+/// * `op`      - opcode pushing the content
+/// * `content` - content of the item, see `Stack::item_content`
+fn push_content(stack: &mut Stack, op: &str, content: Vec<u8>) -> bool {
+    match stack.content_item(content) {
+        Some(entry) => stack.push(entry),
+        None => {
+            error_item_index(op);
+            false
+        }
+    }
+}
+
 /// Verifies an m-of-n multi-signature
 ///
 /// ### Arguments
@@ -2510,11 +2633,11 @@ pub fn op_checkmultisigverify(stack: &mut Stack) -> bool {
 /// * `sigs` - signatures to verify
 /// * `msg`  - data to verify against
 /// * `pks`  - public keys to match against
-fn verify_multisig(sigs: &[Signature], msg: &String, pks: &mut Vec<PublicKey>) -> bool {
+fn verify_multisig(sigs: &[Signature], msg: &[u8], pks: &mut Vec<PublicKey>) -> bool {
     let mut num_valid_sigs = ZERO;
     for (index_sig, sig) in sigs.iter().enumerate() {
         for (index_pk, pk) in pks.iter().enumerate() {
-            if sign::verify_detached(sig, msg.as_bytes(), pk) {
+            if sign::verify_detached(sig, msg, pk) {
                 num_valid_sigs += ONE;
                 pks.remove(index_pk);
                 break;
@@ -5,7 +5,7 @@ use crate::crypto::sign_ed25519::{
     PublicKey, Signature, ED25519_PUBLIC_KEY_LEN, ED25519_SIGNATURE_LEN,
 };
 use crate::script::interface_ops::*;
-use crate::script::{OpCodes, StackEntry};
+use crate::script::{has_byte_items, OpCodes, StackEntry};
 use crate::utils::error_utils::*;
 use crate::utils::transaction_utils::{construct_address, construct_address_for};
 use bincode::serialize;
@@ -14,11 +14,43 @@ use hex::encode;
 use serde::{Deserialize, Serialize};
 use tracing::{error, warn};
 
+/// Resource limits applied while validating and executing a script.
+/// The default values are the consensus limits
+#[derive(Clone, Copy, Debug, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ScriptLimits {
+    pub max_script_size: usize,
+    pub max_ops_per_script: usize,
+    pub max_stack_size: usize,
+    pub max_script_item_size: usize,
+    pub max_pub_keys_per_multisig: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self::consensus()
+    }
+}
+
+impl ScriptLimits {
+    /// Creates the limits enforced by consensus
+    pub fn consensus() -> Self {
+        Self {
+            max_script_size: MAX_SCRIPT_SIZE as usize,
+            max_ops_per_script: MAX_OPS_PER_SCRIPT as usize,
+            max_stack_size: MAX_STACK_SIZE as usize,
+            max_script_item_size: MAX_SCRIPT_ITEM_SIZE as usize,
+            max_pub_keys_per_multisig: MAX_PUB_KEYS_PER_MULTISIG as usize,
+        }
+    }
+}
+
 /// Stack for script execution
 #[derive(Clone, Debug, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Stack {
     pub main_stack: Vec<StackEntry>,
     pub alt_stack: Vec<StackEntry>,
+    pub limits: ScriptLimits,
+    pub script_version: u64,
 }
 
 impl Default for Stack {
@@ -29,16 +61,35 @@ impl Default for Stack {
 
 impl Stack {
     /// Creates a new stack
+    ///
+    /// NOTE: Buffers grow on demand rather than being sized for the stack size limit
     pub fn new() -> Self {
+        Self::with_limits(Default::default())
+    }
+
+    /// Creates a new stack enforcing the given limits
+    ///
+    /// ### Arguments
+    ///
+    /// * `limits`  - Limits to enforce during execution
+    pub fn with_limits(limits: ScriptLimits) -> Self {
         Self {
-            main_stack: Vec::with_capacity(MAX_STACK_SIZE as usize),
-            alt_stack: Vec::with_capacity(MAX_STACK_SIZE as usize),
+            main_stack: Vec::new(),
+            alt_stack: Vec::new(),
+            limits,
+            script_version: SCRIPT_VERSION_V0,
         }
     }
 
+    /// Empties the stack while keeping its allocated buffers for reuse
+    pub fn clear(&mut self) {
+        self.main_stack.clear();
+        self.alt_stack.clear();
+    }
+
     /// Checks if the stack is valid
     pub fn is_valid(&self) -> bool {
-        if self.main_stack.len() + self.alt_stack.len() > MAX_STACK_SIZE as usize {
+        if self.main_stack.len() + self.alt_stack.len() > self.limits.max_stack_size {
             error_max_stack_size();
             return false;
         }
@@ -50,24 +101,27 @@ impl Stack {
         self.main_stack.pop()
     }
 
-    /// Returns the top item on the stack
-    pub fn last(&self) -> Option<StackEntry> {
-        self.main_stack.last().cloned()
+    /// Returns a reference to the top item on the stack
+    pub fn last(&self) -> Option<&StackEntry> {
+        self.main_stack.last()
     }
 
     /// Checks if the last item on the stack is not zero
     pub fn is_last_non_zero(&self) -> bool {
-        self.last() != Some(StackEntry::Num(ZERO))
+        self.last() != Some(&StackEntry::Num(ZERO))
     }
 
     /// Pushes a new entry onto the stack
     pub fn push(&mut self, stack_entry: StackEntry) -> bool {
-        match stack_entry.clone() {
+        match &stack_entry {
             StackEntry::Op(_) => {
                 return false;
             }
-            StackEntry::PubKeyHash(s) | StackEntry::Bytes(s) => {
-                if s.len() > MAX_SCRIPT_ITEM_SIZE as usize {
+            StackEntry::PubKeyHash(_)
+            | StackEntry::Bytes(_)
+            | StackEntry::LegacyPubKeyHash(_)
+            | StackEntry::LegacyBytes(_) => {
+                if stack_entry.size(self.has_byte_items()) > self.limits.max_script_item_size {
                     return false;
                 }
             }
@@ -76,6 +130,40 @@ impl Stack {
         self.main_stack.push(stack_entry);
         true
     }
+
+    /// Checks if the script being executed operates on the raw bytes of items
+    pub fn has_byte_items(&self) -> bool {
+        has_byte_items(self.script_version)
+    }
+
+    /// Returns the content opcodes operate on for the raw bytes of a `PubKeyHash`
+    /// or `Bytes` item: the bytes themselves in scripts with byte items, or else
+    /// the hex string the item is held as
+    ///
+    /// ### Arguments
+    ///
+    /// * `bytes`   - Raw bytes of the item
+    pub fn item_content(&self, bytes: Vec<u8>) -> Vec<u8> {
+        match self.has_byte_items() {
+            true => bytes,
+            false => hex::encode(bytes).into_bytes(),
+        }
+    }
+
+    /// Creates the `Bytes` item holding content produced by an opcode, or `None`
+    /// if the script operates on strings and the content is not a valid string
+    ///
+    /// ### Arguments
+    ///
+    /// * `content` - Content of the item
+    pub fn content_item(&self, content: Vec<u8>) -> Option<StackEntry> {
+        match self.has_byte_items() {
+            true => Some(StackEntry::Bytes(content)),
+            false => String::from_utf8(content)
+                .ok()
+                .map(StackEntry::bytes_from_text),
+        }
+    }
 }
 
 impl From<Vec<StackEntry>> for Stack {
@@ -83,7 +171,9 @@ impl From<Vec<StackEntry>> for Stack {
     fn from(stack: Vec<StackEntry>) -> Self {
         Stack {
             main_stack: stack,
-            alt_stack: Vec::with_capacity(MAX_STACK_SIZE as usize),
+            alt_stack: Vec::new(),
+            limits: Default::default(),
+            script_version: SCRIPT_VERSION_V0,
         }
     }
 }
@@ -172,43 +262,88 @@ impl Script {
         Self { stack: Vec::new() }
     }
 
-    /// Checks if a script is valid
+    /// Checks if a script is valid under the consensus limits and the original rule set
     pub fn is_valid(&self) -> bool {
+        self.is_valid_for(&Default::default(), SCRIPT_VERSION_V0)
+    }
+
+    /// Checks if a script is valid under the given limits and the rule set of a
+    /// given script version. Items held as strings by legacy scripts are only
+    /// valid in scripts that do not operate on raw bytes
+    ///
+    /// ### Arguments
+    ///
+    /// * `limits`          - Limits to check the script against
+    /// * `script_version`  - Script version of the output being spent
+    pub fn is_valid_for(&self, limits: &ScriptLimits, script_version: u64) -> bool {
+        let byte_items = has_byte_items(script_version);
         let mut len = ZERO; // script length in bytes
         let mut ops_count = ZERO; // number of opcodes in script
         for entry in &self.stack {
-            match entry {
-                StackEntry::Op(_) => {
-                    len += ONE;
-                    ops_count += ONE;
-                }
-                StackEntry::Signature(_) => len += ED25519_SIGNATURE_LEN,
-                StackEntry::PubKey(_) => len += ED25519_PUBLIC_KEY_LEN,
-                StackEntry::PubKeyHash(s) | StackEntry::Bytes(s) => len += s.len(),
-                StackEntry::Num(_) => len += usize::BITS as usize / EIGHT,
-            };
+            if byte_items && entry.is_legacy() {
+                error_legacy_item();
+                return false;
+            }
+            len += entry.size(byte_items);
+            if let StackEntry::Op(_) = entry {
+                ops_count += ONE;
+            }
         }
-        if len > MAX_SCRIPT_SIZE as usize {
+        if len > limits.max_script_size {
             error_max_script_size();
             return false;
         }
-        if ops_count > MAX_OPS_PER_SCRIPT as usize {
+        if ops_count > limits.max_ops_per_script {
             error_max_ops_script();
             return false;
         }
         true
     }
 
-    /// Interprets and executes a script
+    /// Interprets and executes a script under the original rule set
     pub fn interpret(&self) -> bool {
-        if !self.is_valid() {
+        self.interpret_for(SCRIPT_VERSION_V0)
+    }
+
+    /// Interprets and executes a script under the rule set of a given script version.
+    /// Upgradable NOPs that are not yet active for the version are executed as no-ops
+    ///
+    /// ### Arguments
+    ///
+    /// * `script_version`  - Script version of the output being spent
+    pub fn interpret_for(&self, script_version: u64) -> bool {
+        self.interpret_with_stack(&mut Stack::new(), script_version)
+    }
+
+    /// Interprets and executes a script under the given limits
+    ///
+    /// ### Arguments
+    ///
+    /// * `limits`          - Limits to enforce during validation and execution
+    /// * `script_version`  - Script version of the output being spent
+    pub fn interpret_with_limits(&self, limits: ScriptLimits, script_version: u64) -> bool {
+        self.interpret_with_stack(&mut Stack::with_limits(limits), script_version)
+    }
+
+    /// Interprets and executes a script on a caller-owned stack, so that the
+    /// stack buffers can be reused when validating many scripts in a row.
+    /// The stack is cleared and set to the script version before execution,
+    /// and its limits are enforced
+    ///
+    /// ### Arguments
+    ///
+    /// * `stack`           - Stack to execute the script on
+    /// * `script_version`  - Script version of the output being spent
+    pub fn interpret_with_stack(&self, stack: &mut Stack, script_version: u64) -> bool {
+        stack.clear();
+        stack.script_version = script_version;
+        if !self.is_valid_for(&stack.limits, script_version) {
             return false;
         }
-        let mut stack = Stack::new();
         let mut cond_stack = ConditionStack::new();
         let mut test_for_return = true;
         for stack_entry in &self.stack {
-            match stack_entry.clone() {
+            match stack_entry {
                 /*---- OPCODE ----*/
                 StackEntry::Op(op) => {
                     if !cond_stack.all_true() && !op.is_conditional() {
@@ -217,113 +352,117 @@ impl Script {
                     }
                     match op {
                         // constants
-                        OpCodes::OP_0 => test_for_return &= op_0(&mut stack),
-                        OpCodes::OP_1 => test_for_return &= op_1(&mut stack),
-                        OpCodes::OP_2 => test_for_return &= op_2(&mut stack),
-                        OpCodes::OP_3 => test_for_return &= op_3(&mut stack),
-                        OpCodes::OP_4 => test_for_return &= op_4(&mut stack),
-                        OpCodes::OP_5 => test_for_return &= op_5(&mut stack),
-                        OpCodes::OP_6 => test_for_return &= op_6(&mut stack),
-                        OpCodes::OP_7 => test_for_return &= op_7(&mut stack),
-                        OpCodes::OP_8 => test_for_return &= op_8(&mut stack),
-                        OpCodes::OP_9 => test_for_return &= op_9(&mut stack),
-                        OpCodes::OP_10 => test_for_return &= op_10(&mut stack),
-                        OpCodes::OP_11 => test_for_return &= op_11(&mut stack),
-                        OpCodes::OP_12 => test_for_return &= op_12(&mut stack),
-                        OpCodes::OP_13 => test_for_return &= op_13(&mut stack),
-                        OpCodes::OP_14 => test_for_return &= op_14(&mut stack),
-                        OpCodes::OP_15 => test_for_return &= op_15(&mut stack),
-                        OpCodes::OP_16 => test_for_return &= op_16(&mut stack),
+                        OpCodes::OP_0 => test_for_return &= op_0(stack),
+                        OpCodes::OP_1 => test_for_return &= op_1(stack),
+                        OpCodes::OP_2 => test_for_return &= op_2(stack),
+                        OpCodes::OP_3 => test_for_return &= op_3(stack),
+                        OpCodes::OP_4 => test_for_return &= op_4(stack),
+                        OpCodes::OP_5 => test_for_return &= op_5(stack),
+                        OpCodes::OP_6 => test_for_return &= op_6(stack),
+                        OpCodes::OP_7 => test_for_return &= op_7(stack),
+                        OpCodes::OP_8 => test_for_return &= op_8(stack),
+                        OpCodes::OP_9 => test_for_return &= op_9(stack),
+                        OpCodes::OP_10 => test_for_return &= op_10(stack),
+                        OpCodes::OP_11 => test_for_return &= op_11(stack),
+                        OpCodes::OP_12 => test_for_return &= op_12(stack),
+                        OpCodes::OP_13 => test_for_return &= op_13(stack),
+                        OpCodes::OP_14 => test_for_return &= op_14(stack),
+                        OpCodes::OP_15 => test_for_return &= op_15(stack),
+                        OpCodes::OP_16 => test_for_return &= op_16(stack),
                         // flow control
-                        OpCodes::OP_NOP => test_for_return &= op_nop(&mut stack),
-                        OpCodes::OP_IF => test_for_return &= op_if(&mut stack, &mut cond_stack),
-                        OpCodes::OP_NOTIF => {
-                            test_for_return &= op_notif(&mut stack, &mut cond_stack)
-                        }
+                        OpCodes::OP_NOP => test_for_return &= op_nop(stack),
+                        OpCodes::OP_IF => test_for_return &= op_if(stack, &mut cond_stack),
+                        OpCodes::OP_NOTIF => test_for_return &= op_notif(stack, &mut cond_stack),
                         OpCodes::OP_ELSE => test_for_return &= op_else(&mut cond_stack),
                         OpCodes::OP_ENDIF => test_for_return &= op_endif(&mut cond_stack),
-                        OpCodes::OP_VERIFY => test_for_return &= op_verify(&mut stack),
-                        OpCodes::OP_BURN => test_for_return &= op_burn(&mut stack),
+                        OpCodes::OP_VERIFY => test_for_return &= op_verify(stack),
+                        OpCodes::OP_BURN => test_for_return &= op_burn(stack),
                         // stack
-                        OpCodes::OP_TOALTSTACK => test_for_return &= op_toaltstack(&mut stack),
-                        OpCodes::OP_FROMALTSTACK => test_for_return &= op_fromaltstack(&mut stack),
-                        OpCodes::OP_2DROP => test_for_return &= op_2drop(&mut stack),
-                        OpCodes::OP_2DUP => test_for_return &= op_2dup(&mut stack),
-                        OpCodes::OP_3DUP => test_for_return &= op_3dup(&mut stack),
-                        OpCodes::OP_2OVER => test_for_return &= op_2over(&mut stack),
-                        OpCodes::OP_2ROT => test_for_return &= op_2rot(&mut stack),
-                        OpCodes::OP_2SWAP => test_for_return &= op_2swap(&mut stack),
-                        OpCodes::OP_IFDUP => test_for_return &= op_ifdup(&mut stack),
-                        OpCodes::OP_DEPTH => test_for_return &= op_depth(&mut stack),
-                        OpCodes::OP_DROP => test_for_return &= op_drop(&mut stack),
-                        OpCodes::OP_DUP => test_for_return &= op_dup(&mut stack),
-                        OpCodes::OP_NIP => test_for_return &= op_nip(&mut stack),
-                        OpCodes::OP_OVER => test_for_return &= op_over(&mut stack),
-                        OpCodes::OP_PICK => test_for_return &= op_pick(&mut stack),
-                        OpCodes::OP_ROLL => test_for_return &= op_roll(&mut stack),
-                        OpCodes::OP_ROT => test_for_return &= op_rot(&mut stack),
-                        OpCodes::OP_SWAP => test_for_return &= op_swap(&mut stack),
-                        OpCodes::OP_TUCK => test_for_return &= op_tuck(&mut stack),
+                        OpCodes::OP_TOALTSTACK => test_for_return &= op_toaltstack(stack),
+                        OpCodes::OP_FROMALTSTACK => test_for_return &= op_fromaltstack(stack),
+                        OpCodes::OP_2DROP => test_for_return &= op_2drop(stack),
+                        OpCodes::OP_2DUP => test_for_return &= op_2dup(stack),
+                        OpCodes::OP_3DUP => test_for_return &= op_3dup(stack),
+                        OpCodes::OP_2OVER => test_for_return &= op_2over(stack),
+                        OpCodes::OP_2ROT => test_for_return &= op_2rot(stack),
+                        OpCodes::OP_2SWAP => test_for_return &= op_2swap(stack),
+                        OpCodes::OP_IFDUP => test_for_return &= op_ifdup(stack),
+                        OpCodes::OP_DEPTH => test_for_return &= op_depth(stack),
+                        OpCodes::OP_DROP => test_for_return &= op_drop(stack),
+                        OpCodes::OP_DUP => test_for_return &= op_dup(stack),
+                        OpCodes::OP_NIP => test_for_return &= op_nip(stack),
+                        OpCodes::OP_OVER => test_for_return &= op_over(stack),
+                        OpCodes::OP_PICK => test_for_return &= op_pick(stack),
+                        OpCodes::OP_ROLL => test_for_return &= op_roll(stack),
+                        OpCodes::OP_ROT => test_for_return &= op_rot(stack),
+                        OpCodes::OP_SWAP => test_for_return &= op_swap(stack),
+                        OpCodes::OP_TUCK => test_for_return &= op_tuck(stack),
                         // splice
-                        OpCodes::OP_CAT => test_for_return &= op_cat(&mut stack),
-                        OpCodes::OP_SUBSTR => test_for_return &= op_substr(&mut stack),
-                        OpCodes::OP_LEFT => test_for_return &= op_left(&mut stack),
-                        OpCodes::OP_RIGHT => test_for_return &= op_right(&mut stack),
-                        OpCodes::OP_SIZE => test_for_return &= op_size(&mut stack),
+                        OpCodes::OP_CAT => test_for_return &= op_cat(stack),
+                        OpCodes::OP_SUBSTR => test_for_return &= op_substr(stack),
+                        OpCodes::OP_LEFT => test_for_return &= op_left(stack),
+                        OpCodes::OP_RIGHT => test_for_return &= op_right(stack),
+                        OpCodes::OP_SIZE => test_for_return &= op_size(stack),
                         // bitwise logic
-                        OpCodes::OP_INVERT => test_for_return &= op_invert(&mut stack),
-                        OpCodes::OP_AND => test_for_return &= op_and(&mut stack),
-                        OpCodes::OP_OR => test_for_return &= op_or(&mut stack),
-                        OpCodes::OP_XOR => test_for_return &= op_xor(&mut stack),
-                        OpCodes::OP_EQUAL => test_for_return &= op_equal(&mut stack),
-                        OpCodes::OP_EQUALVERIFY => test_for_return &= op_equalverify(&mut stack),
+                        OpCodes::OP_INVERT => test_for_return &= op_invert(stack),
+                        OpCodes::OP_AND => test_for_return &= op_and(stack),
+                        OpCodes::OP_OR => test_for_return &= op_or(stack),
+                        OpCodes::OP_XOR => test_for_return &= op_xor(stack),
+                        OpCodes::OP_EQUAL => test_for_return &= op_equal(stack),
+                        OpCodes::OP_EQUALVERIFY => test_for_return &= op_equalverify(stack),
                         // arithmetic
-                        OpCodes::OP_1ADD => test_for_return &= op_1add(&mut stack),
-                        OpCodes::OP_1SUB => test_for_return &= op_1sub(&mut stack),
-                        OpCodes::OP_2MUL => test_for_return &= op_2mul(&mut stack),
-                        OpCodes::OP_2DIV => test_for_return &= op_2div(&mut stack),
-                        OpCodes::OP_NOT => test_for_return &= op_not(&mut stack),
-                        OpCodes::OP_0NOTEQUAL => test_for_return &= op_0notequal(&mut stack),
-                        OpCodes::OP_ADD => test_for_return &= op_add(&mut stack),
-                        OpCodes::OP_SUB => test_for_return &= op_sub(&mut stack),
-                        OpCodes::OP_MUL => test_for_return &= op_mul(&mut stack),
-                        OpCodes::OP_DIV => test_for_return &= op_div(&mut stack),
-                        OpCodes::OP_MOD => test_for_return &= op_mod(&mut stack),
-                        OpCodes::OP_LSHIFT => test_for_return &= op_lshift(&mut stack),
-                        OpCodes::OP_RSHIFT => test_for_return &= op_rshift(&mut stack),
-                        OpCodes::OP_BOOLAND => test_for_return &= op_booland(&mut stack),
-                        OpCodes::OP_BOOLOR => test_for_return &= op_boolor(&mut stack),
-                        OpCodes::OP_NUMEQUAL => test_for_return &= op_numequal(&mut stack),
-                        OpCodes::OP_NUMEQUALVERIFY => {
-                            test_for_return &= op_numequalverify(&mut stack)
-                        }
-                        OpCodes::OP_NUMNOTEQUAL => test_for_return &= op_numnotequal(&mut stack),
-                        OpCodes::OP_LESSTHAN => test_for_return &= op_lessthan(&mut stack),
-                        OpCodes::OP_GREATERTHAN => test_for_return &= op_greaterthan(&mut stack),
-                        OpCodes::OP_LESSTHANOREQUAL => {
-                            test_for_return &= op_lessthanorequal(&mut stack)
-                        }
+                        OpCodes::OP_1ADD => test_for_return &= op_1add(stack),
+                        OpCodes::OP_1SUB => test_for_return &= op_1sub(stack),
+                        OpCodes::OP_2MUL => test_for_return &= op_2mul(stack),
+                        OpCodes::OP_2DIV => test_for_return &= op_2div(stack),
+                        OpCodes::OP_NOT => test_for_return &= op_not(stack),
+                        OpCodes::OP_0NOTEQUAL => test_for_return &= op_0notequal(stack),
+                        OpCodes::OP_ADD => test_for_return &= op_add(stack),
+                        OpCodes::OP_SUB => test_for_return &= op_sub(stack),
+                        OpCodes::OP_MUL => test_for_return &= op_mul(stack),
+                        OpCodes::OP_DIV => test_for_return &= op_div(stack),
+                        OpCodes::OP_MOD => test_for_return &= op_mod(stack),
+                        OpCodes::OP_LSHIFT => test_for_return &= op_lshift(stack),
+                        OpCodes::OP_RSHIFT => test_for_return &= op_rshift(stack),
+                        OpCodes::OP_BOOLAND => test_for_return &= op_booland(stack),
+                        OpCodes::OP_BOOLOR => test_for_return &= op_boolor(stack),
+                        OpCodes::OP_NUMEQUAL => test_for_return &= op_numequal(stack),
+                        OpCodes::OP_NUMEQUALVERIFY => test_for_return &= op_numequalverify(stack),
+                        OpCodes::OP_NUMNOTEQUAL => test_for_return &= op_numnotequal(stack),
+                        OpCodes::OP_LESSTHAN => test_for_return &= op_lessthan(stack),
+                        OpCodes::OP_GREATERTHAN => test_for_return &= op_greaterthan(stack),
+                        OpCodes::OP_LESSTHANOREQUAL => test_for_return &= op_lessthanorequal(stack),
                         OpCodes::OP_GREATERTHANOREQUAL => {
-                            test_for_return &= op_greaterthanorequal(&mut stack)
+                            test_for_return &= op_greaterthanorequal(stack)
                         }
-                        OpCodes::OP_MIN => test_for_return &= op_min(&mut stack),
-                        OpCodes::OP_MAX => test_for_return &= op_max(&mut stack),
-                        OpCodes::OP_WITHIN => test_for_return &= op_within(&mut stack),
+                        OpCodes::OP_MIN => test_for_return &= op_min(stack),
+                        OpCodes::OP_MAX => test_for_return &= op_max(stack),
+                        OpCodes::OP_WITHIN => test_for_return &= op_within(stack),
                         // crypto
-                        OpCodes::OP_SHA3 => test_for_return &= op_sha3(&mut stack),
-                        OpCodes::OP_HASH256 => test_for_return &= op_hash256(&mut stack),
-                        OpCodes::OP_HASH256_V0 => test_for_return &= op_hash256_v0(&mut stack),
-                        OpCodes::OP_HASH256_TEMP => test_for_return &= op_hash256_temp(&mut stack),
-                        OpCodes::OP_CHECKSIG => test_for_return &= op_checksig(&mut stack),
-                        OpCodes::OP_CHECKSIGVERIFY => {
-                            test_for_return &= op_checksigverify(&mut stack)
-                        }
-                        OpCodes::OP_CHECKMULTISIG => {
-                            test_for_return &= op_checkmultisig(&mut stack)
-                        }
+                        OpCodes::OP_SHA3 => test_for_return &= op_sha3(stack),
+                        OpCodes::OP_HASH256 => test_for_return &= op_hash256(stack),
+                        OpCodes::OP_HASH256_V0 => test_for_return &= op_hash256_v0(stack),
+                        OpCodes::OP_HASH256_TEMP => test_for_return &= op_hash256_temp(stack),
+                        OpCodes::OP_CHECKSIG => test_for_return &= op_checksig(stack),
+                        OpCodes::OP_CHECKSIGVERIFY => test_for_return &= op_checksigverify(stack),
+                        OpCodes::OP_CHECKMULTISIG => test_for_return &= op_checkmultisig(stack),
                         OpCodes::OP_CHECKMULTISIGVERIFY => {
-                            test_for_return &= op_checkmultisigverify(&mut stack)
+                            test_for_return &= op_checkmultisigverify(stack)
+                        }
+                        // upgradable nops
+                        nop if nop.is_inactive_nop(script_version) => {
+                            test_for_return &= op_nopx(stack, nop)
                         }
+                        OpCodes::OP_NOP1 => test_for_return &= op_checksha3verify(stack),
+                        OpCodes::OP_NOP2
+                        | OpCodes::OP_NOP3
+                        | OpCodes::OP_NOP4
+                        | OpCodes::OP_NOP5
+                        | OpCodes::OP_NOP6
+                        | OpCodes::OP_NOP7
+                        | OpCodes::OP_NOP8
+                        | OpCodes::OP_NOP9
+                        | OpCodes::OP_NOP10 => test_for_return &= op_nopx(stack, op),
                         // smart data
                         OpCodes::OP_CREATE => (),
                     }
@@ -333,7 +472,9 @@ impl Script {
                 | StackEntry::PubKey(_)
                 | StackEntry::PubKeyHash(_)
                 | StackEntry::Num(_)
-                | StackEntry::Bytes(_) => {
+                | StackEntry::Bytes(_)
+                | StackEntry::LegacyPubKeyHash(_)
+                | StackEntry::LegacyBytes(_) => {
                     if cond_stack.all_true() {
                         test_for_return &= stack.push(stack_entry.clone())
                     }
@@ -374,7 +515,7 @@ impl Script {
             StackEntry::Op(OpCodes::OP_CREATE),
             StackEntry::Num(block_number as usize),
             StackEntry::Op(OpCodes::OP_DROP),
-            StackEntry::Bytes(asset_hash),
+            StackEntry::bytes_from_text(asset_hash),
             StackEntry::Signature(signature),
             StackEntry::PubKey(pub_key),
             StackEntry::Op(OpCodes::OP_CHECKSIG),
@@ -401,12 +542,12 @@ impl Script {
             _ => OpCodes::OP_HASH256,
         };
         let stack = vec![
-            StackEntry::Bytes(check_data),
+            StackEntry::bytes_from_text(check_data),
             StackEntry::Signature(signature),
             StackEntry::PubKey(pub_key),
             StackEntry::Op(OpCodes::OP_DUP),
             StackEntry::Op(op_hash_256),
-            StackEntry::PubKeyHash(construct_address_for(&pub_key, address_version)),
+            StackEntry::pub_key_hash_from_text(construct_address_for(&pub_key, address_version)),
             StackEntry::Op(OpCodes::OP_EQUALVERIFY),
             StackEntry::Op(OpCodes::OP_CHECKSIG),
         ];
@@ -422,7 +563,7 @@ impl Script {
     /// * `signature`   - Signature of this party
     pub fn member_multisig(check_data: String, pub_key: PublicKey, signature: Signature) -> Self {
         let stack = vec![
-            StackEntry::Bytes(check_data),
+            StackEntry::bytes_from_text(check_data),
             StackEntry::Signature(signature),
             StackEntry::PubKey(pub_key),
             StackEntry::Op(OpCodes::OP_CHECKSIG),
@@ -439,7 +580,7 @@ impl Script {
     /// * `check_data`  - Data to have checked against signatures
     /// * `pub_keys`    - The constituent public keys
     pub fn multisig_lock(m: usize, n: usize, check_data: String, pub_keys: Vec<PublicKey>) -> Self {
-        let mut stack = vec![StackEntry::Bytes(check_data), StackEntry::Num(m)];
+        let mut stack = vec![StackEntry::bytes_from_text(check_data), StackEntry::Num(m)];
         stack.append(&mut pub_keys.iter().map(|e| StackEntry::PubKey(*e)).collect());
         stack.push(StackEntry::Num(n));
         stack.push(StackEntry::Op(OpCodes::OP_CHECKMULTISIG));
@@ -453,7 +594,7 @@ impl Script {
     /// * `check_data`  - Data to have signed
     /// * `signatures`  - Signatures to unlock with
     pub fn multisig_unlock(check_data: String, signatures: Vec<Signature>) -> Self {
-        let mut stack = vec![StackEntry::Bytes(check_data)];
+        let mut stack = vec![StackEntry::bytes_from_text(check_data)];
         stack.append(
             &mut signatures
                 .iter()
@@ -478,7 +619,7 @@ impl Script {
         signatures: Vec<Signature>,
         pub_keys: Vec<PublicKey>,
     ) -> Self {
-        let mut stack = vec![StackEntry::Bytes(check_data)];
+        let mut stack = vec![StackEntry::bytes_from_text(check_data)];
         stack.append(
             &mut signatures
                 .iter()
@@ -1,14 +1,140 @@
 #![allow(unused)]
 pub mod interface_ops;
 pub mod lang;
+pub mod policy;
 
-use crate::crypto::sign_ed25519::{PublicKey, Signature};
+use crate::constants::{EIGHT, ONE, SCRIPT_VERSION_V1, SCRIPT_VERSION_V2, TWO};
+use crate::crypto::sign_ed25519::{
+    PublicKey, Signature, ED25519_PUBLIC_KEY_LEN, ED25519_SIGNATURE_LEN,
+};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// Stack entry enum
+///
+/// `PubKeyHash` and `Bytes` items hold raw bytes and are only hex encoded when
+/// serialized or displayed. Scripts built before items held raw bytes carried them
+/// as strings, so items whose string is not canonical lowercase hex are migrated to
+/// `LegacyPubKeyHash` and `LegacyBytes` items holding that string verbatim. Every
+/// item serializes back to the string it was read from, keeping serialized scripts,
+/// P2SH addresses and transaction hashes unchanged
 #[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(from = "WireStackEntry", into = "WireStackEntry")]
 pub enum StackEntry {
+    Op(OpCodes),
+    Signature(Signature),
+    PubKey(PublicKey),
+    PubKeyHash(Vec<u8>),
+    Num(usize),
+    Bytes(Vec<u8>),
+    LegacyPubKeyHash(String),
+    LegacyBytes(String),
+}
+
+impl StackEntry {
+    /// Creates a `Bytes` item from its hex representation, or `None` if it is
+    /// not canonical lowercase hex
+    ///
+    /// ### Arguments
+    ///
+    /// * `s`   - Hex encoded item
+    pub fn bytes_from_hex(s: &str) -> Option<Self> {
+        decode_item_hex(s).map(StackEntry::Bytes)
+    }
+
+    /// Creates a `PubKeyHash` item from an address, or `None` if it is not
+    /// canonical lowercase hex
+    ///
+    /// ### Arguments
+    ///
+    /// * `address` - Hex encoded address
+    pub fn pub_key_hash_from_hex(address: &str) -> Option<Self> {
+        decode_item_hex(address).map(StackEntry::PubKeyHash)
+    }
+
+    /// Creates a `Bytes` item from the string a script holds it as: canonical
+    /// lowercase hex is held as raw bytes, and any other string as a `LegacyBytes` item
+    ///
+    /// ### Arguments
+    ///
+    /// * `s`   - String of the item
+    pub fn bytes_from_text(s: String) -> Self {
+        match decode_item_hex(&s) {
+            Some(bytes) => StackEntry::Bytes(bytes),
+            None => StackEntry::LegacyBytes(s),
+        }
+    }
+
+    /// Creates a `PubKeyHash` item from the string a script holds it as: canonical
+    /// lowercase hex is held as raw bytes, and any other string as a `LegacyPubKeyHash` item
+    ///
+    /// ### Arguments
+    ///
+    /// * `s`   - String of the item, usually an address
+    pub fn pub_key_hash_from_text(s: String) -> Self {
+        match decode_item_hex(&s) {
+            Some(bytes) => StackEntry::PubKeyHash(bytes),
+            None => StackEntry::LegacyPubKeyHash(s),
+        }
+    }
+
+    /// Returns true for items held as strings by scripts built before items held raw bytes
+    pub fn is_legacy(&self) -> bool {
+        matches!(
+            self,
+            StackEntry::LegacyPubKeyHash(_) | StackEntry::LegacyBytes(_)
+        )
+    }
+
+    /// Size of the item in bytes when counted towards the script length and the item
+    /// size limit. `PubKeyHash` and `Bytes` items count their raw bytes in scripts with
+    /// byte items, and the length of the string they are held as otherwise
+    ///
+    /// ### Arguments
+    ///
+    /// * `byte_items`  - Whether the script operates on the raw bytes of items
+    pub fn size(&self, byte_items: bool) -> usize {
+        match self {
+            StackEntry::Op(_) => ONE,
+            StackEntry::Signature(_) => ED25519_SIGNATURE_LEN,
+            StackEntry::PubKey(_) => ED25519_PUBLIC_KEY_LEN,
+            StackEntry::PubKeyHash(b) | StackEntry::Bytes(b) if byte_items => b.len(),
+            StackEntry::PubKeyHash(b) | StackEntry::Bytes(b) => b.len() * TWO,
+            StackEntry::LegacyPubKeyHash(s) | StackEntry::LegacyBytes(s) => s.len(),
+            StackEntry::Num(_) => usize::BITS as usize / EIGHT,
+        }
+    }
+}
+
+/// Returns true if scripts of a given version operate on the raw bytes of `PubKeyHash`
+/// and `Bytes` items. Older versions operate on the strings the items are held as,
+/// which for canonical items is their hex encoding
+///
+/// ### Arguments
+///
+/// * `script_version`  - Script version of the output being spent
+pub fn has_byte_items(script_version: u64) -> bool {
+    script_version >= SCRIPT_VERSION_V2
+}
+
+/// Decodes the hex representation of a `PubKeyHash` or `Bytes` item, or `None` if
+/// it is not canonical lowercase hex, so that encoding the bytes gives back `s`
+///
+/// ### Arguments
+///
+/// * `s`   - Hex encoded item
+pub fn decode_item_hex(s: &str) -> Option<Vec<u8>> {
+    if s.bytes().any(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    hex::decode(s).ok()
+}
+
+/// Serialized layout of stack entries, holding `PubKeyHash` and `Bytes` items as
+/// the strings scripts carried before items held raw bytes
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "StackEntry")]
+enum WireStackEntry {
     Op(OpCodes),
     Signature(Signature),
     PubKey(PublicKey),
@@ -17,6 +143,34 @@ pub enum StackEntry {
     Bytes(String),
 }
 
+impl From<WireStackEntry> for StackEntry {
+    fn from(entry: WireStackEntry) -> Self {
+        match entry {
+            WireStackEntry::Op(op) => StackEntry::Op(op),
+            WireStackEntry::Signature(sig) => StackEntry::Signature(sig),
+            WireStackEntry::PubKey(pk) => StackEntry::PubKey(pk),
+            WireStackEntry::PubKeyHash(s) => StackEntry::pub_key_hash_from_text(s),
+            WireStackEntry::Num(n) => StackEntry::Num(n),
+            WireStackEntry::Bytes(s) => StackEntry::bytes_from_text(s),
+        }
+    }
+}
+
+impl From<StackEntry> for WireStackEntry {
+    fn from(entry: StackEntry) -> Self {
+        match entry {
+            StackEntry::Op(op) => WireStackEntry::Op(op),
+            StackEntry::Signature(sig) => WireStackEntry::Signature(sig),
+            StackEntry::PubKey(pk) => WireStackEntry::PubKey(pk),
+            StackEntry::PubKeyHash(b) => WireStackEntry::PubKeyHash(hex::encode(b)),
+            StackEntry::LegacyPubKeyHash(s) => WireStackEntry::PubKeyHash(s),
+            StackEntry::Num(n) => WireStackEntry::Num(n),
+            StackEntry::Bytes(b) => WireStackEntry::Bytes(hex::encode(b)),
+            StackEntry::LegacyBytes(s) => WireStackEntry::Bytes(s),
+        }
+    }
+}
+
 /// Opcodes enum
 #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
@@ -115,6 +269,17 @@ pub enum OpCodes {
     OP_CHECKSIGVERIFY = 0xad,
     OP_CHECKMULTISIG = 0xae,
     OP_CHECKMULTISIGVERIFY = 0xaf,
+    // upgradable nops
+    OP_NOP1 = 0xb0,
+    OP_NOP2 = 0xb1,
+    OP_NOP3 = 0xb2,
+    OP_NOP4 = 0xb3,
+    OP_NOP5 = 0xb4,
+    OP_NOP6 = 0xb5,
+    OP_NOP7 = 0xb6,
+    OP_NOP8 = 0xb7,
+    OP_NOP9 = 0xb8,
+    OP_NOP10 = 0xb9,
     // smart data
     OP_CREATE = 0xc0,
 }
@@ -127,6 +292,44 @@ impl OpCodes {
             OpCodes::OP_IF | OpCodes::OP_NOTIF | OpCodes::OP_ELSE | OpCodes::OP_ENDIF
         )
     }
+
+    /// Returns true if the opcode is a slot reserved for soft-fork upgrades
+    pub fn is_upgradable_nop(&self) -> bool {
+        matches!(
+            self,
+            OpCodes::OP_NOP1
+                | OpCodes::OP_NOP2
+                | OpCodes::OP_NOP3
+                | OpCodes::OP_NOP4
+                | OpCodes::OP_NOP5
+                | OpCodes::OP_NOP6
+                | OpCodes::OP_NOP7
+                | OpCodes::OP_NOP8
+                | OpCodes::OP_NOP9
+                | OpCodes::OP_NOP10
+        )
+    }
+
+    /// Returns the script version from which an upgradable NOP slot stops being
+    /// a no-op, or `None` if no upgrade has been assigned to the slot yet
+    pub fn activation_version(&self) -> Option<u64> {
+        match self {
+            OpCodes::OP_NOP1 => Some(SCRIPT_VERSION_V1),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the opcode executes as a no-op under the given script version
+    ///
+    /// ### Arguments
+    ///
+    /// * `script_version`  - Script version of the output being spent
+    pub fn is_inactive_nop(&self, script_version: u64) -> bool {
+        match self.activation_version() {
+            Some(version) => script_version < version,
+            None => self.is_upgradable_nop(),
+        }
+    }
 }
 
 /// Allows for string casting
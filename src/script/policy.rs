@@ -0,0 +1,802 @@
+use crate::constants::SCRIPT_VERSION_V2;
+use crate::crypto::sha3_256;
+use crate::crypto::sign_ed25519::{PublicKey, Signature};
+use crate::script::lang::{Script, ScriptLimits};
+use crate::script::{decode_item_hex, OpCodes, StackEntry};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Length in bytes of a SHA3-256 hash
+const SHA3_256_LEN: usize = 32;
+
+/// Maximum nesting depth of a policy, bounding recursion when parsing,
+/// checking and compiling it
+const MAX_POLICY_DEPTH: usize = 32;
+
+/// Spending condition written in the policy language, e.g.
+/// `or(pk(A), and(thresh(2, pk(B), pk(C), pk(D)), sha3(H)))`.
+/// Timelocks such as `after(n)` are not supported, as no script version
+/// activates a locktime opcode, and are rejected when parsing
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Policy {
+    /// `pk(K)`: a signature by the key is required
+    Key(PublicKey),
+    /// `sha3(H)`: the preimage of the SHA3-256 hash is required
+    Sha3(Vec<u8>),
+    /// `and(X, Y)`: both sub-policies must be satisfied
+    And(Box<Policy>, Box<Policy>),
+    /// `or(X, Y)`: either sub-policy must be satisfied
+    Or(Box<Policy>, Box<Policy>),
+    /// `thresh(k, X1, ..., Xn)`: at least `k` of the sub-policies must be satisfied
+    Thresh(usize, Vec<Policy>),
+}
+
+/// Errors raised while parsing or compiling a policy
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PolicyError {
+    Syntax(usize),
+    UnknownFragment(String),
+    InvalidKey(String),
+    InvalidHash(String),
+    InvalidThreshold(usize, usize),
+    InvalidCheckData(String),
+    UnsupportedTimelock,
+    TooDeep,
+    ExceedsLimits,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Syntax(pos) => write!(f, "Unexpected input at position {pos}"),
+            Self::UnknownFragment(name) => write!(f, "Unknown policy fragment: {name}"),
+            Self::InvalidKey(key) => write!(f, "Invalid public key: {key}"),
+            Self::InvalidHash(hash) => write!(f, "Invalid SHA3-256 hash: {hash}"),
+            Self::InvalidThreshold(k, n) => write!(f, "Invalid threshold {k} of {n}"),
+            Self::InvalidCheckData(data) => {
+                write!(f, "Check data is not canonical hex: {data}")
+            }
+            Self::UnsupportedTimelock => {
+                write!(f, "Timelocks are not supported by any script version")
+            }
+            Self::TooDeep => write!(f, "Policy nesting exceeds {MAX_POLICY_DEPTH} levels"),
+            Self::ExceedsLimits => write!(f, "Compiled script exceeds script limits"),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Key(pk) => write!(f, "pk({})", hex::encode(pk.as_ref())),
+            Self::Sha3(h) => write!(f, "sha3({})", hex::encode(h)),
+            Self::And(x, y) => write!(f, "and({x},{y})"),
+            Self::Or(x, y) => write!(f, "or({x},{y})"),
+            Self::Thresh(k, subs) => {
+                write!(f, "thresh({k}")?;
+                for sub in subs {
+                    write!(f, ",{sub}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = PolicyError;
+
+    /// Parses a policy whose keys are given as hex encoded public keys
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, &BTreeMap::new())
+    }
+}
+
+impl Policy {
+    /// Parses a policy, resolving key names such as `A` in `pk(A)` through
+    /// `keys`. Keys not found there are read as hex encoded public keys
+    ///
+    /// ### Arguments
+    ///
+    /// * `policy`  - Policy text to parse
+    /// * `keys`    - Public keys by name
+    pub fn parse(policy: &str, keys: &BTreeMap<String, PublicKey>) -> Result<Self, PolicyError> {
+        let mut parser = Parser {
+            input: policy,
+            pos: 0,
+            keys,
+        };
+        let result = parser.parse_policy(0)?;
+        parser.skip_whitespace();
+        if parser.pos != policy.len() {
+            return Err(PolicyError::Syntax(parser.pos));
+        }
+        result.check()?;
+        Ok(result)
+    }
+
+    /// Checks that hashes, thresholds and the nesting depth are well formed
+    pub fn check(&self) -> Result<(), PolicyError> {
+        self.check_at(0)
+    }
+
+    /// Checks the policy as `check` does, at a nesting depth
+    ///
+    /// ### Arguments
+    ///
+    /// * `depth`   - Nesting depth of the policy
+    fn check_at(&self, depth: usize) -> Result<(), PolicyError> {
+        if depth >= MAX_POLICY_DEPTH {
+            return Err(PolicyError::TooDeep);
+        }
+        match self {
+            Self::Key(_) => Ok(()),
+            Self::Sha3(h) if h.len() != SHA3_256_LEN => {
+                Err(PolicyError::InvalidHash(hex::encode(h)))
+            }
+            Self::Sha3(_) => Ok(()),
+            Self::And(x, y) | Self::Or(x, y) => {
+                x.check_at(depth + 1)?;
+                y.check_at(depth + 1)
+            }
+            Self::Thresh(k, subs) => {
+                if *k == 0 || *k > subs.len() {
+                    return Err(PolicyError::InvalidThreshold(*k, subs.len()));
+                }
+                subs.iter().try_for_each(|sub| sub.check_at(depth + 1))
+            }
+        }
+    }
+
+    /// Compiles the policy to its most compact script under the consensus limits
+    ///
+    /// ### Arguments
+    ///
+    /// * `check_data`  - Hex encoded data every signature must be over, unique to each output
+    pub fn compile(&self, check_data: &str) -> Result<CompiledPolicy, PolicyError> {
+        self.compile_with_limits(check_data, &Default::default())
+    }
+
+    /// Compiles the policy to its most compact script under the given limits.
+    /// The check data signatures are over is part of the locking script, so that
+    /// signatures made for other scripts cannot be replayed against it.
+    /// The script operates on raw bytes, so it runs under `SCRIPT_VERSION_V2`
+    ///
+    /// Signatures only commit to the check data, not to the output being spent,
+    /// so the check data must be unique to each output locked with the policy.
+    /// Signatures over a check data shared by several outputs can be replayed to
+    /// spend all of them. It cannot be derived from the outpoint, as the outpoint
+    /// depends on the locking script the check data is part of
+    ///
+    /// ### Arguments
+    ///
+    /// * `check_data`  - Hex encoded data every signature must be over, unique to each output
+    /// * `limits`      - Limits the compiled script must respect
+    pub fn compile_with_limits(
+        &self,
+        check_data: &str,
+        limits: &ScriptLimits,
+    ) -> Result<CompiledPolicy, PolicyError> {
+        self.check()?;
+        let check_bytes = decode_item_hex(check_data)
+            .ok_or_else(|| PolicyError::InvalidCheckData(check_data.to_owned()))?;
+
+        let fragment = Fragment::compile(self, &check_bytes, limits);
+        let mut stack = Vec::new();
+        fragment.encode(false, &check_bytes, &mut stack);
+        let script = Script::from(stack);
+
+        if !script.is_valid_for(limits, SCRIPT_VERSION_V2) {
+            return Err(PolicyError::ExceedsLimits);
+        }
+        Ok(CompiledPolicy {
+            fragment,
+            script,
+            check_data: check_data.to_owned(),
+        })
+    }
+}
+
+/// A policy compiled to a locking script, keeping the chosen script layout
+/// so that matching unlocking data can be produced
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompiledPolicy {
+    fragment: Fragment,
+    script: Script,
+    check_data: String,
+}
+
+impl CompiledPolicy {
+    /// Returns the locking script
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    /// Returns the hex encoded data whose raw bytes signatures must be over
+    pub fn check_data(&self) -> &str {
+        &self.check_data
+    }
+
+    /// Returns the script version the locking script must be spent under, to be
+    /// encoded in the P2SH address locking the output
+    pub fn script_version(&self) -> u64 {
+        SCRIPT_VERSION_V2
+    }
+
+    /// Produces the unlocking data, in push order, that satisfies the locking
+    /// script with the fewest bytes. Returns `None` if the policy cannot be satisfied
+    ///
+    /// ### Arguments
+    ///
+    /// * `satisfier`   - Available signatures and preimages
+    pub fn satisfy(&self, satisfier: &Satisfier) -> Option<Vec<StackEntry>> {
+        self.fragment.satisfy(satisfier)
+    }
+
+    /// Produces the full script to execute for a spend: the unlocking data
+    /// followed by the locking script
+    ///
+    /// ### Arguments
+    ///
+    /// * `satisfier`   - Available signatures and preimages
+    pub fn spending_script(&self, satisfier: &Satisfier) -> Option<Script> {
+        let mut stack = self.satisfy(satisfier)?;
+        stack.extend(self.script.stack.iter().cloned());
+        Some(Script::from(stack))
+    }
+}
+
+/// Data available to satisfy a compiled policy
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Satisfier {
+    pub signatures: BTreeMap<PublicKey, Signature>,
+    pub preimages: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Satisfier {
+    /// Creates a new satisfier without any signatures or preimages
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a signature over the check data of the compiled policy
+    ///
+    /// ### Arguments
+    ///
+    /// * `pub_key`     - Public key the signature was made with
+    /// * `signature`   - Signature of the check data
+    pub fn add_signature(&mut self, pub_key: PublicKey, signature: Signature) {
+        self.signatures.insert(pub_key, signature);
+    }
+
+    /// Adds a preimage, indexed by its SHA3-256 hash
+    ///
+    /// ### Arguments
+    ///
+    /// * `preimage`    - Preimage to add
+    pub fn add_preimage(&mut self, preimage: Vec<u8>) {
+        let hash = sha3_256::digest(&preimage).to_vec();
+        self.preimages.insert(hash, preimage);
+    }
+}
+
+/// Script layout chosen for a policy
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Fragment {
+    Pk(PublicKey),
+    Sha3(Vec<u8>),
+    AndV(Box<Fragment>, Box<Fragment>),
+    OrIf(Box<Fragment>, Box<Fragment>),
+    Multi(usize, Vec<PublicKey>),
+    Thresh(usize, Vec<Fragment>),
+}
+
+impl Fragment {
+    /// Picks the most compact layout for a policy without timelocks
+    fn compile(policy: &Policy, check_data: &[u8], limits: &ScriptLimits) -> Self {
+        let compile = |p: &Policy| Self::compile(p, check_data, limits);
+        match policy {
+            Policy::Key(pk) => Self::Pk(*pk),
+            Policy::Sha3(h) => Self::Sha3(h.clone()),
+            Policy::And(x, y) => Self::and(compile(x), compile(y)),
+            Policy::Or(x, y) => {
+                let or_if = Self::or(compile(x), compile(y));
+                let multi = Self::multi(1, &[x.as_ref().clone(), y.as_ref().clone()], limits);
+                Self::smallest(vec![Some(or_if), multi], check_data)
+            }
+            Policy::Thresh(k, subs) => {
+                let frags: Vec<Fragment> = subs.iter().map(compile).collect();
+                let and_chain =
+                    (*k == subs.len()).then(|| frags.iter().cloned().reduce(Self::and).unwrap());
+                let or_chain = (*k == 1).then(|| frags.iter().cloned().reduce(Self::or).unwrap());
+                let multi = Self::multi(*k, subs, limits);
+                let thresh = Self::Thresh(*k, frags);
+                Self::smallest(vec![Some(thresh), and_chain, or_chain, multi], check_data)
+            }
+        }
+    }
+
+    fn and(x: Self, y: Self) -> Self {
+        Self::AndV(Box::new(x), Box::new(y))
+    }
+
+    fn or(x: Self, y: Self) -> Self {
+        Self::OrIf(Box::new(x), Box::new(y))
+    }
+
+    /// Builds a multisig layout if every sub-policy is a key
+    fn multi(k: usize, subs: &[Policy], limits: &ScriptLimits) -> Option<Self> {
+        let pks: Option<Vec<PublicKey>> = subs
+            .iter()
+            .map(|s| match s {
+                Policy::Key(pk) => Some(*pk),
+                _ => None,
+            })
+            .collect();
+        pks.filter(|pks| pks.len() <= limits.max_pub_keys_per_multisig)
+            .map(|pks| Self::Multi(k, pks))
+    }
+
+    /// Returns the candidate with the shortest script, the first one on ties
+    fn smallest(candidates: Vec<Option<Self>>, check_data: &[u8]) -> Self {
+        let mut best: Option<(usize, Self)> = None;
+        for candidate in candidates.into_iter().flatten() {
+            let mut stack = Vec::new();
+            candidate.encode(false, check_data, &mut stack);
+            let len = entries_size(&stack);
+            match &best {
+                Some((best_len, _)) if *best_len <= len => (),
+                _ => best = Some((len, candidate)),
+            }
+        }
+        best.unwrap().1
+    }
+
+    /// Appends the script for the fragment. A `verify` fragment leaves nothing
+    /// on the stack and fails the script if unsatisfied; otherwise it leaves
+    /// a non-zero number on success. Signatures are checked against the check
+    /// data pushed by the script itself, never against data from the spender
+    fn encode(&self, verify: bool, check_data: &[u8], out: &mut Vec<StackEntry>) {
+        let pick = |v: OpCodes, b: OpCodes| StackEntry::Op(if verify { v } else { b });
+        match self {
+            Self::Pk(pk) => {
+                // The check data goes below the spender's signature
+                out.push(StackEntry::Bytes(check_data.to_vec()));
+                out.push(StackEntry::Op(OpCodes::OP_SWAP));
+                out.push(StackEntry::PubKey(*pk));
+                out.push(pick(OpCodes::OP_CHECKSIGVERIFY, OpCodes::OP_CHECKSIG));
+            }
+            Self::Sha3(h) => {
+                out.push(StackEntry::Op(OpCodes::OP_SHA3));
+                out.push(StackEntry::Bytes(h.clone()));
+                out.push(pick(OpCodes::OP_EQUALVERIFY, OpCodes::OP_EQUAL));
+            }
+            Self::AndV(x, y) => {
+                x.encode(true, check_data, out);
+                y.encode(verify, check_data, out);
+            }
+            Self::OrIf(x, y) => {
+                out.push(StackEntry::Op(OpCodes::OP_IF));
+                x.encode(verify, check_data, out);
+                out.push(StackEntry::Op(OpCodes::OP_ELSE));
+                y.encode(verify, check_data, out);
+                out.push(StackEntry::Op(OpCodes::OP_ENDIF));
+            }
+            Self::Multi(k, pks) => {
+                // The check data goes below the spender's `k` signatures
+                out.extend((0..*k).map(|_| StackEntry::Op(OpCodes::OP_TOALTSTACK)));
+                out.push(StackEntry::Bytes(check_data.to_vec()));
+                out.extend((0..*k).map(|_| StackEntry::Op(OpCodes::OP_FROMALTSTACK)));
+                out.push(StackEntry::Num(*k));
+                out.extend(pks.iter().map(|pk| StackEntry::PubKey(*pk)));
+                out.push(StackEntry::Num(pks.len()));
+                out.push(pick(
+                    OpCodes::OP_CHECKMULTISIGVERIFY,
+                    OpCodes::OP_CHECKMULTISIG,
+                ));
+            }
+            Self::Thresh(k, subs) => {
+                // Each sub-policy adds 1 or 0 to a running count kept on the alt stack
+                for (i, sub) in subs.iter().enumerate() {
+                    if i > 0 {
+                        out.push(StackEntry::Op(OpCodes::OP_TOALTSTACK));
+                    }
+                    out.push(StackEntry::Op(OpCodes::OP_IF));
+                    sub.encode(true, check_data, out);
+                    out.push(StackEntry::Op(OpCodes::OP_1));
+                    out.push(StackEntry::Op(OpCodes::OP_ELSE));
+                    out.push(StackEntry::Op(OpCodes::OP_0));
+                    out.push(StackEntry::Op(OpCodes::OP_ENDIF));
+                    if i > 0 {
+                        out.push(StackEntry::Op(OpCodes::OP_FROMALTSTACK));
+                        out.push(StackEntry::Op(OpCodes::OP_ADD));
+                    }
+                }
+                out.push(StackEntry::Num(*k));
+                out.push(pick(OpCodes::OP_NUMEQUALVERIFY, OpCodes::OP_NUMEQUAL));
+            }
+        }
+    }
+
+    /// Produces the smallest unlocking data for the fragment, in push order
+    fn satisfy(&self, satisfier: &Satisfier) -> Option<Vec<StackEntry>> {
+        match self {
+            Self::Pk(pk) => {
+                let sig = satisfier.signatures.get(pk)?;
+                Some(vec![StackEntry::Signature(*sig)])
+            }
+            Self::Sha3(h) => {
+                let preimage = satisfier.preimages.get(h)?;
+                Some(vec![StackEntry::Bytes(preimage.clone())])
+            }
+            Self::AndV(x, y) => {
+                // `x` runs first, so its data must be on top
+                let mut witness = y.satisfy(satisfier)?;
+                witness.extend(x.satisfy(satisfier)?);
+                Some(witness)
+            }
+            Self::OrIf(x, y) => {
+                let left = x.satisfy(satisfier).map(|mut w| {
+                    w.push(StackEntry::Num(1));
+                    w
+                });
+                let right = y.satisfy(satisfier).map(|mut w| {
+                    w.push(StackEntry::Num(0));
+                    w
+                });
+                match (left, right) {
+                    (Some(l), Some(r)) if entries_size(&r) < entries_size(&l) => Some(r),
+                    (left, right) => left.or(right),
+                }
+            }
+            Self::Multi(k, pks) => {
+                let sigs: Vec<StackEntry> = pks
+                    .iter()
+                    .filter_map(|pk| satisfier.signatures.get(pk))
+                    .take(*k)
+                    .map(|sig| StackEntry::Signature(*sig))
+                    .collect();
+                if sigs.len() < *k {
+                    return None;
+                }
+                Some(sigs)
+            }
+            Self::Thresh(k, subs) => {
+                let sats: Vec<Option<Vec<StackEntry>>> =
+                    subs.iter().map(|sub| sub.satisfy(satisfier)).collect();
+                let mut chosen: Vec<usize> =
+                    (0..subs.len()).filter(|i| sats[*i].is_some()).collect();
+                if chosen.len() < *k {
+                    return None;
+                }
+                chosen.sort_by_key(|i| sats[*i].as_deref().map_or(0, entries_size));
+                chosen.truncate(*k);
+
+                // The first sub-policy runs first, so its data must be on top
+                let mut witness = Vec::new();
+                for (i, sat) in sats.into_iter().enumerate().rev() {
+                    match sat {
+                        Some(sat) if chosen.contains(&i) => {
+                            witness.extend(sat);
+                            witness.push(StackEntry::Num(1));
+                        }
+                        _ => witness.push(StackEntry::Num(0)),
+                    }
+                }
+                Some(witness)
+            }
+        }
+    }
+}
+
+/// Total size in bytes of a sequence of stack entries
+fn entries_size(entries: &[StackEntry]) -> usize {
+    entries.iter().map(|entry| entry.size(true)).sum()
+}
+
+/// Recursive descent parser for the policy language
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    keys: &'a BTreeMap<String, PublicKey>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.input[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), PolicyError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(PolicyError::Syntax(self.pos)),
+        }
+    }
+
+    fn token(&mut self) -> Result<&'a str, PolicyError> {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(PolicyError::Syntax(self.pos));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, PolicyError> {
+        let pos = self.pos;
+        self.token()?.parse().map_err(|_| PolicyError::Syntax(pos))
+    }
+
+    fn key(&mut self) -> Result<PublicKey, PolicyError> {
+        let name = self.token()?;
+        if let Some(pk) = self.keys.get(name) {
+            return Ok(*pk);
+        }
+        hex::decode(name)
+            .ok()
+            .and_then(|bytes| PublicKey::from_slice(&bytes))
+            .ok_or_else(|| PolicyError::InvalidKey(name.to_owned()))
+    }
+
+    fn parse_policy(&mut self, depth: usize) -> Result<Policy, PolicyError> {
+        if depth >= MAX_POLICY_DEPTH {
+            return Err(PolicyError::TooDeep);
+        }
+        let name = self.token()?;
+        self.expect('(')?;
+        let policy = match name {
+            "pk" => Policy::Key(self.key()?),
+            "after" => return Err(PolicyError::UnsupportedTimelock),
+            "sha3" => {
+                let hash = self.token()?;
+                let bytes =
+                    hex::decode(hash).map_err(|_| PolicyError::InvalidHash(hash.to_owned()))?;
+                Policy::Sha3(bytes)
+            }
+            "and" | "or" => {
+                let x = Box::new(self.parse_policy(depth + 1)?);
+                self.expect(',')?;
+                let y = Box::new(self.parse_policy(depth + 1)?);
+                match name {
+                    "and" => Policy::And(x, y),
+                    _ => Policy::Or(x, y),
+                }
+            }
+            "thresh" => {
+                let k = self.number()?;
+                let mut subs = Vec::new();
+                while self.eat(',') {
+                    subs.push(self.parse_policy(depth + 1)?);
+                }
+                Policy::Thresh(k, subs)
+            }
+            _ => return Err(PolicyError::UnknownFragment(name.to_owned())),
+        };
+        self.expect(')')?;
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519::{self as sign, SecretKey};
+
+    fn keys(names: &[&str]) -> (BTreeMap<String, PublicKey>, BTreeMap<String, SecretKey>) {
+        let mut pks = BTreeMap::new();
+        let mut sks = BTreeMap::new();
+        for name in names {
+            let (pk, sk) = sign::gen_keypair();
+            pks.insert(name.to_string(), pk);
+            sks.insert(name.to_string(), sk);
+        }
+        (pks, sks)
+    }
+
+    fn check_data() -> String {
+        hex::encode(vec![0, 0, 0])
+    }
+
+    fn satisfier_with(
+        pks: &BTreeMap<String, PublicKey>,
+        sks: &BTreeMap<String, SecretKey>,
+        signers: &[&str],
+        check_data: &str,
+    ) -> Satisfier {
+        let mut satisfier = Satisfier::new();
+        for name in signers {
+            let sig = sign::sign_detached(&hex::decode(check_data).unwrap(), &sks[*name]);
+            satisfier.add_signature(pks[*name], sig);
+        }
+        satisfier
+    }
+
+    #[test]
+    /// Checks a compiled policy against each way of satisfying it
+    fn test_compile_and_satisfy_policy() {
+        let (pks, sks) = keys(&["A", "B", "C", "D"]);
+        let policy = "or(pk(A), and(thresh(2, pk(B), pk(C), pk(D)), pk(C)))";
+        let compiled = Policy::parse(policy, &pks)
+            .unwrap()
+            .compile(&check_data())
+            .unwrap();
+        let run = |signers: &[&str]| {
+            let satisfier = satisfier_with(&pks, &sks, signers, compiled.check_data());
+            compiled
+                .spending_script(&satisfier)
+                .map(|s| s.interpret_for(SCRIPT_VERSION_V2))
+        };
+
+        assert_eq!(run(&["A"]), Some(true));
+        assert_eq!(run(&["B", "C"]), Some(true));
+        assert_eq!(run(&["A", "B", "C"]), Some(true));
+        assert_eq!(run(&["B", "D"]), None);
+        assert_eq!(run(&["C"]), None);
+        assert_eq!(run(&[]), None);
+    }
+
+    #[test]
+    /// Checks that timelocks are rejected when parsing, as no script version
+    /// activates a locktime opcode and the spend could be made at any height
+    fn test_timelock_policy_is_rejected() {
+        let (pks, _) = keys(&["A"]);
+        for policy in ["after(1000)", "or(pk(A), after(1000))", "after(0)"] {
+            assert_eq!(
+                Policy::parse(policy, &pks),
+                Err(PolicyError::UnsupportedTimelock)
+            );
+        }
+    }
+
+    #[test]
+    /// Checks that signatures are only accepted over the check data of the
+    /// locking script, whatever the spender pushes
+    fn test_satisfy_with_invalid_signature() {
+        let (pks, sks) = keys(&["A", "B"]);
+        let policy = Policy::parse("and(pk(A), pk(B))", &pks).unwrap();
+        let compiled = policy.compile(&check_data()).unwrap();
+        let mut satisfier = satisfier_with(&pks, &sks, &["A", "B"], &check_data());
+        assert!(compiled
+            .spending_script(&satisfier)
+            .unwrap()
+            .interpret_for(SCRIPT_VERSION_V2));
+
+        let wrong_sig = sign::sign_detached(b"other", &sks["A"]);
+        satisfier.add_signature(pks["A"], wrong_sig);
+        assert!(!compiled
+            .spending_script(&satisfier)
+            .unwrap()
+            .interpret_for(SCRIPT_VERSION_V2));
+
+        // Signatures over other check data cannot be replayed, even alongside it
+        let other_data = hex::encode(b"other");
+        let replayed = satisfier_with(&pks, &sks, &["A", "B"], &other_data);
+        let mut script = vec![StackEntry::bytes_from_hex(&other_data).unwrap()];
+        script.extend(compiled.satisfy(&replayed).unwrap());
+        script.extend(compiled.script().stack.iter().cloned());
+        assert!(!Script::from(script).interpret_for(SCRIPT_VERSION_V2));
+        assert_eq!(
+            policy.compile("not hex"),
+            Err(PolicyError::InvalidCheckData("not hex".to_owned()))
+        );
+    }
+
+    #[test]
+    /// Checks generic thresholds mixing keys and hashes
+    fn test_thresh_with_preimage() {
+        let (pks, sks) = keys(&["A", "B"]);
+        let preimage = b"secret".to_vec();
+        let hash = hex::encode(sha3_256::digest(&preimage));
+        let policy = format!("thresh(2, pk(A), sha3({hash}), and(pk(A), pk(B)))");
+        let compiled = Policy::parse(&policy, &pks)
+            .unwrap()
+            .compile(&check_data())
+            .unwrap();
+        assert_eq!(
+            compiled.script().stack.last(),
+            Some(&StackEntry::Op(OpCodes::OP_NUMEQUAL))
+        );
+
+        let mut satisfier = satisfier_with(&pks, &sks, &["B"], &check_data());
+        assert!(compiled.satisfy(&satisfier).is_none());
+        satisfier.add_preimage(preimage.clone());
+        assert!(compiled.satisfy(&satisfier).is_none());
+
+        let mut satisfier = satisfier_with(&pks, &sks, &["A"], &check_data());
+        satisfier.add_preimage(preimage);
+        assert!(compiled
+            .spending_script(&satisfier)
+            .unwrap()
+            .interpret_for(SCRIPT_VERSION_V2));
+
+        let satisfier = satisfier_with(&pks, &sks, &["A", "B"], &check_data());
+        assert!(compiled
+            .spending_script(&satisfier)
+            .unwrap()
+            .interpret_for(SCRIPT_VERSION_V2));
+    }
+
+    #[test]
+    /// Checks that key thresholds use multisig only within the multisig key limit
+    fn test_thresh_of_keys_layout() {
+        let names: Vec<String> = (0..3).map(|i| format!("K{i}")).collect();
+        let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+        let (pks, sks) = keys(&names);
+        let policy = Policy::parse("thresh(2, pk(K0), pk(K1), pk(K2))", &pks).unwrap();
+
+        let compiled = policy.compile(&check_data()).unwrap();
+        assert_eq!(
+            compiled.script().stack.last(),
+            Some(&StackEntry::Op(OpCodes::OP_CHECKMULTISIG))
+        );
+        let satisfier = satisfier_with(&pks, &sks, &["K0", "K2"], &check_data());
+        assert!(compiled
+            .spending_script(&satisfier)
+            .unwrap()
+            .interpret_for(SCRIPT_VERSION_V2));
+
+        let limits = ScriptLimits {
+            max_pub_keys_per_multisig: 2,
+            ..Default::default()
+        };
+        let compiled = policy.compile_with_limits(&check_data(), &limits).unwrap();
+        assert_eq!(
+            compiled.script().stack.last(),
+            Some(&StackEntry::Op(OpCodes::OP_NUMEQUAL))
+        );
+        let script = compiled.spending_script(&satisfier).unwrap();
+        assert!(script.interpret_with_limits(limits, SCRIPT_VERSION_V2));
+    }
+
+    #[test]
+    /// Checks policy text round trips and parse errors
+    fn test_parse_policy() {
+        let (pks, _) = keys(&["A", "B"]);
+        let policy = Policy::parse("or(pk(A), and(pk(B), thresh(1, pk(A), pk(B))))", &pks).unwrap();
+        assert_eq!(policy.to_string().parse::<Policy>(), Ok(policy));
+
+        let parse = |s: &str| Policy::parse(s, &pks);
+        assert_eq!(parse("pk(A"), Err(PolicyError::Syntax(4)));
+        assert_eq!(parse("pk(A) x"), Err(PolicyError::Syntax(6)));
+        assert_eq!(
+            parse("xor(pk(A), pk(B))"),
+            Err(PolicyError::UnknownFragment("xor".to_owned()))
+        );
+        assert_eq!(parse("pk(Z)"), Err(PolicyError::InvalidKey("Z".to_owned())));
+        assert_eq!(parse("after(7)"), Err(PolicyError::UnsupportedTimelock));
+        assert_eq!(
+            parse("thresh(3, pk(A), pk(B))"),
+            Err(PolicyError::InvalidThreshold(3, 2))
+        );
+        assert_eq!(
+            parse("sha3(abcd)"),
+            Err(PolicyError::InvalidHash("abcd".to_owned()))
+        );
+
+        // Nesting is bounded, including for policies built directly
+        let nested = |depth: usize| "and(pk(A),".repeat(depth) + "pk(B)" + &")".repeat(depth);
+        assert!(parse(&nested(MAX_POLICY_DEPTH - 1)).is_ok());
+        assert_eq!(parse(&nested(MAX_POLICY_DEPTH)), Err(PolicyError::TooDeep));
+        assert_eq!(parse(&nested(100_000)), Err(PolicyError::TooDeep));
+        let built = (0..MAX_POLICY_DEPTH).fold(Policy::Key(pks["A"]), |p, _| {
+            Policy::And(Box::new(p), Box::new(Policy::Key(pks["B"])))
+        });
+        assert_eq!(built.check(), Err(PolicyError::TooDeep));
+    }
+}
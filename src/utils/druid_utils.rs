@@ -1,66 +1,557 @@
+use crate::constants::NETWORK_VERSION_LEGACY_DDE;
 use crate::primitives::asset::Asset;
-use crate::primitives::druid::DruidExpectation;
+use crate::primitives::druid::{Druid, DruidExpectation};
 use crate::primitives::transaction::Transaction;
-use crate::utils::transaction_utils::construct_tx_ins_address;
-use std::collections::BTreeSet;
+use crate::utils::transaction_utils::{construct_tx_hash, construct_tx_ins_address};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::iter::Extend;
+use tracing::trace;
 
-/// Verifies that all DDE transaction expectations are met for DRUID-matching transactions
+/// Errors raised when validating the transactions of a DRUID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DruidValidationError {
+    NoTransactions,
+    ConflictingValues,
+    MissingParticipant(String),
+    ParticipantCount { expected: usize, found: usize },
+    UnmetExpectations,
+    InvalidDruid,
+    Expired,
+}
+
+impl fmt::Display for DruidValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoTransactions => write!(f, "No transactions match the DRUID"),
+            Self::ConflictingValues => {
+                write!(
+                    f,
+                    "DRUID transactions declare different participants or expectations"
+                )
+            }
+            Self::MissingParticipant(address) => {
+                write!(f, "No transaction from participant {address}")
+            }
+            Self::ParticipantCount { expected, found } => {
+                write!(
+                    f,
+                    "Expected {expected} participant transactions, found {found}"
+                )
+            }
+            Self::UnmetExpectations => write!(f, "DRUID expectations are not met"),
+            Self::InvalidDruid => write!(f, "DRUID is not derived from its transactions"),
+            Self::Expired => write!(f, "DRUID transactions have expired or disagree on expiry"),
+        }
+    }
+}
+
+/// Verifies that the `druid_info` of all DRUID-matching transactions is consistent
+/// with the DRUID's derivation: the DRUID must be well formed and derived from the
+/// input addresses and expectations of these transactions, which must all declare
+/// the number of participants. Trades made only of transactions up to
+/// `NETWORK_VERSION_LEGACY_DDE` predate derived DRUIDs and are exempt from these checks
 ///
 /// ### Arguments
 ///
 /// * `druid`           - DRUID to match all transactions on
 /// * `transactions`    - Transactions to verify
-pub fn druid_expectations_are_met<'a>(
+pub fn druid_is_valid<'a>(
     druid: &str,
     transactions: impl Iterator<Item = &'a Transaction>,
 ) -> bool {
+    let druid_txs: Vec<&Transaction> = transactions
+        .filter(|tx| matches!(&tx.druid_info, Some(druid_info) if druid_info.druid == druid))
+        .collect();
+    let is_legacy = |tx: &&Transaction| tx.version <= NETWORK_VERSION_LEGACY_DDE;
+    if !druid_txs.is_empty() && druid_txs.iter().all(is_legacy) {
+        trace!("DRUID {} only matches legacy transactions", druid);
+        return true;
+    }
+
+    let parsed: Druid = match druid.parse() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            trace!("Invalid DRUID {}: {}", druid, e);
+            return false;
+        }
+    };
+
+    let mut participants = BTreeSet::new();
     let mut expects = BTreeSet::new();
-    let mut tx_source = BTreeSet::new();
+    let mut declared_participants = BTreeSet::new();
 
-    for tx in transactions {
+    for tx in druid_txs {
         if let Some(druid_info) = &tx.druid_info {
-            let ins = construct_tx_ins_address(&tx.inputs);
+            participants.insert(construct_tx_ins_address(&tx.inputs));
+            expects.extend(druid_info.expectations.iter());
+            declared_participants.insert(druid_info.participants);
+        }
+    }
+
+    if declared_participants.len() != 1 || !declared_participants.contains(&participants.len()) {
+        trace!("DRUID transactions do not match their declared participants");
+        return false;
+    }
+
+    if !parsed.is_derived_from(&participants, expects) {
+        trace!("DRUID {} is not derived from its transactions", druid);
+        return false;
+    }
+    true
+}
+
+/// Verifies that the DRUID-matching transactions all declare the same expiry and
+/// that it has not been reached, so that they are still valid together
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to verify
+/// * `block_height`    - Height of the block the transactions would be included in
+pub fn druid_is_unexpired<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+    block_height: u64,
+) -> bool {
+    let druid_infos: Vec<_> = transactions
+        .filter_map(|tx| tx.druid_info.as_ref())
+        .filter(|druid_info| druid_info.druid == druid)
+        .collect();
+
+    let expiries: BTreeSet<Option<u64>> = druid_infos.iter().map(|d| d.expiry_height).collect();
+    if expiries.len() > 1 {
+        trace!(
+            "DRUID {} transactions declare different expiry heights",
+            druid
+        );
+        return false;
+    }
+
+    if druid_infos.iter().any(|d| d.is_expired_at(block_height)) {
+        trace!("DRUID {} expired before block {}", druid, block_height);
+        return false;
+    }
+    true
+}
+
+/// Verifies that all DDE transaction expectations are met for DRUID-matching transactions
+/// and that they are still valid together at a block height
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to verify
+/// * `block_height`    - Height of the block the transactions would be included in
+pub fn druid_expectations_are_met<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+    block_height: u64,
+) -> bool {
+    match validate_druid_txs_at(druid, transactions, block_height) {
+        Ok(()) => true,
+        Err(e) => {
+            trace!("Invalid DRUID {} transactions: {}", druid, e);
+            false
+        }
+    }
+}
 
-            // Ensure match with passed DRUID
-            if druid_info.druid == druid {
-                expects.extend(druid_info.expectations.iter());
+/// Validates the DRUID-matching transactions as a complete trade: there must be exactly
+/// `participants` distinct transactions, all declaring the same participants and
+/// expectations, one from each expected sender, and together they must meet every expectation.
+/// The DRUID must also be derived from these transactions, as checked by `druid_is_valid`
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to validate
+pub fn validate_druid_txs<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+) -> Result<(), DruidValidationError> {
+    let druid_txs = get_druid_txs(druid, transactions);
+
+    let druid_values = |tx: &'a Transaction| {
+        let druid_info = tx.druid_info.as_ref()?;
+        let expects: BTreeSet<&DruidExpectation> = druid_info.expectations.iter().collect();
+        Some((druid_info.participants, expects))
+    };
+    let mut values = druid_txs.values().map(|tx| druid_values(tx));
+    let (participants, expects) = match values.next() {
+        Some(Some(first)) => first,
+        _ => return Err(DruidValidationError::NoTransactions),
+    };
+    if values.any(|v| v.as_ref() != Some(&(participants, expects.clone()))) {
+        return Err(DruidValidationError::ConflictingValues);
+    }
 
-                for out in &tx.outputs {
-                    if let Some(pk) = &out.script_public_key {
-                        tx_source.insert((ins.clone(), pk, &out.value));
-                    }
+    let (senders, tx_source) = get_druid_tx_source(&druid_txs);
+    if let Some(missing) = expects.iter().find(|e| !senders.contains(&e.from)) {
+        return Err(DruidValidationError::MissingParticipant(
+            missing.from.clone(),
+        ));
+    }
+    if druid_txs.len() != participants {
+        return Err(DruidValidationError::ParticipantCount {
+            expected: participants,
+            found: druid_txs.len(),
+        });
+    }
+
+    if match_expectations(&expects, &tx_source).len() != expects.len() {
+        return Err(DruidValidationError::UnmetExpectations);
+    }
+    if !druid_is_valid(druid, druid_txs.values().copied()) {
+        return Err(DruidValidationError::InvalidDruid);
+    }
+    Ok(())
+}
+
+/// Validates the DRUID-matching transactions as a complete trade, as in `validate_druid_txs`,
+/// for inclusion in a block: they must also declare the same expiry, not yet reached
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to validate
+/// * `block_height`    - Height of the block the transactions would be included in
+pub fn validate_druid_txs_at<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+    block_height: u64,
+) -> Result<(), DruidValidationError> {
+    let transactions: Vec<&Transaction> = transactions.collect();
+    validate_druid_txs(druid, transactions.iter().copied())?;
+    if !druid_is_unexpired(druid, transactions.into_iter(), block_height) {
+        return Err(DruidValidationError::Expired);
+    }
+    Ok(())
+}
+
+/// Reason the closest output to an expectation does not meet it.
+/// Variants are ordered from the closest to the furthest match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExpectationMismatch {
+    /// The output meets the expectation, but already meets another one
+    AlreadyMatched,
+    /// The output is paid to the expected address, but holds another amount of the asset
+    WrongAmount,
+    /// The output holds the expected asset, but is paid to another address
+    WrongAddress,
+    /// The output is paid to the expected address, but holds another asset
+    WrongAsset,
+}
+
+impl fmt::Display for ExpectationMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::AlreadyMatched => write!(f, "already matched"),
+            Self::WrongAmount => write!(f, "wrong amount"),
+            Self::WrongAddress => write!(f, "wrong address"),
+            Self::WrongAsset => write!(f, "wrong asset"),
+        }
+    }
+}
+
+/// Output of the expected sender closest to meeting an unmet expectation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosestOutput {
+    pub to: String,
+    pub asset: Asset,
+    pub mismatch: ExpectationMismatch,
+}
+
+/// Whether an expectation is met, or the closest output if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectationStatus {
+    Met,
+    Unmet(Option<ClosestOutput>),
+}
+
+/// Status of a single expectation of a DRUID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectationReport {
+    pub expectation: DruidExpectation,
+    pub status: ExpectationStatus,
+}
+
+/// Diagnostics of a DRUID's expectations, listing every expectation as met or
+/// unmet and the expected senders without a transaction
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct DruidReport {
+    pub expectations: Vec<ExpectationReport>,
+    pub missing_participants: Vec<String>,
+}
+
+impl DruidReport {
+    /// Whether every expectation is met and every expected sender has a transaction
+    pub fn is_met(&self) -> bool {
+        self.missing_participants.is_empty() && self.unmet().next().is_none()
+    }
+
+    /// Iterates over the unmet expectations
+    pub fn unmet(&self) -> impl Iterator<Item = &ExpectationReport> {
+        self.expectations
+            .iter()
+            .filter(|r| r.status != ExpectationStatus::Met)
+    }
+}
+
+impl fmt::Display for DruidReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for report in &self.expectations {
+            let e = &report.expectation;
+            match &report.status {
+                ExpectationStatus::Met => {
+                    writeln!(f, "Met: {} -> {}: {:?}", e.from, e.to, e.asset)?
                 }
+                ExpectationStatus::Unmet(None) => writeln!(
+                    f,
+                    "Unmet: {} -> {}: {:?}, no candidate output",
+                    e.from, e.to, e.asset
+                )?,
+                ExpectationStatus::Unmet(Some(closest)) => writeln!(
+                    f,
+                    "Unmet: {} -> {}: {:?}, closest output to {} holds {:?} ({})",
+                    e.from, e.to, e.asset, closest.to, closest.asset, closest.mismatch
+                )?,
             }
         }
+        for participant in &self.missing_participants {
+            writeln!(f, "Missing transaction from participant {participant}")?;
+        }
+        Ok(())
     }
+}
 
-    expects.iter().all(|e| expectation_met(e, &tx_source))
+/// Reports, for each expectation of the DRUID-matching transactions, whether it is met
+/// and if not the closest output of its sender, along with the senders whose
+/// transactions are absent. Expectations are matched as in `validate_druid_txs`
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to report on
+pub fn druid_expectations_report<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+) -> DruidReport {
+    let druid_txs = get_druid_txs(druid, transactions);
+    let expects: BTreeSet<&DruidExpectation> = druid_txs
+        .values()
+        .filter_map(|tx| tx.druid_info.as_ref())
+        .flat_map(|druid_info| druid_info.expectations.iter())
+        .collect();
+    let (senders, tx_source) = get_druid_tx_source(&druid_txs);
+    let matches = match_expectations(&expects, &tx_source);
+
+    let expectations = expects
+        .iter()
+        .map(|e| ExpectationReport {
+            expectation: (*e).clone(),
+            status: match matches.get(e) {
+                Some(_) => ExpectationStatus::Met,
+                None => ExpectationStatus::Unmet(closest_output(e, &tx_source)),
+            },
+        })
+        .collect();
+    let missing_participants = expects
+        .iter()
+        .map(|e| &e.from)
+        .filter(|from| !senders.contains(*from))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .cloned()
+        .collect();
+
+    DruidReport {
+        expectations,
+        missing_participants,
+    }
 }
 
-/// Predicate for expected transaction presence in the transaction set
+/// Gets the distinct DRUID-matching transactions, keyed by transaction hash
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to filter
+fn get_druid_txs<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+) -> BTreeMap<String, &'a Transaction> {
+    transactions
+        .filter(|tx| matches!(&tx.druid_info, Some(d) if d.druid == druid))
+        .map(|tx| (construct_tx_hash(tx), tx))
+        .collect()
+}
+
+/// Gets the input addresses of the DRUID transactions, and the outputs they pay
+/// as (sender, recipient, asset) entries
+///
+/// ### Arguments
+///
+/// * `druid_txs`   - Distinct DRUID-matching transactions
+fn get_druid_tx_source<'a>(
+    druid_txs: &BTreeMap<String, &'a Transaction>,
+) -> (BTreeSet<String>, BTreeSet<(String, &'a String, &'a Asset)>) {
+    let mut senders = BTreeSet::new();
+    let mut tx_source = BTreeSet::new();
+    for tx in druid_txs.values() {
+        let ins = construct_tx_ins_address(&tx.inputs);
+        for out in &tx.outputs {
+            if let Some(pk) = &out.script_public_key {
+                tx_source.insert((ins.clone(), pk, &out.value));
+            }
+        }
+        senders.insert(ins);
+    }
+    (senders, tx_source)
+}
+
+/// Matches expectations to distinct outputs, or output bundle components, returning
+/// the match of every expectation that could be met.
+///
+/// The result is deterministic: expectations are matched from the most to the least
+/// constrained comparison, each taking the first unused output in `tx_source` order,
+/// which for a given sender, recipient and asset type is the smallest one
+///
+/// ### Arguments
+///
+/// * `expects`     - The expectations to check on
+/// * `tx_source`   - The source transaction source to match against
+fn match_expectations<'e>(
+    expects: &BTreeSet<&'e DruidExpectation>,
+    tx_source: &BTreeSet<(String, &String, &Asset)>,
+) -> BTreeMap<&'e DruidExpectation, (usize, Option<Asset>)> {
+    let mut ordered: Vec<&DruidExpectation> = expects.iter().copied().collect();
+    ordered.sort_by_key(|e| e.comparison);
+
+    let mut used = BTreeSet::new();
+    let mut matches = BTreeMap::new();
+    for e in ordered {
+        if let Some(matched) = expectation_match(e, tx_source, &used) {
+            used.insert(matched.clone());
+            matches.insert(e, matched);
+        }
+    }
+    matches
+}
+
+/// Finds the first unused output, or output bundle component, meeting an expectation.
+/// An expected token or receipt amount may also be met by a bundle holding that amount
 ///
 /// ### Arguments
 ///
 /// * `e`           - The expectation to check on
 /// * `tx_source`   - The source transaction source to match against
-fn expectation_met(e: &DruidExpectation, tx_source: &BTreeSet<(String, &String, &Asset)>) -> bool {
-    tx_source.get(&(e.from.clone(), &e.to, &e.asset)).is_some()
+/// * `used`        - Outputs and bundle components already matched to an expectation
+fn expectation_match(
+    e: &DruidExpectation,
+    tx_source: &BTreeSet<(String, &String, &Asset)>,
+    used: &BTreeSet<(usize, Option<Asset>)>,
+) -> Option<(usize, Option<Asset>)> {
+    let is_used = |index: usize, component: &Option<Asset>| {
+        used.iter()
+            .any(|(i, c)| *i == index && (c.is_none() || component.is_none() || c == component))
+    };
+
+    tx_source
+        .iter()
+        .enumerate()
+        .filter(|(_, (from, to, _))| *from == e.from && **to == e.to)
+        .find_map(|(index, (_, _, asset))| {
+            let component = match asset {
+                _ if e.is_met_by(asset) => None,
+                Asset::Bundle(bundle) => Some(bundle.components().find(|c| e.is_met_by(c))?),
+                _ => return None,
+            };
+            (!is_used(index, &component)).then_some((index, component))
+        })
+}
+
+/// Finds the output of the expected sender closest to meeting an unmet expectation
+///
+/// ### Arguments
+///
+/// * `e`           - The unmet expectation
+/// * `tx_source`   - The source transaction source to search
+fn closest_output(
+    e: &DruidExpectation,
+    tx_source: &BTreeSet<(String, &String, &Asset)>,
+) -> Option<ClosestOutput> {
+    let holds = |asset: &Asset, predicate: &dyn Fn(&Asset) -> bool| match asset {
+        Asset::Bundle(bundle) => predicate(asset) || bundle.components().any(|c| predicate(&c)),
+        _ => predicate(asset),
+    };
+    let is_met = |asset: &Asset| e.is_met_by(asset);
+    let is_comparable = |asset: &Asset| asset.is_greater_or_equal_to(&e.asset).is_some();
+
+    tx_source
+        .iter()
+        .filter(|(from, _, _)| *from == e.from)
+        .filter_map(|(_, to, asset)| {
+            let mismatch = match (**to == e.to, holds(asset, &is_met)) {
+                (true, true) => ExpectationMismatch::AlreadyMatched,
+                (true, false) if holds(asset, &is_comparable) => ExpectationMismatch::WrongAmount,
+                (true, false) => ExpectationMismatch::WrongAsset,
+                (false, true) => ExpectationMismatch::WrongAddress,
+                (false, false) => return None,
+            };
+            Some(ClosestOutput {
+                to: (*to).clone(),
+                asset: (*asset).clone(),
+                mismatch,
+            })
+        })
+        .min_by_key(|closest| closest.mismatch)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::{DRUID_LENGTH, DRUID_PREFIX};
+    use crate::crypto::sign_ed25519::{self as sign, Signature};
     use crate::primitives::asset::{Asset, DataAsset, TokenAmount};
-    use crate::primitives::druid::{DdeValues, DruidExpectation};
+    use crate::primitives::druid::{
+        DdeValues, DruidError, DruidExpectation, ExpectationComparison,
+    };
     use crate::primitives::transaction::*;
     use crate::utils::transaction_utils::*;
 
+    /// Util function to create the inputs of a participant spending `hash`
+    fn participant_tx_ins(hash: &str) -> Vec<TxIn> {
+        construct_payment_tx_ins(vec![TxConstructor {
+            previous_out: OutPoint::new(hash.to_owned(), 0),
+            signatures: vec![Signature::from_slice(&[0; 64]).unwrap()],
+            pub_keys: vec![sign::gen_keypair().0],
+            address_version: None,
+        }])
+    }
+
+    /// Util function to set the DRUID derived from the transactions on each of them
+    fn set_derived_druid(txs: &mut [Transaction]) -> String {
+        let participants: Vec<String> = txs
+            .iter()
+            .map(|tx| construct_tx_ins_address(&tx.inputs))
+            .collect();
+        let expects: Vec<DruidExpectation> = txs
+            .iter()
+            .flat_map(|tx| tx.druid_info.as_ref().unwrap().expectations.clone())
+            .collect();
+        let druid = Druid::generate(&participants, &expects).to_string();
+        for tx in txs {
+            tx.druid_info.as_mut().unwrap().druid = druid.clone();
+        }
+        druid
+    }
+
     /// Util function to create valid DDE asset tx's
-    fn create_dde_txs() -> Vec<Transaction> {
-        let druid = "VALUE".to_owned();
-        let tx_input = construct_payment_tx_ins(vec![]);
-        let from_addr = construct_tx_ins_address(&tx_input);
+    fn create_dde_txs() -> (String, Vec<Transaction>) {
+        let druid = String::new();
+        let (alice_input, bob_input) =
+            (participant_tx_ins("alice_tx"), participant_tx_ins("bob_tx"));
 
         // Alice
         let amount = TokenAmount(10);
@@ -87,73 +578,85 @@ mod tests {
             ..Default::default()
         };
 
-        // Expectations (from addresses the same due to empty TxIn)
+        // Expectations
         let expects = vec![
             DruidExpectation {
-                from: from_addr.clone(),
+                from: construct_tx_ins_address(&alice_input),
                 to: bob_addr,
                 asset: alice_asset,
+                ..Default::default()
             },
             DruidExpectation {
-                from: from_addr,
+                from: construct_tx_ins_address(&bob_input),
                 to: alice_addr,
                 asset: bob_asset,
+                ..Default::default()
             },
         ];
 
         // Txs
         let alice_tx = construct_dde_tx(
             druid.clone(),
-            tx_input.clone(),
+            alice_input,
             vec![token_tx_out],
             2,
             expects.clone(),
         );
 
-        let bob_tx = construct_dde_tx(druid, tx_input, vec![data_tx_out], 2, expects);
+        let bob_tx = construct_dde_tx(druid, bob_input, vec![data_tx_out], 2, expects);
 
-        vec![alice_tx, bob_tx]
+        let mut txs = vec![alice_tx, bob_tx];
+        (set_derived_druid(&mut txs), txs)
     }
 
     /// Util function to create valid receipt-based payment tx's
-    fn create_rb_payment_txs() -> (Transaction, Transaction) {
+    fn create_rb_payment_txs() -> (String, Transaction, Transaction) {
         // Arrange
         //
         let amount = TokenAmount(33);
         let payment = TokenAmount(11);
-        let druid = "VALUE".to_owned();
+        let druid = String::new();
 
-        let tx_input = construct_payment_tx_ins(vec![]);
-        let from_addr = construct_tx_ins_address(&tx_input);
+        let (send_input, recv_input) =
+            (participant_tx_ins("send_tx"), participant_tx_ins("recv_tx"));
 
         let alice_addr = "1111".to_owned();
         let bob_addr = "00000".to_owned();
 
         let sender_address_excess = "11112".to_owned();
 
+        // Both halves declare the expectations of the whole trade
+        let expectations = vec![
+            DruidExpectation {
+                from: construct_tx_ins_address(&recv_input),
+                to: alice_addr.clone(),
+                asset: Asset::receipt(1, Some("drs_tx_hash".to_owned()), None),
+                ..Default::default()
+            },
+            DruidExpectation {
+                from: construct_tx_ins_address(&send_input),
+                to: bob_addr.clone(),
+                asset: Asset::Token(payment),
+                ..Default::default()
+            },
+        ];
+
         // Act
         //
         let send_tx = {
-            let tx_ins = {
-                // constructors with enough money for amount and excess, caller responsibility.
-                construct_payment_tx_ins(vec![])
-            };
-            let excess_tx_out = TxOut::new_token_amount(sender_address_excess, amount - payment);
-
-            let expectation = DruidExpectation {
-                from: from_addr.clone(),
-                to: alice_addr.clone(),
-                asset: Asset::receipt(1, Some("drs_tx_hash".to_owned()), None),
-            };
+            let excess_tx_out = TxOut::new_token_amount(
+                sender_address_excess,
+                amount.checked_sub(payment).unwrap(),
+            );
 
             let mut tx = construct_rb_payments_send_tx(
-                tx_ins,
+                send_input,
                 Vec::new(),
                 bob_addr.clone(),
                 payment,
                 0,
                 druid.clone(),
-                vec![expectation],
+                expectations.clone(),
             );
 
             tx.outputs.push(excess_tx_out);
@@ -162,43 +665,61 @@ mod tests {
         };
 
         let recv_tx = {
-            let tx_ins = {
-                // constructors with enough money for amount and excess, caller responsibility.
-                let tx_ins_constructor = vec![];
-                construct_payment_tx_ins(tx_ins_constructor)
-            };
-            let expectation = DruidExpectation {
-                from: from_addr,
-                to: bob_addr,
-                asset: Asset::Token(payment),
-            };
-
             // create the sender that match the receiver.
             construct_rb_receive_payment_tx(
-                tx_ins,
+                recv_input,
                 Vec::new(),
                 alice_addr,
                 0,
                 druid,
-                vec![expectation],
+                expectations,
                 Some("drs_tx_hash".to_owned()),
             )
         };
 
-        (send_tx, recv_tx)
+        let mut txs = [send_tx, recv_tx];
+        let druid = set_derived_druid(&mut txs);
+        let [send_tx, recv_tx] = txs;
+        (druid, send_tx, recv_tx)
     }
 
     #[test]
     /// Checks that matching DDE transactions are verified as such by DDE verifier
     fn should_pass_matching_dde_tx_valid() {
-        let txs = create_dde_txs();
-        assert!(druid_expectations_are_met("VALUE", txs.iter()));
+        let (druid, txs) = create_dde_txs();
+        assert!(druid_expectations_are_met(&druid, txs.iter(), 0));
+    }
+
+    #[test]
+    /// Checks that legacy trades keep free-form DRUIDs, which later versions reject
+    fn should_pass_legacy_dde_tx_free_form_druid() {
+        let (derived, mut txs) = create_dde_txs();
+        let druid = "VALUE".to_owned();
+        for tx in &mut txs {
+            tx.druid_info.as_mut().unwrap().druid = druid.clone();
+        }
+        assert_eq!(
+            validate_druid_txs(&druid, txs.iter()),
+            Err(DruidValidationError::InvalidDruid)
+        );
+
+        for tx in &mut txs {
+            tx.version = NETWORK_VERSION_LEGACY_DDE;
+        }
+        assert!(druid_is_valid(&druid, txs.iter()));
+        assert_eq!(validate_druid_txs(&druid, txs.iter()), Ok(()));
+        assert!(druid_expectations_are_met(&druid, txs.iter(), 0));
+
+        // Legacy transactions cannot carry a trade with current ones
+        txs[0].version = NETWORK_VERSION_LEGACY_DDE + 1;
+        assert!(!druid_is_valid(&druid, txs.iter()));
+        assert!(!druid_is_valid(&derived, txs.iter()));
     }
 
     #[test]
     /// Checks that DDE transactions with non-matching expects fail
     fn should_fail_dde_tx_value_expect_mismatch() {
-        let mut txs = create_dde_txs();
+        let (druid, mut txs) = create_dde_txs();
         let mut change_tx = txs.pop().unwrap();
         let orig_tx = txs[0].clone();
 
@@ -208,32 +729,35 @@ mod tests {
 
         // New druid info
         let nm_druid_info = DdeValues {
-            druid: "VALUE".to_owned(),
+            druid: druid.clone(),
             participants: 2,
             expectations: expects,
+            expiry_height: None,
         };
         change_tx.druid_info = Some(nm_druid_info);
 
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![orig_tx, change_tx].iter()
+            &druid,
+            vec![orig_tx, change_tx].iter(),
+            0
         ));
     }
 
     #[test]
     /// Checks that matching receipt-based payments are verified as such by the DDE verifier
     fn should_pass_matching_rb_payment_valid() {
-        let (send_tx, recv_tx) = create_rb_payment_txs();
+        let (druid, send_tx, recv_tx) = create_rb_payment_txs();
         assert!(druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &druid,
+            vec![send_tx, recv_tx].iter(),
+            0
         ));
     }
 
     #[test]
     /// Checks that receipt-based payments with non-matching DRUIDs fail
     fn should_fail_rb_payment_druid_mismatch() {
-        let (send_tx, mut recv_tx) = create_rb_payment_txs();
+        let (druid, send_tx, mut recv_tx) = create_rb_payment_txs();
 
         let mut druid_info = recv_tx.druid_info.unwrap();
         druid_info.druid = "Not_VAlue".to_owned();
@@ -241,47 +765,457 @@ mod tests {
 
         // Non-matching druid
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &druid,
+            vec![send_tx, recv_tx].iter(),
+            0
         ));
     }
 
     #[test]
     /// Checks that receipt-based payments with non-matching addresses fail
     fn should_fail_rb_payment_addr_mismatch() {
-        let (send_tx, mut recv_tx) = create_rb_payment_txs();
+        let (druid, send_tx, mut recv_tx) = create_rb_payment_txs();
         recv_tx.outputs[0].script_public_key = Some("11145".to_string());
 
         // Non-matching address expectation
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &druid,
+            vec![send_tx, recv_tx].iter(),
+            0
         ));
     }
 
     #[test]
     /// Checks that receipt-based payments with non-matching value expectations fail
     fn should_fail_rb_payment_value_expect_mismatch() {
-        let (mut send_tx, recv_tx) = create_rb_payment_txs();
+        let (druid, mut send_tx, recv_tx) = create_rb_payment_txs();
         send_tx.outputs[0].value = Asset::token_u64(10);
 
         // Non-matching address expectation
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &druid,
+            vec![send_tx, recv_tx].iter(),
+            0
         ));
     }
 
     #[test]
     /// Checks that receipt-based payments with non-matching DRS expectations fail
     fn should_fail_rb_payment_drs_expect_mismatch() {
-        let (send_tx, mut recv_tx) = create_rb_payment_txs();
+        let (druid, send_tx, mut recv_tx) = create_rb_payment_txs();
         recv_tx.outputs[0].value = Asset::receipt(1, Some("invalid_drs_tx_hash".to_string()), None);
 
         // Non-matching address expectation
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &druid,
+            vec![send_tx, recv_tx].iter(),
+            0
+        ));
+    }
+
+    #[test]
+    /// Checks that token and receipt expectations can be met by a bundled output
+    fn should_pass_dde_tx_bundle_expectations() {
+        let tx_input = participant_tx_ins("bundle_tx");
+        let from_addr = construct_tx_ins_address(&tx_input);
+        let bob_addr = "22222".to_owned();
+        let receipts = vec![("drs_tx_hash".to_owned(), 2)].into_iter().collect();
+        let bundle_tx_out =
+            TxOut::new_asset(bob_addr.clone(), Asset::bundle(TokenAmount(10), receipts));
+        let expect = |asset| DruidExpectation {
+            from: from_addr.clone(),
+            to: bob_addr.clone(),
+            asset,
+            ..Default::default()
+        };
+        let bundle_tx = |expects| {
+            let mut txs = [construct_dde_tx(
+                String::new(),
+                tx_input.clone(),
+                vec![bundle_tx_out.clone()],
+                1,
+                expects,
+            )];
+            (set_derived_druid(&mut txs), txs)
+        };
+
+        let (met_druid, met) = bundle_tx(vec![
+            expect(Asset::Token(TokenAmount(10))),
+            expect(Asset::receipt(2, Some("drs_tx_hash".to_owned()), None)),
+        ]);
+        let (unmet_druid, unmet) = bundle_tx(vec![expect(Asset::receipt(
+            1,
+            Some("drs_tx_hash".to_owned()),
+            None,
+        ))]);
+
+        assert!(druid_expectations_are_met(&met_druid, met.iter(), 0));
+        assert!(!druid_expectations_are_met(&unmet_druid, unmet.iter(), 0));
+    }
+
+    #[test]
+    /// Checks that derived DRUIDs have the expected format and parse back
+    fn should_parse_derived_druid() {
+        let participants = vec!["alice".to_owned(), "bob".to_owned()];
+        let expects = vec![DruidExpectation::default()];
+        let druid = Druid::derive(&participants, &expects, [7; 8]);
+        let encoded = druid.to_string();
+
+        assert_eq!(encoded.len(), DRUID_LENGTH);
+        assert!(encoded.starts_with(DRUID_PREFIX));
+        assert_eq!(encoded.parse(), Ok(druid));
+        assert_eq!(
+            druid,
+            Druid::derive(participants.iter().rev(), &expects, [7; 8])
+        );
+        assert_ne!(druid, Druid::derive(&participants, &expects, [8; 8]));
+        assert_ne!(
+            Druid::generate(&participants, &expects),
+            Druid::generate(&participants, &expects)
+        );
+
+        assert_eq!("VALUE".parse::<Druid>(), Err(DruidError::InvalidPrefix));
+        assert_eq!(
+            encoded[..DRUID_LENGTH - 2].parse::<Druid>(),
+            Err(DruidError::InvalidLength)
+        );
+        assert_eq!(
+            format!("{}zz", &encoded[..DRUID_LENGTH - 2]).parse::<Druid>(),
+            Err(DruidError::InvalidHex)
+        );
+        assert_eq!(
+            format!("{}FF", &encoded[..DRUID_LENGTH - 2]).parse::<Druid>(),
+            Err(DruidError::InvalidHex)
+        );
+    }
+
+    #[test]
+    /// Checks that DRUIDs are validated against the transactions they are derived from
+    fn should_validate_druid_derivation() {
+        let (alice_ins, bob_ins) = (participant_tx_ins("alice_tx"), participant_tx_ins("bob_tx"));
+        let (alice_addr, bob_addr) = (
+            construct_tx_ins_address(&alice_ins),
+            construct_tx_ins_address(&bob_ins),
+        );
+        let expects = vec![
+            DruidExpectation {
+                from: alice_addr.clone(),
+                to: "bob_receive".to_owned(),
+                asset: Asset::token_u64(10),
+                ..Default::default()
+            },
+            DruidExpectation {
+                from: bob_addr.clone(),
+                to: "alice_receive".to_owned(),
+                asset: Asset::receipt(1, Some("drs_tx_hash".to_owned()), None),
+                ..Default::default()
+            },
+        ];
+        let druid = Druid::generate(&[alice_addr, bob_addr], &expects).to_string();
+        let txs = |druid: &str, participants, expects: &[DruidExpectation]| {
+            vec![
+                construct_dde_tx(
+                    druid.to_owned(),
+                    alice_ins.clone(),
+                    vec![TxOut::new_token_amount(
+                        "bob_receive".to_owned(),
+                        TokenAmount(10),
+                    )],
+                    participants,
+                    expects.to_vec(),
+                ),
+                construct_dde_tx(
+                    druid.to_owned(),
+                    bob_ins.clone(),
+                    vec![TxOut::new_asset(
+                        "alice_receive".to_owned(),
+                        Asset::receipt(1, Some("drs_tx_hash".to_owned()), None),
+                    )],
+                    participants,
+                    expects.to_vec(),
+                ),
+            ]
+        };
+
+        let valid = txs(&druid, 2, &expects);
+        let wrong_participants = txs(&druid, 3, &expects);
+        let changed_expects = txs(&druid, 2, &expects[..1]);
+        let legacy = txs("VALUE", 2, &expects);
+
+        assert!(druid_is_valid(&druid, valid.iter()));
+        assert!(druid_expectations_are_met(&druid, valid.iter(), 0));
+        assert!(!druid_is_valid(&druid, valid.iter().take(1)));
+        assert!(!druid_is_valid(&druid, wrong_participants.iter()));
+        assert!(!druid_is_valid(&druid, changed_expects.iter()));
+        assert!(!druid_is_valid("VALUE", legacy.iter()));
+        assert_eq!(
+            validate_druid_txs("VALUE", legacy.iter()),
+            Err(DruidValidationError::InvalidDruid)
+        );
+    }
+
+    #[test]
+    /// Checks that range expectations are each met by a distinct output
+    fn should_match_range_expectations() {
+        use ExpectationComparison::*;
+
+        let tx_input = participant_tx_ins("range_tx");
+        let from_addr = construct_tx_ins_address(&tx_input);
+        let bob_addr = "22222".to_owned();
+        let receipt = |drs: &str| Asset::receipt(3, Some(drs.to_owned()), None);
+        let tx_outs = vec![
+            TxOut::new_token_amount(bob_addr.clone(), TokenAmount(10)),
+            TxOut::new_token_amount(bob_addr.clone(), TokenAmount(4)),
+            TxOut::new_asset(bob_addr.clone(), receipt("drs_a")),
+        ];
+        let expect = |asset, comparison| DruidExpectation {
+            from: from_addr.clone(),
+            to: bob_addr.clone(),
+            asset,
+            comparison,
+        };
+        let is_met = |expects| {
+            let mut txs = [construct_dde_tx(
+                String::new(),
+                tx_input.clone(),
+                tx_outs.clone(),
+                1,
+                expects,
+            )];
+            let druid = set_derived_druid(&mut txs);
+            druid_expectations_are_met(&druid, txs.iter(), 0)
+        };
+
+        assert!(is_met(vec![
+            expect(Asset::token_u64(5), AtLeast),
+            expect(Asset::token_u64(5), AtMost),
+            expect(Asset::receipt(1, Some("drs_a".to_owned()), None), Any),
+        ]));
+        assert!(is_met(vec![
+            expect(Asset::token_u64(3), AtLeast),
+            expect(Asset::token_u64(4), Exact),
+        ]));
+        assert!(is_met(vec![expect(Asset::token_u64(0), Any)]));
+
+        // Outputs cannot meet more than one expectation
+        assert!(!is_met(vec![
+            expect(Asset::token_u64(5), AtLeast),
+            expect(Asset::token_u64(6), AtLeast),
+        ]));
+        assert!(!is_met(vec![expect(Asset::token_u64(11), AtLeast)]));
+        assert!(!is_met(vec![expect(Asset::token_u64(3), AtMost)]));
+        assert!(!is_met(vec![expect(receipt("drs_b"), Any)]));
+        assert!(!is_met(vec![
+            expect(receipt("drs_a"), AtMost),
+            expect(receipt("drs_a"), Any)
+        ]));
+    }
+
+    #[test]
+    /// Checks that DRUID transactions are only valid together before their expiry
+    fn should_fail_expired_dde_txs() {
+        let (druid, txs) = create_dde_txs();
+        let expiring = |expiry_height| {
+            let mut txs = txs.clone();
+            for tx in &mut txs {
+                tx.druid_info.as_mut().unwrap().expiry_height = expiry_height;
+            }
+            txs
+        };
+        let expiring_txs = expiring(Some(100));
+        let mut mismatched_txs = expiring_txs.clone();
+        mismatched_txs[1].druid_info.as_mut().unwrap().expiry_height = Some(200);
+
+        assert!(druid_is_unexpired(&druid, txs.iter(), u64::MAX));
+        assert!(druid_is_unexpired(&druid, expiring_txs.iter(), 99));
+        assert!(!druid_is_unexpired(&druid, expiring_txs.iter(), 100));
+        assert!(!druid_is_unexpired(&druid, mismatched_txs.iter(), 0));
+        assert!(!druid_is_unexpired(&druid, expiring(Some(0)).iter(), 0));
+
+        assert_eq!(
+            validate_druid_txs_at(&druid, expiring_txs.iter(), 99),
+            Ok(())
+        );
+        assert_eq!(
+            validate_druid_txs_at(&druid, expiring_txs.iter(), 100),
+            Err(DruidValidationError::Expired)
+        );
+        assert!(druid_expectations_are_met(&druid, expiring_txs.iter(), 99));
+        assert!(!druid_expectations_are_met(
+            &druid,
+            mismatched_txs.iter(),
+            0
         ));
     }
+
+    /// Util function to create the transactions of a trade where Alice pays Bob,
+    /// Bob pays Carol and Carol pays Alice 10 tokens each
+    fn create_three_party_txs() -> (String, Vec<String>, Vec<Transaction>) {
+        let ins: Vec<Vec<TxIn>> = ["alice_tx", "bob_tx", "carol_tx"]
+            .iter()
+            .map(|hash| participant_tx_ins(hash))
+            .collect();
+        let addrs: Vec<String> = ins.iter().map(|i| construct_tx_ins_address(i)).collect();
+
+        let expects: Vec<DruidExpectation> = (0..3)
+            .map(|i| DruidExpectation {
+                from: addrs[i].clone(),
+                to: addrs[(i + 1) % 3].clone(),
+                asset: Asset::token_u64(10),
+                ..Default::default()
+            })
+            .collect();
+        let mut txs: Vec<Transaction> = (0..3)
+            .map(|i| {
+                construct_dde_tx(
+                    String::new(),
+                    ins[i].clone(),
+                    vec![TxOut::new_token_amount(
+                        addrs[(i + 1) % 3].clone(),
+                        TokenAmount(10),
+                    )],
+                    3,
+                    expects.clone(),
+                )
+            })
+            .collect();
+
+        (set_derived_druid(&mut txs), addrs, txs)
+    }
+
+    #[test]
+    /// Checks that a trade needs one transaction per declared participant
+    fn should_fail_dde_txs_participant_mismatch() {
+        let (druid, addrs, txs) = create_three_party_txs();
+
+        let mut conflicting = txs.clone();
+        conflicting[2]
+            .druid_info
+            .as_mut()
+            .unwrap()
+            .expectations
+            .pop();
+        let mut miscounted = txs.clone();
+        for tx in &mut miscounted {
+            tx.druid_info.as_mut().unwrap().participants = 4;
+        }
+        let duplicated = [&txs[0], &txs[1], &txs[1]];
+
+        assert_eq!(validate_druid_txs(&druid, txs.iter()), Ok(()));
+        assert_eq!(
+            validate_druid_txs(&druid, txs.iter().take(2)),
+            Err(DruidValidationError::MissingParticipant(addrs[2].clone()))
+        );
+        assert_eq!(
+            validate_druid_txs(&druid, duplicated.iter().copied()),
+            Err(DruidValidationError::MissingParticipant(addrs[2].clone()))
+        );
+        assert_eq!(
+            validate_druid_txs(&druid, conflicting.iter()),
+            Err(DruidValidationError::ConflictingValues)
+        );
+        assert_eq!(
+            validate_druid_txs(&druid, miscounted.iter()),
+            Err(DruidValidationError::ParticipantCount {
+                expected: 4,
+                found: 3
+            })
+        );
+        assert_eq!(
+            validate_druid_txs("OTHER", txs.iter()),
+            Err(DruidValidationError::NoTransactions)
+        );
+        assert!(!druid_expectations_are_met(&druid, txs.iter().take(2), 0));
+    }
+
+    #[test]
+    /// Checks that unmet expectations are reported with their closest output
+    fn should_report_unmet_expectations() {
+        let (druid, addrs, txs) = create_three_party_txs();
+        let with_output = |i: usize, to: &str, asset| {
+            let mut tx = txs[i].clone();
+            tx.outputs = vec![TxOut::new_asset(to.to_owned(), asset)];
+            tx
+        };
+        let report = |txs: &[Transaction]| druid_expectations_report(&druid, txs.iter());
+        let unmet = |report: &DruidReport| -> Vec<(String, ExpectationStatus)> {
+            report
+                .unmet()
+                .map(|r| (r.expectation.from.clone(), r.status.clone()))
+                .collect()
+        };
+        let closest = |to: &String, asset, mismatch| {
+            ExpectationStatus::Unmet(Some(ClosestOutput {
+                to: to.clone(),
+                asset,
+                mismatch,
+            }))
+        };
+
+        let complete = report(&txs);
+        let wrong_amount = report(&[
+            with_output(0, &addrs[1], Asset::token_u64(9)),
+            txs[1].clone(),
+            txs[2].clone(),
+        ]);
+        let wrong_address_and_asset = report(&[
+            txs[0].clone(),
+            with_output(1, "other", Asset::token_u64(10)),
+            with_output(
+                2,
+                &addrs[0],
+                Asset::receipt(10, Some("drs".to_owned()), None),
+            ),
+        ]);
+        let missing = report(&txs[..2]);
+
+        assert!(complete.is_met());
+        assert_eq!(complete.expectations.len(), 3);
+        assert_eq!(
+            unmet(&wrong_amount),
+            vec![(
+                addrs[0].clone(),
+                closest(
+                    &addrs[1],
+                    Asset::token_u64(9),
+                    ExpectationMismatch::WrongAmount
+                )
+            )]
+        );
+        assert!(wrong_amount.to_string().contains("(wrong amount)"));
+
+        let mut expected = vec![
+            (
+                addrs[1].clone(),
+                closest(
+                    &"other".to_owned(),
+                    Asset::token_u64(10),
+                    ExpectationMismatch::WrongAddress,
+                ),
+            ),
+            (
+                addrs[2].clone(),
+                closest(
+                    &addrs[0],
+                    Asset::receipt(10, Some("drs".to_owned()), None),
+                    ExpectationMismatch::WrongAsset,
+                ),
+            ),
+        ];
+        let mut actual = unmet(&wrong_address_and_asset);
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        actual.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(actual, expected);
+
+        assert!(!missing.is_met());
+        assert_eq!(missing.missing_participants, vec![addrs[2].clone()]);
+        assert_eq!(
+            unmet(&missing),
+            vec![(addrs[2].clone(), ExpectationStatus::Unmet(None))]
+        );
+        assert!(missing.to_string().contains(&format!(
+            "Missing transaction from participant {}",
+            addrs[2]
+        )));
+    }
 }
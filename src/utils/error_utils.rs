@@ -67,12 +67,20 @@ pub fn error_num_signatures(op: &str) {
     error!("{op}: {ERROR_NUM_SIGNATURES}")
 }
 
+pub fn error_hash_mismatch(op: &str) {
+    error!("{op}: {ERROR_HASH_MISMATCH}")
+}
+
 // script
 
 pub fn error_max_script_size() {
     error!("{ERROR_MAX_SCRIPT_SIZE}")
 }
 
+pub fn error_legacy_item() {
+    error!("{ERROR_LEGACY_ITEM}")
+}
+
 pub fn error_max_stack_size() {
     error!("{ERROR_MAX_STACK_SIZE}")
 }
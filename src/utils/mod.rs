@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use crate::constants::D_DISPLAY_PLACES;
+use crate::constants::{D_DISPLAY_DECIMALS, D_DISPLAY_PLACES_U64, TOTAL_TOKENS};
 use crate::primitives::asset::TokenAmount;
 
 // ------- MODS ------- //
@@ -16,19 +16,36 @@ pub mod transaction_utils;
 /// Determines whether the passed value is within bounds of
 /// available tokens in the supply.
 ///
-/// TODO: Currently placeholder, needs to be filled in once requirements known
-pub fn is_valid_amount(_value: &TokenAmount) -> bool {
-    true
+/// ### Arguments
+///
+/// * `value`   - Value to check
+pub fn is_valid_amount(value: &TokenAmount) -> bool {
+    value.0 <= TOTAL_TOKENS
 }
 
-/// Formats an incoming value to be displayed
+/// Formats an incoming value to be displayed, using integer arithmetic only.
+///
+/// The fractional part is rounded half up to `D_DISPLAY_DECIMALS` digits,
+/// which is precise enough for the result to parse back to the same value
 ///
 /// ### Arguments
 ///
 /// * `value`   - Value to format for display
 pub fn format_for_display(value: &u64) -> String {
-    let value_f64 = *value as f64;
-    (value_f64 / D_DISPLAY_PLACES).to_string()
+    let scale = 10_u64.pow(D_DISPLAY_DECIMALS);
+    let mut whole = value / D_DISPLAY_PLACES_U64;
+    let remainder = value % D_DISPLAY_PLACES_U64;
+    let mut fraction = (remainder * scale + D_DISPLAY_PLACES_U64 / 2) / D_DISPLAY_PLACES_U64;
+    if fraction == scale {
+        whole += 1;
+        fraction = 0;
+    }
+    if fraction == 0 {
+        return whole.to_string();
+    }
+
+    let digits = format!("{:0width$}", fraction, width = D_DISPLAY_DECIMALS as usize);
+    format!("{}.{}", whole, digits.trim_end_matches('0'))
 }
 
 /// Create a single `BTreeMap<E, T>` struct from two `BTreeMap<E, T>` structs
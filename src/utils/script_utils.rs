@@ -4,30 +4,42 @@ use crate::crypto::sha3_256;
 use crate::crypto::sign_ed25519::{
     self as sign, PublicKey, Signature, ED25519_PUBLIC_KEY_LEN, ED25519_SIGNATURE_LEN,
 };
-use crate::primitives::asset::{Asset, AssetValues, ReceiptAsset, TokenAmount};
+use crate::primitives::asset::{
+    Asset, AssetValues, AssetValuesError, BalanceDiff, CollectionDefinition, DustPolicy,
+    IssuanceDefinition, IssuedAsset, ItemAsset, ReceiptAsset, ReceiptMetadata, TokenAmount,
+};
 use crate::primitives::druid::DruidExpectation;
 use crate::primitives::transaction::*;
 use crate::script::interface_ops::*;
-use crate::script::lang::{ConditionStack, Script, Stack};
+use crate::script::lang::{ConditionStack, Script, ScriptLimits, Stack};
 use crate::script::{OpCodes, StackEntry};
 use crate::utils::error_utils::*;
+use crate::utils::is_valid_amount;
 use crate::utils::transaction_utils::{
-    construct_address, construct_tx_in_signable_asset_hash, construct_tx_in_signable_hash,
+    construct_address, construct_tx_hash, construct_tx_in_signable_asset_hash,
+    construct_tx_in_signable_hash, is_burn_tx_out,
 };
 use bincode::serialize;
 use bytes::Bytes;
 use hex::encode;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::thread::current;
 use tracing::{debug, error, info, trace};
 
-use super::transaction_utils::construct_p2sh_address;
+use super::transaction_utils::{
+    construct_p2sh_address, construct_p2sh_address_for, p2sh_address_script_version,
+};
 
 /// Verifies that all incoming transactions are allowed to be spent. Returns false if a single
 /// transaction doesn't verify
 ///
 /// TODO: Currently assumes p2pkh and p2sh, abstract to all tx types
 ///
+/// Re-issuances of issued tokens and item mints are always rejected here, as their
+/// validity depends on the token's issuance state or the collection's minted serials.
+/// Callers MUST validate them with `tx_is_valid_reissuance` or `tx_is_valid_item_mint`
+///
 /// ### Arguments
 ///
 /// * `tx`  - Transaction to verify
@@ -35,7 +47,37 @@ pub fn tx_is_valid<'a>(
     tx: &Transaction,
     is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
 ) -> bool {
+    tx_is_valid_with_limits(tx, Default::default(), is_in_utxo)
+}
+
+/// Verifies that all incoming transactions are allowed to be spent, executing
+/// their scripts under the given limits instead of the consensus ones.
+/// Re-issuances and item mints are rejected as they are by `tx_is_valid`
+///
+/// ### Arguments
+///
+/// * `tx`      - Transaction to verify
+/// * `limits`  - Script limits to enforce
+pub fn tx_is_valid_with_limits<'a>(
+    tx: &Transaction,
+    limits: ScriptLimits,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+) -> bool {
+    // Re-issuances MUST be checked against the issuance state by `tx_is_valid_reissuance`
+    if tx.is_reissuance_tx() {
+        error!("RE-ISSUANCE NEEDS ITS ISSUANCE STATE CHECKED");
+        return false;
+    }
+
+    // Item mints MUST be checked against the minted serials by `tx_is_valid_item_mint`
+    if tx.is_item_mint_tx() {
+        error!("ITEM MINT NEEDS ITS COLLECTION STATE CHECKED");
+        return false;
+    }
+
     let mut tx_ins_spent: AssetValues = Default::default();
+    // Interpreter stack shared by all inputs to avoid reallocating per script
+    let mut stack = Stack::with_limits(limits);
     // TODO: Add support for `Data` asset variant
     // `Receipt` assets MUST have an a DRS value associated with them when they are getting on-spent
     if tx.outputs.iter().any(|out| {
@@ -46,11 +88,57 @@ pub fn tx_is_valid<'a>(
         return false;
     }
 
+    // `Issued` assets MUST reference their genesis and not redefine the token when on-spent
+    if tx.outputs.iter().any(|out| match &out.value {
+        Asset::Issued(issued) => issued.genesis_hash.is_none() || issued.definition.is_some(),
+        _ => false,
+    }) {
+        error!("ON-SPENDING NEEDS EMPTY DEFINITION AND NON-EMPTY GENESIS HASH");
+        return false;
+    }
+
+    // `Item` assets MUST reference their collection and carry no mint-only fields when on-spent
+    if tx.outputs.iter().any(|out| match &out.value {
+        Asset::Item(item) => {
+            item.collection_hash.is_none() || item.definition.is_some() || item.metadata.is_some()
+        }
+        _ => false,
+    }) {
+        error!("ON-SPENDING NEEDS EMPTY DEFINITION AND METADATA AND NON-EMPTY COLLECTION HASH");
+        return false;
+    }
+
+    // `Bundle` assets MUST hold something and list no empty receipt amounts
+    if tx.outputs.iter().any(|out| match &out.value {
+        Asset::Bundle(bundle) => bundle.is_empty() || bundle.receipts.values().any(|a| *a == 0),
+        _ => false,
+    }) {
+        error!("BUNDLES NEED NON-EMPTY CONTENTS");
+        return false;
+    }
+
+    // DDE values MUST be encodable in the layout of the transaction's version
+    if matches!(&tx.druid_info, Some(druid_info) if !druid_info.is_encodable_in(tx.version)) {
+        error!("DDE VALUES NEED A VERSION ENCODING THEIR COMPARISONS AND EXPIRY");
+        return false;
+    }
+
+    // Burn outputs destroy their value immediately, so they cannot be time locked or empty receipts
+    if tx
+        .outputs
+        .iter()
+        .filter(|out| is_burn_tx_out(out))
+        .any(|out| out.locktime != 0 || (out.value.is_receipt() && out.value.receipt_amount() == 0))
+    {
+        error!("BURN OUTPUTS NEED NO LOCKTIME AND NON-EMPTY RECEIPTS");
+        return false;
+    }
+
     for tx_in in &tx.inputs {
         // Ensure the transaction is in the `UTXO` set
-        let tx_out_point = tx_in.previous_out.as_ref().unwrap().clone();
+        let tx_out_point = tx_in.previous_out.as_ref().unwrap();
 
-        let tx_out = if let Some(tx_out) = is_in_utxo(&tx_out_point) {
+        let tx_out = if let Some(tx_out) = is_in_utxo(tx_out_point) {
             tx_out
         } else {
             error!("UTXO DOESN'T CONTAIN THIS TX");
@@ -59,12 +147,13 @@ pub fn tx_is_valid<'a>(
 
         // At this point `TxIn` will be valid
         let tx_out_pk = tx_out.script_public_key.as_ref();
-        let tx_out_hash = construct_tx_in_signable_hash(&tx_out_point);
+        let tx_out_hash = construct_tx_in_signable_hash(tx_out_point);
 
         if let Some(pk) = tx_out_pk {
             // Check will need to include other signature types here
-            if !tx_has_valid_p2pkh_sig(&tx_in.script_signature, &tx_out_hash, pk)
-                && !tx_has_valid_p2sh_script(&tx_in.script_signature, pk)
+            let script = &tx_in.script_signature;
+            if !tx_has_valid_p2pkh_sig(script, &tx_out_hash, pk, &mut stack)
+                && !p2sh_script_is_valid(script, pk, &mut stack)
             {
                 return false;
             }
@@ -72,8 +161,16 @@ pub fn tx_is_valid<'a>(
             return false;
         }
 
-        let asset = tx_out.value.clone().with_fixed_hash(&tx_out_point);
-        tx_ins_spent.update_add(&asset);
+        let asset = tx_out.value.clone().with_fixed_hash(tx_out_point);
+        if let Err(e) = tx_ins_spent.update_add(&asset) {
+            error!("INVALID INPUT SUM: {}", e);
+            return false;
+        }
+    }
+
+    if !is_valid_amount(&tx_ins_spent.tokens) {
+        error!("INPUT SUM EXCEEDS TOTAL TOKENS");
+        return false;
     }
 
     tx_outs_are_valid(&tx.outputs, tx_ins_spent)
@@ -100,37 +197,336 @@ pub fn tx_outs_are_valid(tx_outs: &[TxOut], tx_ins_spent: AssetValues) -> bool {
             }
         }
 
-        tx_outs_spent.update_add(&tx_out.value);
+        if let Err(e) = tx_outs_spent.update_add(&tx_out.value) {
+            trace!("Invalid output sum: {}", e);
+            return false;
+        }
+    }
+
+    if !is_valid_amount(&tx_outs_spent.tokens) {
+        trace!("Output sum exceeds total tokens");
+        return false;
     }
 
     // Ensure that the `TxIn`s correlate with the `TxOut`s
-    tx_outs_spent.is_equal(&tx_ins_spent)
+    if !tx_outs_spent.is_equal(&tx_ins_spent) {
+        trace!(
+            "Inputs and outputs are unbalanced: {}",
+            tx_ins_spent.balance_diff(&tx_outs_spent)
+        );
+        return false;
+    }
+    true
+}
+
+/// Verifies that a transaction is valid and standard, i.e. that it may be relayed.
+/// Standardness is stricter than validity: blocks may still contain non-standard
+/// transactions
+///
+/// ### Arguments
+///
+/// * `tx`      - Transaction to verify
+/// * `policy`  - Dust thresholds to enforce on the outputs
+pub fn tx_is_standard<'a>(
+    tx: &Transaction,
+    policy: &DustPolicy,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+) -> bool {
+    tx_outs_are_standard(&tx.outputs, policy) && tx_is_valid(tx, is_in_utxo)
+}
+
+/// Verifies that none of the outgoing `TxOut`s are dust. Burn outputs are exempt,
+/// as they are destroyed rather than added to the UTXO set
+///
+/// ### Arguments
+///
+/// * `tx_outs` - `TxOut`s to verify
+/// * `policy`  - Dust thresholds to enforce
+pub fn tx_outs_are_standard(tx_outs: &[TxOut], policy: &DustPolicy) -> bool {
+    for tx_out in tx_outs.iter().filter(|tx_out| !is_burn_tx_out(tx_out)) {
+        if policy.is_dust(&tx_out.value) {
+            trace!("Output below dust threshold: {:?}", tx_out.value);
+            return false;
+        }
+    }
+    true
+}
+
+/// Errors raised when computing the balance of a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxBalanceError {
+    MissingInput(OutPoint),
+    InvalidSum(AssetValuesError),
+}
+
+impl fmt::Display for TxBalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingInput(out_point) => {
+                write!(f, "Input {out_point:?} is not in the UTXO set")
+            }
+            Self::InvalidSum(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Computes, per asset, the input and output totals of a transaction and the
+/// resulting surplus or deficit, without checking any scripts
+///
+/// ### Arguments
+///
+/// * `tx`          - Transaction to compute the balance of
+/// * `is_in_utxo`  - UTXO view to look up the spent outputs in
+pub fn tx_balance_diff<'a>(
+    tx: &Transaction,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+) -> Result<BalanceDiff, TxBalanceError> {
+    let mut tx_ins_spent: AssetValues = Default::default();
+    for tx_out_point in tx
+        .inputs
+        .iter()
+        .filter_map(|tx_in| tx_in.previous_out.as_ref())
+    {
+        let tx_out = is_in_utxo(tx_out_point)
+            .ok_or_else(|| TxBalanceError::MissingInput(tx_out_point.clone()))?;
+        let asset = tx_out.value.clone().with_fixed_hash(tx_out_point);
+        tx_ins_spent
+            .update_add(&asset)
+            .map_err(TxBalanceError::InvalidSum)?;
+    }
+
+    let mut tx_outs_spent: AssetValues = Default::default();
+    for tx_out in &tx.outputs {
+        tx_outs_spent
+            .update_add(&tx_out.value)
+            .map_err(TxBalanceError::InvalidSum)?;
+    }
+
+    Ok(tx_ins_spent.balance_diff(&tx_outs_spent))
 }
 
 /// Checks whether a create transaction has a valid input script
 ///
+/// Re-issuance of an existing issued token is not a standalone create: it must be
+/// checked with `tx_is_valid_reissuance` against the token's issuance state
+///
 /// ### Arguments
 ///
 /// * `script`      - Script to validate
 /// * `asset`       - Asset to be created
-pub fn tx_has_valid_create_script(script: &Script, asset: &Asset) -> bool {
-    let mut it = script.stack.iter();
-    let asset_hash = construct_tx_in_signable_asset_hash(asset);
+/// * `tx_version`  - Version of the create transaction
+pub fn tx_has_valid_create_script(script: &Script, asset: &Asset, tx_version: usize) -> bool {
+    if asset.is_bundle() {
+        trace!("Bundles cannot be created, only assembled from existing assets");
+        return false;
+    }
 
     if let Asset::Receipt(r) = asset {
-        if !receipt_has_valid_size(r) {
-            trace!("Receipt metadata is too large");
+        if !receipt_has_valid_metadata(r, tx_version) {
+            return false;
+        }
+    }
+
+    if let Asset::Item(item) = asset {
+        if !item_has_valid_metadata(item) {
+            return false;
+        }
+    }
+
+    let signer = match create_script_signer(script, asset, tx_version) {
+        Some(signer) => signer,
+        None => {
+            trace!("Invalid script for create: {:?}", script.stack,);
+            return false;
+        }
+    };
+
+    if let Asset::Issued(issued) = asset {
+        return issued_genesis_is_valid(issued, signer);
+    }
+
+    if let Asset::Item(item) = asset {
+        return collection_genesis_is_valid(item, signer);
+    }
+
+    true
+}
+
+/// Checks whether a transaction re-issuing an existing issued token is valid
+///
+/// The caller provides the token's definition from its genesis transaction and the
+/// total amount issued so far, which must reflect all previously accepted issuances.
+/// This is the only check accepting re-issuances: `tx_is_valid` rejects them all
+///
+/// ### Arguments
+///
+/// * `tx`              - Re-issuance transaction to verify
+/// * `definition`      - Definition of the issued token
+/// * `issued_supply`   - Total amount of the token issued so far
+pub fn tx_is_valid_reissuance(
+    tx: &Transaction,
+    definition: &IssuanceDefinition,
+    issued_supply: u64,
+) -> bool {
+    if !tx.is_reissuance_tx() {
+        trace!("Transaction is not a re-issuance");
+        return false;
+    }
+
+    let tx_in = &tx.inputs[0];
+    let asset = &tx.outputs[0].value;
+    let issued = match asset {
+        Asset::Issued(issued) if issued.definition.is_none() => issued,
+        _ => {
+            trace!("Re-issuance must not redefine the issued token");
+            return false;
+        }
+    };
+
+    match create_script_signer(&tx_in.script_signature, asset, tx.version) {
+        Some(signer) if *signer == definition.issuer => {}
+        _ => {
+            trace!("Re-issuance is not signed by the issuer");
+            return false;
+        }
+    }
+
+    match issued_supply.checked_add(issued.amount) {
+        Some(total) if definition.allows_supply(total, false) => true,
+        _ => {
+            trace!("Re-issuance exceeds the supply rule");
+            false
+        }
+    }
+}
+
+/// Checks whether a transaction minting an item into an existing collection is valid
+///
+/// The caller provides the collection's definition from its genesis transaction and the
+/// serials minted so far, which must reflect all previously accepted mints.
+/// This is the only check accepting item mints: `tx_is_valid` rejects them all
+///
+/// ### Arguments
+///
+/// * `tx`              - Item mint transaction to verify
+/// * `definition`      - Definition of the collection
+/// * `minted_serials`  - Serials already minted in the collection, including the genesis item
+pub fn tx_is_valid_item_mint(
+    tx: &Transaction,
+    definition: &CollectionDefinition,
+    minted_serials: &BTreeSet<u64>,
+) -> bool {
+    if !tx.is_item_mint_tx() {
+        trace!("Transaction is not an item mint");
+        return false;
+    }
+
+    let tx_in = &tx.inputs[0];
+    let asset = &tx.outputs[0].value;
+    let item = match asset {
+        Asset::Item(item) if item.definition.is_none() => item,
+        _ => {
+            trace!("Item mint must not redefine the collection");
+            return false;
+        }
+    };
+
+    if !item_has_valid_metadata(item) {
+        return false;
+    }
+
+    match create_script_signer(&tx_in.script_signature, asset, tx.version) {
+        Some(signer) if *signer == definition.creator => {}
+        _ => {
+            trace!("Item mint is not signed by the collection creator");
+            return false;
+        }
+    }
+
+    if minted_serials.contains(&item.serial) || !definition.allows_serial(item.serial) {
+        trace!("Item serial {} is not available", item.serial);
+        return false;
+    }
+
+    true
+}
+
+/// Proves that an item belongs to a collection, given the collection's genesis
+/// transaction and the transaction that minted the item. For the genesis item
+/// both transactions are the same
+///
+/// ### Arguments
+///
+/// * `item`        - Item to prove membership of
+/// * `genesis_tx`  - Genesis transaction of the collection
+/// * `mint_tx`     - Transaction that minted the item
+pub fn item_is_in_collection(
+    item: &ItemAsset,
+    genesis_tx: &Transaction,
+    mint_tx: &Transaction,
+) -> bool {
+    let genesis_hash = construct_tx_hash(genesis_tx);
+    if item.collection_hash.as_ref() != Some(&genesis_hash) {
+        trace!("Item does not reference the collection genesis");
+        return false;
+    }
+
+    let definition = match genesis_tx.get_create_asset() {
+        Some(Asset::Item(ItemAsset {
+            definition: Some(definition),
+            ..
+        })) => definition,
+        _ => {
+            trace!("Transaction is not a collection genesis");
             return false;
         }
+    };
+    let genesis_asset = &genesis_tx.outputs[0].value;
+    let genesis_script = &genesis_tx.inputs[0].script_signature;
+    if !tx_has_valid_create_script(genesis_script, genesis_asset, genesis_tx.version) {
+        return false;
     }
 
+    let minted = match mint_tx.get_create_asset() {
+        Some(asset) => asset
+            .clone()
+            .with_fixed_hash(&OutPoint::new(genesis_hash, 0)),
+        None => return false,
+    };
+    let is_minted_item = matches!(&minted, Asset::Item(minted) if minted.is_same_item(item));
+    if !is_minted_item {
+        trace!("Transaction did not mint the item");
+        return false;
+    }
+
+    // Items other than the genesis one must be minted by the collection creator
+    item.serial == 0 && mint_tx == genesis_tx
+        || (item.serial != 0 && tx_is_valid_item_mint(mint_tx, definition, &BTreeSet::new()))
+}
+
+/// Checks the shape and signature of a create script for the given asset,
+/// returning the public key that signed it
+///
+/// ### Arguments
+///
+/// * `script`      - Script to validate
+/// * `asset`       - Asset to be created
+/// * `tx_version`  - Version of the create transaction
+fn create_script_signer<'a>(
+    script: &'a Script,
+    asset: &Asset,
+    tx_version: usize,
+) -> Option<&'a PublicKey> {
+    let mut it = script.stack.iter();
+    let asset_hash = construct_tx_in_signable_asset_hash(asset, tx_version);
+
     if let (
         Some(StackEntry::Op(OpCodes::OP_CREATE)),
         Some(StackEntry::Num(_)),
         Some(StackEntry::Op(OpCodes::OP_DROP)),
         Some(StackEntry::Bytes(b)),
         Some(StackEntry::Signature(_)),
-        Some(StackEntry::PubKey(_)),
+        Some(StackEntry::PubKey(pk)),
         Some(StackEntry::Op(OpCodes::OP_CHECKSIG)),
         None,
     ) = (
@@ -143,13 +539,46 @@ pub fn tx_has_valid_create_script(script: &Script, asset: &Asset) -> bool {
         it.next(),
         it.next(),
     ) {
-        if b == &asset_hash && script.interpret() {
-            return true;
+        if hex::encode(b) == asset_hash && script.interpret() {
+            return Some(pk);
         }
     }
 
-    trace!("Invalid script for create: {:?}", script.stack,);
-    false
+    None
+}
+
+/// Checks that the genesis of an issued token defines it correctly and
+/// is signed by its issuer
+///
+/// ### Arguments
+///
+/// * `issued`  - Issued asset created by the genesis transaction
+/// * `signer`  - Public key that signed the create script
+fn issued_genesis_is_valid(issued: &IssuedAsset, signer: &PublicKey) -> bool {
+    let definition = match (&issued.genesis_hash, &issued.definition) {
+        (None, Some(definition)) => definition,
+        _ => {
+            trace!("Issued token genesis needs a definition and no genesis hash");
+            return false;
+        }
+    };
+
+    if !definition.is_valid() {
+        trace!("Issued token definition is invalid");
+        return false;
+    }
+
+    if *signer != definition.issuer {
+        trace!("Issued token genesis is not signed by the issuer");
+        return false;
+    }
+
+    if !definition.allows_supply(issued.amount, true) {
+        trace!("Issued token genesis exceeds the supply rule");
+        return false;
+    }
+
+    true
 }
 
 /// Checks whether a transaction to spend tokens in P2PKH has a valid signature
@@ -159,7 +588,13 @@ pub fn tx_has_valid_create_script(script: &Script, asset: &Asset) -> bool {
 /// * `script`          - Script to validate
 /// * `outpoint_hash`   - Hash of the corresponding outpoint
 /// * `tx_out_pub_key`  - Public key of the previous tx_out
-fn tx_has_valid_p2pkh_sig(script: &Script, outpoint_hash: &str, tx_out_pub_key: &str) -> bool {
+/// * `stack`           - Reusable stack to execute the script on
+fn tx_has_valid_p2pkh_sig(
+    script: &Script,
+    outpoint_hash: &str,
+    tx_out_pub_key: &str,
+    stack: &mut Stack,
+) -> bool {
     let mut it = script.stack.iter();
 
     if let (
@@ -185,7 +620,10 @@ fn tx_has_valid_p2pkh_sig(script: &Script, outpoint_hash: &str, tx_out_pub_key:
         it.next(),
         it.next(),
     ) {
-        if h == tx_out_pub_key && b == outpoint_hash && script.interpret() {
+        if bytes_match_hex(h, tx_out_pub_key)
+            && bytes_match_hex(b, outpoint_hash)
+            && script.interpret_with_stack(stack, SCRIPT_VERSION_V0)
+        {
             return true;
         }
     }
@@ -199,17 +637,46 @@ fn tx_has_valid_p2pkh_sig(script: &Script, outpoint_hash: &str, tx_out_pub_key:
     false
 }
 
-/// Checks whether a transaction to spend tokens in P2SH has a valid hash and executing script
+/// Compares raw bytes against their lowercase hex encoding without allocating
 ///
 /// ### Arguments
 ///
-/// * `script`          - Script to validate
-/// * `address`         - Address of the P2SH transaction
+/// * `bytes`   - Raw bytes to compare
+/// * `hex`     - Hex encoded string to compare against
+fn bytes_match_hex(bytes: &[u8], hex: &str) -> bool {
+    const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+    hex.len() == bytes.len() * 2
+        && bytes.iter().zip(hex.as_bytes().chunks(2)).all(|(b, c)| {
+            c[0] == HEX_CHARS[(b >> 4) as usize] && c[1] == HEX_CHARS[(b & 0xf) as usize]
+        })
+}
+
+/// Checks whether a transaction to spend tokens in P2SH has a valid hash and executing script.
+/// The script runs under the rule set of the script version encoded in the address
+///
+/// ### Arguments
+///
+/// * `script`  - Script to validate
+/// * `address` - Address of the P2SH transaction
 pub fn tx_has_valid_p2sh_script(script: &Script, address: &str) -> bool {
-    let p2sh_address = construct_p2sh_address(script);
+    p2sh_script_is_valid(script, address, &mut Stack::new())
+}
 
-    if p2sh_address == address {
-        return script.interpret();
+/// Checks a P2SH script as `tx_has_valid_p2sh_script` does, executing it on a reusable stack
+///
+/// ### Arguments
+///
+/// * `script`  - Script to validate
+/// * `address` - Address of the P2SH transaction
+/// * `stack`   - Reusable stack to execute the script on
+fn p2sh_script_is_valid(script: &Script, address: &str, stack: &mut Stack) -> bool {
+    let script_version = p2sh_address_script_version(address);
+    let p2sh_address = script_version.and_then(|v| construct_p2sh_address_for(script, v));
+
+    if let (Some(script_version), Some(p2sh_address)) = (script_version, p2sh_address) {
+        if p2sh_address == address {
+            return script.interpret_with_stack(stack, script_version);
+        }
     }
 
     trace!(
@@ -221,14 +688,74 @@ pub fn tx_has_valid_p2sh_script(script: &Script, address: &str) -> bool {
     false
 }
 
-/// Checks that a receipt's metadata conforms to the network size constraint
+/// Checks that a receipt's metadata conforms to the network size constraint.
+/// Transactions after `NETWORK_VERSION_LEGACY_RECEIPT_METADATA` must also hold
+/// canonically encoded, valid `ReceiptMetadata`, while earlier ones are free-form
 ///
 /// ### Arguments
 ///
-/// * `receipt` - Receipt to check
-fn receipt_has_valid_size(receipt: &ReceiptAsset) -> bool {
+/// * `receipt`     - Receipt to check
+/// * `tx_version`  - Version of the transaction creating the receipt
+fn receipt_has_valid_metadata(receipt: &ReceiptAsset, tx_version: usize) -> bool {
     if let Some(metadata) = &receipt.metadata {
-        return metadata.len() <= MAX_METADATA_BYTES;
+        if metadata.len() > MAX_METADATA_BYTES {
+            trace!("Receipt metadata is too large");
+            return false;
+        }
+    }
+    if tx_version <= NETWORK_VERSION_LEGACY_RECEIPT_METADATA {
+        return true;
+    }
+    if let Err(e) = receipt.structured_metadata() {
+        trace!("Invalid receipt metadata: {}", e);
+        return false;
+    }
+    true
+}
+
+/// Checks that the genesis of a collection defines it correctly and
+/// is signed by its creator
+///
+/// ### Arguments
+///
+/// * `item`    - Genesis item created by the transaction
+/// * `signer`  - Public key that signed the create script
+fn collection_genesis_is_valid(item: &ItemAsset, signer: &PublicKey) -> bool {
+    let definition = match (&item.collection_hash, &item.definition) {
+        (None, Some(definition)) => definition,
+        _ => {
+            trace!("Collection genesis needs a definition and no collection hash");
+            return false;
+        }
+    };
+
+    if item.serial != 0 || !definition.allows_serial(item.serial) {
+        trace!("Collection genesis must be the item with serial 0");
+        return false;
+    }
+
+    if *signer != definition.creator {
+        trace!("Collection genesis is not signed by the creator");
+        return false;
+    }
+
+    true
+}
+
+/// Checks that an item's metadata conforms to the network size constraint
+/// and is canonically encoded, valid `ReceiptMetadata`
+///
+/// ### Arguments
+///
+/// * `item`    - Item to check
+fn item_has_valid_metadata(item: &ItemAsset) -> bool {
+    if item.metadata.as_ref().map_or(0, String::len) > MAX_METADATA_BYTES {
+        trace!("Item metadata is too large");
+        return false;
+    }
+    if let Err(e) = item.structured_metadata() {
+        trace!("Invalid item metadata: {}", e);
+        return false;
     }
     true
 }
@@ -246,12 +773,17 @@ fn address_has_valid_length(address: &str) -> bool {
 mod tests {
     use super::*;
     use crate::constants::RECEIPT_ACCEPT_VAL;
-    use crate::primitives::asset::{Asset, DataAsset};
+    use crate::primitives::asset::{Asset, AssetKey, DataAsset, SupplyRule};
     use crate::primitives::druid::DdeValues;
     use crate::primitives::transaction::OutPoint;
     use crate::utils::test_utils::generate_tx_with_ins_and_outs_assets;
     use crate::utils::transaction_utils::*;
 
+    /// Util function to create a `Bytes` item held as the string `s`
+    fn text_item(s: &str) -> StackEntry {
+        StackEntry::bytes_from_text(s.to_owned())
+    }
+
     /*---- CONSTANTS OPS ----*/
 
     #[test]
@@ -471,7 +1003,7 @@ mod tests {
         assert_eq!(cond_stack.first_false_pos, Some(0));
         /// error item type
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(String::new()));
+        stack.push(StackEntry::Bytes(Vec::new()));
         let mut cond_stack = ConditionStack::new();
         let b = op_if(&mut stack, &mut cond_stack);
         assert!(!b);
@@ -516,7 +1048,7 @@ mod tests {
         assert_eq!(cond_stack.first_false_pos, Some(0));
         /// error item type
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(String::new()));
+        stack.push(StackEntry::Bytes(Vec::new()));
         let mut cond_stack = ConditionStack::new();
         let b = op_notif(&mut stack, &mut cond_stack);
         assert!(!b);
@@ -959,7 +1491,7 @@ mod tests {
         /// op_pick([1,"hello"]) -> fail
         let mut stack = Stack::new();
         stack.push(StackEntry::Num(1));
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(StackEntry::Bytes(b"hello".to_vec()));
         let b = op_pick(&mut stack);
         assert!(!b);
         /// op_pick([1,1]) -> fail
@@ -1007,7 +1539,7 @@ mod tests {
         /// op_roll([1,"hello"]) -> fail
         let mut stack = Stack::new();
         stack.push(StackEntry::Num(1));
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(StackEntry::Bytes(b"hello".to_vec()));
         let b = op_roll(&mut stack);
         assert!(!b);
         /// op_roll([1,1]) -> fail
@@ -1089,36 +1621,36 @@ mod tests {
     fn test_cat() {
         /// op_cat(["hello","world"]) -> ["helloworld"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
-        stack.push(StackEntry::Bytes("world".to_string()));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("helloworld".to_string())];
+        stack.push(text_item("hello"));
+        stack.push(text_item("world"));
+        let mut v: Vec<StackEntry> = vec![text_item("helloworld")];
         op_cat(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_cat(["hello",""]) -> ["hello"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
-        stack.push(StackEntry::Bytes("".to_string()));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("hello".to_string())];
+        stack.push(text_item("hello"));
+        stack.push(text_item(""));
+        let mut v: Vec<StackEntry> = vec![text_item("hello")];
         op_cat(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_cat(["a","a"*MAX_SCRIPT_ITEM_SIZE]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("a".to_string()));
+        stack.push(text_item("a"));
         let mut s = String::new();
         for i in 1..=MAX_SCRIPT_ITEM_SIZE {
             s.push('a');
         }
-        stack.push(StackEntry::Bytes(s.to_string()));
+        stack.push(text_item(&s));
         let b = op_cat(&mut stack);
         assert!(!b);
         /// op_cat(["hello"]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         let b = op_cat(&mut stack);
         assert!(!b);
         /// op_cat(["hello", 1]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(1));
         let b = op_cat(&mut stack);
         assert!(!b)
@@ -1129,62 +1661,62 @@ mod tests {
     fn test_substr() {
         /// op_substr(["hello",1,2]) -> ["el"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         for i in 1..=2 {
             stack.push(StackEntry::Num(i));
         }
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("el".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("el")];
         op_substr(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_substr(["hello",0,0]) -> [""]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         for i in 1..=2 {
             stack.push(StackEntry::Num(0));
         }
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("")];
         op_substr(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_substr(["hello",0,5]) -> ["hello"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(0));
         stack.push(StackEntry::Num(5));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("hello".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("hello")];
         op_substr(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_substr(["hello",5,0]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(5));
         stack.push(StackEntry::Num(0));
         let b = op_substr(&mut stack);
         assert!(!b);
         /// op_substr(["hello",1,5]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(1));
         stack.push(StackEntry::Num(5));
         let b = op_substr(&mut stack);
         assert!(!b);
         /// op_substr(["hello",1]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(1));
         let b = op_substr(&mut stack);
         assert!(!b);
         /// op_substr(["hello",1,usize::MAX]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(1));
         stack.push(StackEntry::Num(usize::MAX));
         let b = op_substr(&mut stack);
         assert!(!b);
         /// op_substr(["hello",1,""]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(1));
-        stack.push(StackEntry::Bytes("".to_string()));
+        stack.push(text_item(""));
         let b = op_substr(&mut stack);
         assert!(!b)
     }
@@ -1194,34 +1726,34 @@ mod tests {
     fn test_left() {
         /// op_left(["hello",2]) -> ["he"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(2));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("he".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("he")];
         op_left(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_left(["hello",0]) -> [""]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(0));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("")];
         op_left(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_left(["hello",5]) -> ["hello"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(5));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("hello".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("hello")];
         op_left(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_left(["hello",""]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
-        stack.push(StackEntry::Bytes("".to_string()));
+        stack.push(text_item("hello"));
+        stack.push(text_item(""));
         let b = op_left(&mut stack);
         assert!(!b);
         /// op_left(["hello"]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         let b = op_left(&mut stack);
         assert!(!b)
     }
@@ -1231,34 +1763,34 @@ mod tests {
     fn test_right() {
         /// op_right(["hello",0]) -> ["hello"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(0));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("hello".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("hello")];
         op_right(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_right(["hello",2]) -> ["llo"]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(2));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("llo".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("llo")];
         op_right(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_right(["hello",5]) -> [""]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         stack.push(StackEntry::Num(5));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("".to_string())];
+        let mut v: Vec<StackEntry> = vec![text_item("")];
         op_right(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_right(["hello",""]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
-        stack.push(StackEntry::Bytes("".to_string()));
+        stack.push(text_item("hello"));
+        stack.push(text_item(""));
         let b = op_right(&mut stack);
         assert!(!b);
         /// op_right(["hello"]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(text_item("hello"));
         let b = op_right(&mut stack);
         assert!(!b)
     }
@@ -1268,15 +1800,20 @@ mod tests {
     fn test_size() {
         /// op_size(["hello"]) -> ["hello",5]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("hello".to_string()));
-        let mut v: Vec<StackEntry> =
-            vec![StackEntry::Bytes("hello".to_string()), StackEntry::Num(5)];
+        stack.push(text_item("hello"));
+        let mut v: Vec<StackEntry> = vec![text_item("hello"), StackEntry::Num(5)];
         op_size(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_size([""]) -> ["",0]
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes("".to_string()));
-        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes("".to_string()), StackEntry::Num(0)];
+        stack.push(text_item(""));
+        let mut v: Vec<StackEntry> = vec![text_item(""), StackEntry::Num(0)];
+        op_size(&mut stack);
+        assert_eq!(stack.main_stack, v);
+        /// op_size([0xabcd]) -> [0xabcd,4], measuring the string "abcd" it is held as
+        let mut stack = Stack::new();
+        stack.push(StackEntry::bytes_from_hex("abcd").unwrap());
+        let mut v: Vec<StackEntry> = vec![StackEntry::Bytes(vec![0xab, 0xcd]), StackEntry::Num(4)];
         op_size(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_size([1]) -> fail
@@ -1375,7 +1912,7 @@ mod tests {
         /// op_equal(["hello","hello"]) -> [1]
         let mut stack = Stack::new();
         for i in 1..=2 {
-            stack.push(StackEntry::Bytes("hello".to_string()));
+            stack.push(StackEntry::Bytes(b"hello".to_vec()));
         }
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(1)];
         op_equal(&mut stack);
@@ -1401,7 +1938,7 @@ mod tests {
         /// op_equalverify(["hello","hello"]) -> []
         let mut stack = Stack::new();
         for i in 1..=2 {
-            stack.push(StackEntry::Bytes("hello".to_string()));
+            stack.push(StackEntry::Bytes(b"hello".to_vec()));
         }
         let mut v: Vec<StackEntry> = vec![];
         op_equalverify(&mut stack);
@@ -2010,24 +2547,24 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let msg = hex::encode(vec![0, 0, 0]);
         let sig = sign::sign_detached(msg.as_bytes(), &sk);
-        let h = hex::encode(sha3_256::digest(sig.as_ref()));
+        let h = sha3_256::digest(sig.as_ref()).to_vec();
         let mut stack = Stack::new();
         stack.push(StackEntry::Signature(sig));
         let mut v: Vec<StackEntry> = vec![StackEntry::Bytes(h)];
         op_sha3(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_sha3([pk]) -> [sha3_256(pk)]
-        let h = hex::encode(sha3_256::digest(pk.as_ref()));
+        let h = sha3_256::digest(pk.as_ref()).to_vec();
         let mut stack = Stack::new();
         stack.push(StackEntry::PubKey(pk));
         let mut v: Vec<StackEntry> = vec![StackEntry::Bytes(h)];
         op_sha3(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_sha3(["hello"]) -> [sha3_256("hello")]
-        let s = "hello".to_string();
-        let h = hex::encode(sha3_256::digest(s.as_bytes()));
+        let s = text_item("hello");
+        let h = sha3_256::digest(b"hello").to_vec();
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(s));
+        stack.push(s);
         let mut v: Vec<StackEntry> = vec![StackEntry::Bytes(h)];
         op_sha3(&mut stack);
         assert_eq!(stack.main_stack, v);
@@ -2049,7 +2586,8 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let mut stack = Stack::new();
         stack.push(StackEntry::PubKey(pk));
-        let mut v: Vec<StackEntry> = vec![StackEntry::PubKeyHash(construct_address(&pk))];
+        let mut v: Vec<StackEntry> =
+            vec![StackEntry::pub_key_hash_from_hex(&construct_address(&pk)).unwrap()];
         op_hash256(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_hash256([]) -> fail
@@ -2065,7 +2603,8 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let mut stack = Stack::new();
         stack.push(StackEntry::PubKey(pk));
-        let mut v: Vec<StackEntry> = vec![StackEntry::PubKeyHash(construct_address_v0(&pk))];
+        let mut v: Vec<StackEntry> =
+            vec![StackEntry::pub_key_hash_from_hex(&construct_address_v0(&pk)).unwrap()];
         op_hash256_v0(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_hash256([]) -> fail
@@ -2081,7 +2620,8 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let mut stack = Stack::new();
         stack.push(StackEntry::PubKey(pk));
-        let mut v: Vec<StackEntry> = vec![StackEntry::PubKeyHash(construct_address_temp(&pk))];
+        let mut v: Vec<StackEntry> =
+            vec![StackEntry::pub_key_hash_from_hex(&construct_address_temp(&pk)).unwrap()];
         op_hash256_temp(&mut stack);
         assert_eq!(stack.main_stack, v);
         /// op_hash256([]) -> fail
@@ -2098,7 +2638,7 @@ mod tests {
         let msg = hex::encode(vec![0, 0, 0]);
         let sig = sign::sign_detached(msg.as_bytes(), &sk);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig));
         stack.push(StackEntry::PubKey(pk));
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(1)];
@@ -2108,7 +2648,7 @@ mod tests {
         /// op_checksig([msg',sig,pk]) -> [0]
         let msg = hex::encode(vec![0, 0, 1]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig));
         stack.push(StackEntry::PubKey(pk));
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(0)];
@@ -2119,7 +2659,7 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig));
         stack.push(StackEntry::PubKey(pk));
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(0)];
@@ -2142,7 +2682,7 @@ mod tests {
         let msg = hex::encode(vec![0, 0, 0]);
         let sig = sign::sign_detached(msg.as_bytes(), &sk);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig));
         stack.push(StackEntry::PubKey(pk));
         let mut v: Vec<StackEntry> = vec![];
@@ -2152,7 +2692,7 @@ mod tests {
         /// op_checksigverify([msg',sig,pk]) -> fail
         let msg = hex::encode(vec![0, 0, 1]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig));
         stack.push(StackEntry::PubKey(pk));
         let b = op_checksigverify(&mut stack);
@@ -2162,7 +2702,7 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig));
         stack.push(StackEntry::PubKey(pk));
         let b = op_checksigverify(&mut stack);
@@ -2188,7 +2728,7 @@ mod tests {
         let sig1 = sign::sign_detached(msg.as_bytes(), &sk1);
         let sig2 = sign::sign_detached(msg.as_bytes(), &sk2);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Signature(sig2));
         stack.push(StackEntry::Num(2));
@@ -2203,7 +2743,7 @@ mod tests {
         /// op_checkmultisig([msg,0,pk1,pk2,pk3,3]) -> [1]
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Num(0));
         stack.push(StackEntry::PubKey(pk1));
         stack.push(StackEntry::PubKey(pk2));
@@ -2216,7 +2756,7 @@ mod tests {
         /// op_checkmultisig([msg,0,0]) -> [1]
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Num(0));
         stack.push(StackEntry::Num(0));
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(1)];
@@ -2226,7 +2766,7 @@ mod tests {
         /// op_checkmultisig([msg,sig1,1,pk1,1]) -> [1]
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Num(1));
         stack.push(StackEntry::PubKey(pk1));
@@ -2239,7 +2779,7 @@ mod tests {
         let msg = hex::encode(vec![0, 0, 0]);
         let sig3 = sign::sign_detached(msg.as_bytes(), &sk3);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig3));
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Num(2));
@@ -2254,7 +2794,7 @@ mod tests {
         /// op_checkmultisig([msg',sig1,sig2,2,pk1,pk2,pk3,3]) -> [0]
         let msg = hex::encode(vec![0, 0, 1]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Signature(sig2));
         stack.push(StackEntry::Num(2));
@@ -2269,7 +2809,7 @@ mod tests {
         /// op_checkmultisig([msg,sig1,sig1,2,pk1,pk2,pk3,3]) -> [0]
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Num(2));
@@ -2341,7 +2881,7 @@ mod tests {
         let sig1 = sign::sign_detached(msg.as_bytes(), &sk1);
         let sig2 = sign::sign_detached(msg.as_bytes(), &sk2);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Signature(sig2));
         stack.push(StackEntry::Num(2));
@@ -2356,7 +2896,7 @@ mod tests {
         /// op_checkmultisigverify([msg,0,pk1,pk2,pk3,3]) -> []
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Num(0));
         stack.push(StackEntry::PubKey(pk1));
         stack.push(StackEntry::PubKey(pk2));
@@ -2369,7 +2909,7 @@ mod tests {
         /// op_checkmultisig([msg,0,0]) -> []
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Num(0));
         stack.push(StackEntry::Num(0));
         let mut v: Vec<StackEntry> = vec![];
@@ -2379,7 +2919,7 @@ mod tests {
         /// op_checkmultisigverify([msg,sig1,1,pk1,1]) -> []
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Num(1));
         stack.push(StackEntry::PubKey(pk1));
@@ -2392,7 +2932,7 @@ mod tests {
         let msg = hex::encode(vec![0, 0, 0]);
         let sig3 = sign::sign_detached(msg.as_bytes(), &sk3);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig3));
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Num(2));
@@ -2407,7 +2947,7 @@ mod tests {
         /// op_checkmultisigverify([msg',sig1,sig2,2,pk1,pk2,pk3,3]) -> fail
         let msg = hex::encode(vec![0, 0, 1]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Signature(sig2));
         stack.push(StackEntry::Num(2));
@@ -2421,7 +2961,7 @@ mod tests {
         /// op_checkmultisigverify([msg,sig1,sig1,2,pk1,pk2,pk3,3]) -> fail
         let msg = hex::encode(vec![0, 0, 0]);
         let mut stack = Stack::new();
-        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::bytes_from_hex(&msg).unwrap());
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Signature(sig1));
         stack.push(StackEntry::Num(2));
@@ -2480,18 +3020,217 @@ mod tests {
         assert!(!b);
     }
 
+    /*---- UPGRADABLE NOP OPS ----*/
+
     #[test]
-    fn test_is_valid_script() {
-        // empty script
-        let v = vec![];
-        let script = Script::from(v);
-        assert!(script.is_valid());
-        // script length <= 10000 bytes
-        let v = vec![StackEntry::Bytes("a".repeat(500)); 20];
+    /// Test OP_NOP1-OP_NOP10
+    fn test_nopx() {
+        /// op_nopx([1]) -> [1]
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Num(1));
+        let mut v: Vec<StackEntry> = vec![StackEntry::Num(1)];
+        op_nopx(&mut stack, &OpCodes::OP_NOP10);
+        assert_eq!(stack.main_stack, v);
+        /// op_nopx([1]) with a non-NOP opcode -> fail
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Num(1));
+        let b = op_nopx(&mut stack, &OpCodes::OP_DUP);
+        assert!(!b)
+    }
+
+    #[test]
+    /// Test OP_CHECKSHA3VERIFY
+    fn test_checksha3verify() {
+        /// op_checksha3verify(["hello",sha3_256("hello")]) -> ["hello",sha3_256("hello")]
+        let s = text_item("hello");
+        let h = sha3_256::digest(b"hello").to_vec();
+        let mut stack = Stack::new();
+        stack.push(s.clone());
+        stack.push(StackEntry::Bytes(h.clone()));
+        let mut v: Vec<StackEntry> = vec![s.clone(), StackEntry::Bytes(h)];
+        let b = op_checksha3verify(&mut stack);
+        assert!(b);
+        assert_eq!(stack.main_stack, v);
+        /// op_checksha3verify(["hello",sha3_256("world")]) -> fail
+        let h = sha3_256::digest(b"world").to_vec();
+        let mut stack = Stack::new();
+        stack.push(s.clone());
+        stack.push(StackEntry::Bytes(h));
+        let b = op_checksha3verify(&mut stack);
+        assert!(!b);
+        /// op_checksha3verify(["hello",1]) -> fail
+        let mut stack = Stack::new();
+        stack.push(s);
+        stack.push(StackEntry::Num(1));
+        let b = op_checksha3verify(&mut stack);
+        assert!(!b);
+        /// op_checksha3verify([]) -> fail
+        let mut stack = Stack::new();
+        let b = op_checksha3verify(&mut stack);
+        assert!(!b)
+    }
+
+    #[test]
+    /// Checks that a stack reused across scripts does not leak state between them
+    fn test_interpret_with_reused_stack() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.main_stack.capacity(), 0);
+
+        let failing = Script::from(vec![
+            StackEntry::Num(1),
+            StackEntry::Num(2),
+            StackEntry::Op(OpCodes::OP_0),
+        ]);
+        assert!(!failing.interpret_with_stack(&mut stack, SCRIPT_VERSION_V0));
+        assert_eq!(stack.main_stack.len(), 3);
+
+        let depth = Script::from(vec![StackEntry::Op(OpCodes::OP_DEPTH)]);
+        assert!(!depth.interpret_with_stack(&mut stack, SCRIPT_VERSION_V0));
+        assert_eq!(stack.main_stack, vec![StackEntry::Num(0)]);
+
+        let valid = Script::from(vec![StackEntry::Op(OpCodes::OP_1)]);
+        assert!(valid.interpret_with_stack(&mut stack, SCRIPT_VERSION_V0));
+    }
+
+    #[test]
+    /// Checks the allocation-free comparison of raw bytes against hex strings
+    fn test_bytes_match_hex() {
+        assert!(bytes_match_hex(&[0xab, 0x01], "ab01"));
+        assert!(bytes_match_hex(&[], ""));
+        assert!(!bytes_match_hex(&[0xab, 0x01], "AB01"));
+        assert!(!bytes_match_hex(&[0xab, 0x01], "ab0"));
+        assert!(!bytes_match_hex(&[0xab, 0x01], "ab02"));
+    }
+
+    #[test]
+    fn test_interpret_upgradable_nops() {
+        let valid_hash = sha3_256::digest(hex::encode(b"hello").as_bytes()).to_vec();
+        let invalid_hash = sha3_256::digest(b"world").to_vec();
+        // OP_NOP1 is a no-op under the original rules
+        assert!(create_sha3_preimage_script(valid_hash.clone()).interpret());
+        assert!(create_sha3_preimage_script(invalid_hash.clone()).interpret());
+        // OP_NOP1 is OP_CHECKSHA3VERIFY from SCRIPT_VERSION_V1 onwards, hashing the hex string
+        assert!(create_sha3_preimage_script(valid_hash.clone()).interpret_for(SCRIPT_VERSION_V1));
+        assert!(!create_sha3_preimage_script(invalid_hash.clone()).interpret_for(SCRIPT_VERSION_V1));
+        // From SCRIPT_VERSION_V2 the preimage is hashed as raw bytes
+        let byte_hash = sha3_256::digest(b"hello").to_vec();
+        assert!(create_sha3_preimage_script(byte_hash).interpret_for(SCRIPT_VERSION_V2));
+        assert!(!create_sha3_preimage_script(valid_hash).interpret_for(SCRIPT_VERSION_V2));
+        assert!(!create_sha3_preimage_script(invalid_hash).interpret_for(SCRIPT_VERSION_V2));
+        // Unassigned slots stay no-ops under every version
+        let v = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_NOP2),
+            StackEntry::Op(OpCodes::OP_NOP10),
+        ];
+        let script = Script::from(v);
+        assert!(script.interpret());
+        assert!(script.interpret_for(SCRIPT_VERSION_V1));
+    }
+
+    /// Util function to create a script revealing the preimage "hello" of `hash` through OP_NOP1
+    fn create_sha3_preimage_script(hash: Vec<u8>) -> Script {
+        Script::from(vec![
+            StackEntry::Bytes(b"hello".to_vec()),
+            StackEntry::Bytes(hash),
+            StackEntry::Op(OpCodes::OP_NOP1),
+            StackEntry::Op(OpCodes::OP_2DROP),
+            StackEntry::Op(OpCodes::OP_1),
+        ])
+    }
+
+    #[test]
+    /// Checks that byte items keep their hex string encoding when serialized
+    fn test_stack_entry_hex_serialization() {
+        // Bytes(0xabcd) is serialized as the hex string "abcd"
+        let entry = StackEntry::Bytes(vec![0xab, 0xcd]);
+        let mut expected = vec![5, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(b"abcd");
+        assert_eq!(serialize(&entry).unwrap(), expected);
+        assert_eq!(
+            bincode::deserialize::<StackEntry>(&expected).unwrap(),
+            entry
+        );
+        // Legacy items that are not canonical lowercase hex are kept verbatim
+        for legacy_item in ["hello", "CAFE", "abc"] {
+            let mut legacy = vec![5, 0, 0, 0, legacy_item.len() as u8, 0, 0, 0, 0, 0, 0, 0];
+            legacy.extend_from_slice(legacy_item.as_bytes());
+            let entry = bincode::deserialize::<StackEntry>(&legacy).unwrap();
+            assert_eq!(entry, StackEntry::LegacyBytes(legacy_item.to_owned()));
+            assert!(entry.is_legacy());
+            assert_eq!(serialize(&entry).unwrap(), legacy);
+        }
+        assert_eq!(StackEntry::bytes_from_hex("CAFE"), None);
+        assert_eq!(StackEntry::pub_key_hash_from_hex("hello"), None);
+        assert_eq!(
+            StackEntry::bytes_from_hex("cafe"),
+            Some(StackEntry::Bytes(vec![0xca, 0xfe]))
+        );
+        assert_eq!(
+            StackEntry::bytes_from_text("hello".to_owned()),
+            StackEntry::LegacyBytes("hello".to_owned())
+        );
+    }
+
+    #[test]
+    /// Checks the byte semantics of splice and hash ops from SCRIPT_VERSION_V2
+    fn test_byte_item_semantics() {
+        /// op_cat([0xab,0xcd]) -> [0xabcd]
+        let mut stack = Stack::new();
+        stack.script_version = SCRIPT_VERSION_V2;
+        stack.push(StackEntry::Bytes(vec![0xab]));
+        stack.push(StackEntry::Bytes(vec![0xcd]));
+        assert!(op_cat(&mut stack));
+        assert_eq!(stack.main_stack, vec![StackEntry::Bytes(vec![0xab, 0xcd])]);
+        /// op_size([0xabcd]) -> [0xabcd,2]
+        assert!(op_size(&mut stack));
+        assert_eq!(stack.main_stack[1], StackEntry::Num(2));
+        /// op_substr([0xabcdef,1,1]) -> [0xcd]
+        let mut stack = Stack::new();
+        stack.script_version = SCRIPT_VERSION_V2;
+        stack.push(StackEntry::Bytes(vec![0xab, 0xcd, 0xef]));
+        stack.push(StackEntry::Num(1));
+        stack.push(StackEntry::Num(1));
+        assert!(op_substr(&mut stack));
+        assert_eq!(stack.main_stack, vec![StackEntry::Bytes(vec![0xcd])]);
+        /// op_sha3 hashes the raw bytes, not their hex string
+        let mut stack = Stack::new();
+        stack.script_version = SCRIPT_VERSION_V2;
+        stack.push(StackEntry::Bytes(b"hello".to_vec()));
+        assert!(op_sha3(&mut stack));
+        let digest = sha3_256::digest(b"hello").to_vec();
+        assert_eq!(stack.main_stack, vec![StackEntry::Bytes(digest)]);
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Bytes(b"hello".to_vec()));
+        assert!(op_sha3(&mut stack));
+        let digest = sha3_256::digest(hex::encode(b"hello").as_bytes()).to_vec();
+        assert_eq!(stack.main_stack, vec![StackEntry::Bytes(digest)]);
+        // Item sizes are counted in bytes rather than hex characters
+        let item = StackEntry::Bytes(vec![0; 300]);
+        let mut stack = Stack::new();
+        assert!(!stack.push(item.clone()));
+        stack.script_version = SCRIPT_VERSION_V2;
+        assert!(stack.push(item));
+        // Items held as strings only run under the original semantics
+        let script = Script::from(vec![StackEntry::bytes_from_text("hello".to_owned())]);
+        assert!(script.is_valid_for(&Default::default(), SCRIPT_VERSION_V0));
+        assert!(!script.is_valid_for(&Default::default(), SCRIPT_VERSION_V2));
+        assert!(script.interpret());
+        assert!(!script.interpret_for(SCRIPT_VERSION_V2));
+    }
+
+    #[test]
+    fn test_is_valid_script() {
+        // empty script
+        let v = vec![];
+        let script = Script::from(v);
+        assert!(script.is_valid());
+        // script length <= 10000 bytes
+        let v = vec![text_item(&"a".repeat(500)); 20];
         let script = Script::from(v);
         assert!(script.is_valid());
         // script length > 10000 bytes
-        let v = vec![StackEntry::Bytes("a".repeat(500)); 21];
+        let v = vec![text_item(&"a".repeat(500)); 21];
         let script = Script::from(v);
         assert!(!script.is_valid());
         // # opcodes <= 201
@@ -2504,6 +3243,52 @@ mod tests {
         assert!(!script.is_valid());
     }
 
+    #[test]
+    /// Checks that scripts are validated and executed against configurable limits
+    fn test_interpret_with_limits() {
+        let consensus = ScriptLimits::default();
+        let tight = ScriptLimits {
+            max_script_size: 16,
+            max_ops_per_script: 2,
+            max_stack_size: 2,
+            max_script_item_size: 4,
+            max_pub_keys_per_multisig: 1,
+        };
+        let loose = ScriptLimits {
+            max_stack_size: consensus.max_stack_size * 2,
+            ..consensus
+        };
+
+        let script = Script::from(vec![StackEntry::Num(1); 3]);
+        assert!(script.interpret_with_limits(consensus, SCRIPT_VERSION_V0));
+        assert!(!script.interpret_with_limits(tight, SCRIPT_VERSION_V0));
+
+        let script = Script::from(vec![StackEntry::Op(OpCodes::OP_1); 3]);
+        assert!(script.is_valid());
+        assert!(!script.is_valid_for(&tight, SCRIPT_VERSION_V0));
+
+        let script = Script::from(vec![StackEntry::Bytes(vec![1; 5])]);
+        assert!(script.interpret_with_limits(consensus, SCRIPT_VERSION_V0));
+        assert!(!script.interpret_with_limits(tight, SCRIPT_VERSION_V0));
+
+        let v = vec![StackEntry::Num(1); consensus.max_stack_size + 1];
+        let script = Script::from(v);
+        assert!(!script.interpret());
+        assert!(script.interpret_with_limits(loose, SCRIPT_VERSION_V0));
+
+        let (pk1, sk1) = sign::gen_keypair();
+        let (pk2, _) = sign::gen_keypair();
+        let msg = hex::encode(vec![0, 0, 0]);
+        let sig1 = sign::sign_detached(msg.as_bytes(), &sk1);
+        let script = Script::multisig_validation(1, 2, msg, vec![sig1], vec![pk1, pk2]);
+        assert!(script.interpret());
+        let limits = ScriptLimits {
+            max_pub_keys_per_multisig: 1,
+            ..consensus
+        };
+        assert!(!script.interpret_with_limits(limits, SCRIPT_VERSION_V0));
+    }
+
     #[test]
     fn test_is_valid_stack() {
         // empty stack
@@ -2545,11 +3330,11 @@ mod tests {
         let script = Script::from(v);
         assert!(script.interpret());
         // script length <= 10000 bytes
-        let v = vec![StackEntry::Bytes("a".repeat(500)); 20];
+        let v = vec![text_item(&"a".repeat(500)); 20];
         let script = Script::from(v);
         assert!(script.interpret());
         // script length > 10000 bytes
-        let v = vec![StackEntry::Bytes("a".repeat(500)); 21];
+        let v = vec![text_item(&"a".repeat(500)); 21];
         let script = Script::from(v);
         assert!(!script.interpret());
         // # opcodes <= 201
@@ -2776,12 +3561,16 @@ mod tests {
     /// Checks that a correct create script is validated as such
     fn test_pass_create_script_valid() {
         let asset = Asset::receipt(1, None, None);
-        let asset_hash = construct_tx_in_signable_asset_hash(&asset);
+        let asset_hash = construct_tx_in_signable_asset_hash(&asset, NETWORK_VERSION as usize);
         let (pk, sk) = sign::gen_keypair();
         let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
 
         let script = Script::new_create_asset(0, asset_hash, signature, pk);
-        assert!(tx_has_valid_create_script(&script, &asset));
+        assert!(tx_has_valid_create_script(
+            &script,
+            &asset,
+            NETWORK_VERSION as usize
+        ));
     }
 
     #[test]
@@ -2789,12 +3578,587 @@ mod tests {
     fn test_fail_create_receipt_script_invalid() {
         let metadata = String::from_utf8_lossy(&[0; MAX_METADATA_BYTES + 1]).to_string();
         let asset = Asset::receipt(1, None, Some(metadata));
-        let asset_hash = construct_tx_in_signable_asset_hash(&asset);
+        let asset_hash = construct_tx_in_signable_asset_hash(&asset, NETWORK_VERSION as usize);
         let (pk, sk) = sign::gen_keypair();
         let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
 
         let script = Script::new_create_asset(0, asset_hash, signature, pk);
-        assert!(!tx_has_valid_create_script(&script, &asset));
+        assert!(!tx_has_valid_create_script(
+            &script,
+            &asset,
+            NETWORK_VERSION as usize
+        ));
+    }
+
+    #[test]
+    /// Checks that create scripts require structured metadata covered by the signature
+    fn test_create_receipt_script_structured_metadata() {
+        let (pk, sk) = sign::gen_keypair();
+        let create_script = |asset: &Asset| {
+            let asset_hash = construct_tx_in_signable_asset_hash(asset, NETWORK_VERSION as usize);
+            let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+            Script::new_create_asset(0, asset_hash, signature, pk)
+        };
+
+        let mut metadata = ReceiptMetadata::new("Voucher".to_owned(), "One coffee".to_owned());
+        metadata
+            .attributes
+            .insert("store".to_owned(), "main".to_owned());
+        let asset = Asset::receipt_with_metadata(1, None, &metadata);
+        let script = create_script(&asset);
+        assert!(tx_has_valid_create_script(
+            &script,
+            &asset,
+            NETWORK_VERSION as usize
+        ));
+
+        // Metadata cannot be altered after signing
+        metadata.description = "Two coffees".to_owned();
+        let altered = Asset::receipt_with_metadata(1, None, &metadata);
+        assert!(!tx_has_valid_create_script(
+            &script,
+            &altered,
+            NETWORK_VERSION as usize
+        ));
+
+        // Free-form metadata is rejected
+        let asset = Asset::receipt(1, None, Some("{\"name\":\"test\"}".to_owned()));
+        assert!(!tx_has_valid_create_script(
+            &create_script(&asset),
+            &asset,
+            NETWORK_VERSION as usize
+        ));
+
+        // Legacy transactions keep free-form metadata, left out of the signature
+        let legacy = NETWORK_VERSION_LEGACY_RECEIPT_METADATA;
+        let asset_hash = construct_tx_in_signable_asset_hash(&asset, legacy);
+        assert_eq!(
+            asset_hash,
+            construct_tx_in_signable_asset_hash(&Asset::receipt(1, None, None), legacy)
+        );
+        let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+        let script = Script::new_create_asset(0, asset_hash, signature, pk);
+        assert!(tx_has_valid_create_script(&script, &asset, legacy));
+        assert!(!tx_has_valid_create_script(
+            &script,
+            &asset,
+            NETWORK_VERSION as usize
+        ));
+    }
+
+    #[test]
+    /// Checks that issued token genesis transactions respect the definition and supply rule
+    fn test_issued_token_genesis() {
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, other_sk) = sign::gen_keypair();
+        let genesis_is_valid = |tx: &Transaction| {
+            tx.is_create_tx()
+                && !tx.is_reissuance_tx()
+                && tx_has_valid_create_script(
+                    &tx.inputs[0].script_signature,
+                    &tx.outputs[0].value,
+                    tx.version,
+                )
+        };
+
+        let fixed = construct_issued_token_create_tx(0, pk, &sk, 1000, 2, SupplyRule::Fixed);
+        let capped = construct_issued_token_create_tx(0, pk, &sk, 1000, 2, SupplyRule::Max(1000));
+        let over_cap = construct_issued_token_create_tx(0, pk, &sk, 1001, 2, SupplyRule::Max(1000));
+        let decimals = MAX_ISSUED_TOKEN_DECIMALS + 1;
+        let bad_decimals =
+            construct_issued_token_create_tx(0, pk, &sk, 1, decimals, SupplyRule::Fixed);
+
+        // Definition naming a different issuer than the signer
+        let mut wrong_issuer =
+            construct_issued_token_create_tx(0, pk, &sk, 1, 2, SupplyRule::Fixed);
+        let definition = IssuanceDefinition {
+            issuer: pk,
+            decimals: 2,
+            supply: SupplyRule::Fixed,
+        };
+        let asset = Asset::issued(1, None, Some(definition));
+        wrong_issuer.inputs = construct_create_tx_in(0, &asset, other_pk, &other_sk);
+
+        assert!(genesis_is_valid(&fixed));
+        assert!(genesis_is_valid(&capped));
+        assert!(!genesis_is_valid(&over_cap));
+        assert!(!genesis_is_valid(&bad_decimals));
+        assert!(!genesis_is_valid(&wrong_issuer));
+    }
+
+    #[test]
+    /// Checks that only the issuer can re-issue a token, and only within its supply rule
+    fn test_issued_token_reissuance() {
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, other_sk) = sign::gen_keypair();
+        let genesis_hash = "g_hash".to_owned();
+        let definition = |supply| IssuanceDefinition {
+            issuer: pk,
+            decimals: 0,
+            supply,
+        };
+
+        let tx = construct_reissuance_tx(1, genesis_hash.clone(), pk, &sk, 500);
+        let not_issuer = construct_reissuance_tx(1, genesis_hash.clone(), other_pk, &other_sk, 500);
+        let genesis = construct_issued_token_create_tx(0, pk, &sk, 500, 0, SupplyRule::Max(1000));
+
+        assert!(tx.is_create_tx() && tx.is_reissuance_tx());
+        assert!(tx_is_valid_reissuance(
+            &tx,
+            &definition(SupplyRule::Max(1000)),
+            500
+        ));
+        assert!(!tx_is_valid_reissuance(
+            &tx,
+            &definition(SupplyRule::Max(1000)),
+            501
+        ));
+        assert!(!tx_is_valid_reissuance(
+            &tx,
+            &definition(SupplyRule::Max(1000)),
+            u64::MAX
+        ));
+        assert!(!tx_is_valid_reissuance(
+            &tx,
+            &definition(SupplyRule::Fixed),
+            500
+        ));
+        assert!(!tx_is_valid_reissuance(
+            &not_issuer,
+            &definition(SupplyRule::Max(1000)),
+            0
+        ));
+        assert!(!tx_is_valid_reissuance(
+            &genesis,
+            &definition(SupplyRule::Max(1000)),
+            0
+        ));
+
+        // Re-issuance cannot be accepted as a standalone create
+        let script = &tx.inputs[0].script_signature;
+        assert!(!tx_has_valid_create_script(
+            script,
+            &tx.outputs[0].value,
+            tx.version
+        ));
+
+        // Nor by transaction validation, which cannot check the issuance state
+        assert!(!tx_is_valid(&tx, |_| None));
+    }
+
+    #[test]
+    /// Checks that issued tokens on-spend under their genesis hash and balance separately
+    fn test_issued_token_on_spend() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let genesis = construct_issued_token_create_tx(0, pk, &sk, 1000, 0, SupplyRule::Fixed);
+        let genesis_hash = construct_tx_hash(&genesis);
+        let out_point = OutPoint::new(genesis_hash.clone(), 0);
+        let utxo: BTreeMap<OutPoint, TxOut> = vec![(out_point.clone(), genesis.outputs[0].clone())]
+            .into_iter()
+            .collect();
+
+        let spend = |outputs: Vec<Asset>| {
+            let signable_hash = construct_tx_in_signable_hash(&out_point);
+            let signature = sign::sign_detached(signable_hash.as_bytes(), &sk);
+            let tx_in = TxIn::new_from_input(
+                out_point.clone(),
+                Script::pay2pkh(signable_hash, signature, pk, None),
+            );
+            let tx_outs = outputs
+                .into_iter()
+                .map(|asset| TxOut::new_asset(address.clone(), asset))
+                .collect();
+            construct_tx_core(vec![tx_in], tx_outs)
+        };
+        let issued = |amount, hash: &str| Asset::issued(amount, Some(hash.to_owned()), None);
+
+        let valid = spend(vec![issued(600, &genesis_hash), issued(400, &genesis_hash)]);
+        let too_much = spend(vec![issued(1001, &genesis_hash)]);
+        let wrong_genesis = spend(vec![issued(1000, "other_hash")]);
+        let no_genesis = spend(vec![Asset::issued(1000, None, None)]);
+        let as_receipt = spend(vec![Asset::receipt(1000, Some(genesis_hash.clone()), None)]);
+
+        assert!(tx_is_valid(&valid, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&too_much, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&wrong_genesis, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&no_genesis, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&as_receipt, |v| utxo.get(v)));
+    }
+
+    #[test]
+    /// Checks that collections are defined by their creator and items minted with unique serials
+    fn test_collection_genesis_and_item_mint() {
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, other_sk) = sign::gen_keypair();
+        let genesis_is_valid = |tx: &Transaction| {
+            tx.is_create_tx()
+                && tx_has_valid_create_script(
+                    &tx.inputs[0].script_signature,
+                    &tx.outputs[0].value,
+                    tx.version,
+                )
+        };
+        let metadata = ReceiptMetadata::new("Ticket".to_owned(), "Row A".to_owned()).encode();
+
+        let genesis = construct_collection_create_tx(0, pk, &sk, Some(3), Some(metadata.clone()));
+        let bad_metadata = construct_collection_create_tx(0, pk, &sk, None, Some("{}".to_owned()));
+        let empty = construct_collection_create_tx(0, pk, &sk, Some(0), None);
+        assert!(genesis_is_valid(&genesis));
+        assert!(!genesis_is_valid(&bad_metadata));
+        assert!(!genesis_is_valid(&empty));
+
+        let collection_hash = construct_tx_hash(&genesis);
+        let definition = CollectionDefinition {
+            creator: pk,
+            max_items: Some(3),
+        };
+        let minted: BTreeSet<u64> = vec![0, 1].into_iter().collect();
+        let mint = |serial, pk, sk: &_| {
+            construct_item_mint_tx(0, collection_hash.clone(), serial, pk, sk, None)
+        };
+
+        assert!(mint(2, pk, &sk).is_item_mint_tx());
+        assert!(tx_is_valid_item_mint(
+            &mint(2, pk, &sk),
+            &definition,
+            &minted
+        ));
+        assert!(!tx_is_valid_item_mint(
+            &mint(1, pk, &sk),
+            &definition,
+            &minted
+        ));
+        assert!(!tx_is_valid_item_mint(
+            &mint(3, pk, &sk),
+            &definition,
+            &minted
+        ));
+        assert!(!tx_is_valid_item_mint(
+            &mint(2, other_pk, &other_sk),
+            &definition,
+            &minted
+        ));
+        assert!(!tx_is_valid_item_mint(&genesis, &definition, &minted));
+
+        // Item mints cannot be accepted as a standalone create
+        let tx = mint(2, pk, &sk);
+        assert!(!tx_has_valid_create_script(
+            &tx.inputs[0].script_signature,
+            &tx.outputs[0].value,
+            tx.version
+        ));
+
+        // Nor by transaction validation, which cannot check the minted serials
+        assert!(!tx_is_valid(&tx, |_| None));
+    }
+
+    #[test]
+    /// Checks that items are on-spent whole and cannot be duplicated
+    fn test_item_is_indivisible_on_spend() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let genesis = construct_collection_create_tx(0, pk, &sk, None, None);
+        let collection_hash = construct_tx_hash(&genesis);
+        let out_point = OutPoint::new(collection_hash.clone(), 0);
+        let utxo: BTreeMap<OutPoint, TxOut> = vec![(out_point.clone(), genesis.outputs[0].clone())]
+            .into_iter()
+            .collect();
+
+        let spend = |outputs: Vec<Asset>| {
+            let signable_hash = construct_tx_in_signable_hash(&out_point);
+            let signature = sign::sign_detached(signable_hash.as_bytes(), &sk);
+            let tx_in = TxIn::new_from_input(
+                out_point.clone(),
+                Script::pay2pkh(signable_hash, signature, pk, None),
+            );
+            let tx_outs = outputs
+                .into_iter()
+                .map(|asset| TxOut::new_asset(address.clone(), asset))
+                .collect();
+            construct_tx_core(vec![tx_in], tx_outs)
+        };
+        let item = |serial| Asset::item(Some(collection_hash.clone()), serial, None, None);
+
+        let valid = spend(vec![item(0)]);
+        let duplicated = spend(vec![item(0), item(0)]);
+        let other_serial = spend(vec![item(1)]);
+        let with_metadata = spend(vec![Asset::item(
+            Some(collection_hash.clone()),
+            0,
+            Some(ReceiptMetadata::new("Ticket".to_owned(), String::new()).encode()),
+            None,
+        )]);
+        let as_receipt = spend(vec![Asset::receipt(1, Some(collection_hash.clone()), None)]);
+
+        assert!(tx_is_valid(&valid, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&duplicated, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&other_serial, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&with_metadata, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&as_receipt, |v| utxo.get(v)));
+    }
+
+    #[test]
+    /// Checks that item membership is proven from the collection genesis and item mint
+    fn test_item_is_in_collection() {
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, other_sk) = sign::gen_keypair();
+        let genesis = construct_collection_create_tx(0, pk, &sk, None, None);
+        let other_genesis = construct_collection_create_tx(0, other_pk, &other_sk, None, None);
+        let collection_hash = construct_tx_hash(&genesis);
+        let mint = construct_item_mint_tx(0, collection_hash.clone(), 1, pk, &sk, None);
+        let forged =
+            construct_item_mint_tx(0, collection_hash.clone(), 1, other_pk, &other_sk, None);
+        let item = |serial| ItemAsset::new(Some(collection_hash.clone()), serial, None, None);
+
+        assert!(item_is_in_collection(&item(0), &genesis, &genesis));
+        assert!(item_is_in_collection(&item(1), &genesis, &mint));
+        assert!(!item_is_in_collection(&item(2), &genesis, &mint));
+        assert!(!item_is_in_collection(&item(1), &genesis, &forged));
+        assert!(!item_is_in_collection(
+            &item(0),
+            &other_genesis,
+            &other_genesis
+        ));
+    }
+
+    #[test]
+    /// Checks that receipt burns balance against their inputs and are recognised in supply
+    fn test_receipt_burn_tx() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let genesis = construct_receipt_create_tx(0, pk, &sk, 10, DrsTxHashSpec::Create, None);
+        let drs_tx_hash = construct_tx_hash(&genesis);
+        let out_point = OutPoint::new(drs_tx_hash.clone(), 0);
+        let utxo: BTreeMap<OutPoint, TxOut> = vec![(out_point.clone(), genesis.outputs[0].clone())]
+            .into_iter()
+            .collect();
+
+        let burn = |amount, change: u64| {
+            let signable_hash = construct_tx_in_signable_hash(&out_point);
+            let signature = sign::sign_detached(signable_hash.as_bytes(), &sk);
+            let tx_in = TxIn::new_from_input(
+                out_point.clone(),
+                Script::pay2pkh(signable_hash, signature, pk, None),
+            );
+            let receipt = Asset::receipt(change, Some(drs_tx_hash.clone()), None);
+            let change_tx_outs = vec![TxOut::new_asset(address.clone(), receipt)];
+            construct_receipt_burn_tx(vec![tx_in], drs_tx_hash.clone(), amount, change_tx_outs)
+        };
+
+        let redeem = burn(3, 7);
+        let mut locked = burn(3, 7);
+        locked.outputs[0].locktime = 10;
+
+        assert!(is_burn_tx_out(&redeem.outputs[0]));
+        assert!(!is_burn_tx_out(&redeem.outputs[1]));
+        assert!(tx_is_valid(&redeem, |v| utxo.get(v)));
+        assert!(!tx_is_valid(&burn(4, 7), |v| utxo.get(v)));
+        assert!(!tx_is_valid(&burn(0, 10), |v| utxo.get(v)));
+        assert!(!tx_is_valid(&locked, |v| utxo.get(v)));
+
+        // Burned receipts are subtracted from the outstanding supply
+        let mut supply = BTreeMap::new();
+        update_receipt_supply(&mut supply, &drs_tx_hash, &genesis);
+        update_receipt_supply(&mut supply, &construct_tx_hash(&redeem), &redeem);
+        let receipt_supply = supply[&drs_tx_hash];
+        assert_eq!((receipt_supply.created, receipt_supply.burned), (10, 3));
+        assert_eq!(receipt_supply.outstanding(), 7);
+    }
+
+    #[test]
+    /// Checks that the per-asset balance of a transaction reports surpluses and deficits
+    fn test_tx_balance_diff() {
+        let (utxo, tx) = generate_tx_with_ins_and_outs_assets(
+            &[
+                (10, None, None),
+                (5, Some("drs_a"), None),
+                (3, Some("drs_b"), None),
+            ],
+            &[(7, None), (6, Some("drs_a")), (3, Some("drs_b"))],
+        );
+
+        let diff = tx_balance_diff(&tx, |v| utxo.get(v)).unwrap();
+        let token = diff.get(&AssetKey::Token);
+        let receipt_a = diff.get(&AssetKey::Receipt("drs_a".to_owned()));
+
+        assert!(!diff.is_balanced());
+        assert_eq!((token.input, token.output, token.surplus()), (10, 7, 3));
+        assert_eq!(
+            (receipt_a.input, receipt_a.output, receipt_a.deficit()),
+            (5, 6, 1)
+        );
+        assert!(diff
+            .get(&AssetKey::Receipt("drs_b".to_owned()))
+            .is_balanced());
+        assert_eq!(diff.unbalanced().count(), 2);
+        assert_eq!(
+            diff.to_string(),
+            "Token: in 10 out 7 (surplus 3), Receipt drs_a: in 5 out 6 (deficit 1)"
+        );
+
+        let (utxo, tx) = generate_tx_with_ins_and_outs_assets(&[(10, None, None)], &[(10, None)]);
+        let diff = tx_balance_diff(&tx, |v| utxo.get(v)).unwrap();
+        assert!(diff.is_balanced());
+        assert_eq!(diff.to_string(), "Balanced");
+
+        let missing = tx.inputs[0].previous_out.clone().unwrap();
+        assert_eq!(
+            tx_balance_diff(&tx, |_| None),
+            Err(TxBalanceError::MissingInput(missing))
+        );
+    }
+
+    #[test]
+    /// Checks that bundled outputs balance against the tokens and receipts they hold
+    fn test_bundle_tx_outs() {
+        let (utxo, mut tx) = generate_tx_with_ins_and_outs_assets(
+            &[
+                (10, None, None),
+                (5, Some("drs_a"), None),
+                (3, Some("drs_b"), None),
+            ],
+            &[],
+        );
+        let address = construct_address(&sign::gen_keypair().0);
+        let bundle = |tokens, a, b| {
+            let receipts = vec![("drs_a".to_owned(), a), ("drs_b".to_owned(), b)]
+                .into_iter()
+                .filter(|(_, amount)| *amount != 0)
+                .collect();
+            TxOut::new_asset(
+                address.clone(),
+                Asset::bundle(TokenAmount(tokens), receipts),
+            )
+        };
+        let mut with_outputs = |outputs: Vec<TxOut>| {
+            tx.outputs = outputs;
+            tx_is_valid(&tx, |v| utxo.get(v))
+        };
+
+        let split = vec![bundle(6, 5, 0), bundle(4, 0, 3)];
+        let mixed = vec![
+            bundle(10, 5, 0),
+            TxOut::new_asset(
+                address.clone(),
+                Asset::receipt(3, Some("drs_b".to_owned()), None),
+            ),
+        ];
+        let empty = vec![bundle(10, 5, 3), bundle(0, 0, 0)];
+        let zero_entry = vec![TxOut::new_asset(
+            address.clone(),
+            Asset::bundle(
+                TokenAmount(10),
+                vec![
+                    ("drs_a".to_owned(), 5),
+                    ("drs_b".to_owned(), 3),
+                    ("drs_c".to_owned(), 0),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+        )];
+
+        assert!(with_outputs(vec![bundle(10, 5, 3)]));
+        assert!(with_outputs(split));
+        assert!(with_outputs(mixed));
+        assert!(!with_outputs(vec![bundle(10, 4, 3)]));
+        assert!(!with_outputs(empty));
+        assert!(!with_outputs(zero_entry));
+
+        // Bundles cannot be created from nothing
+        let (pk, sk) = sign::gen_keypair();
+        let asset = Asset::bundle(TokenAmount(1), BTreeMap::new());
+        let asset_hash = construct_tx_in_signable_asset_hash(&asset, NETWORK_VERSION as usize);
+        let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+        let script = Script::new_create_asset(0, asset_hash, signature, pk);
+        assert!(!tx_has_valid_create_script(
+            &script,
+            &asset,
+            NETWORK_VERSION as usize
+        ));
+    }
+
+    #[test]
+    /// Checks that dust outputs are non-standard, except when burned
+    fn test_dust_tx_outs() {
+        let (utxo, mut tx) = generate_tx_with_ins_and_outs_assets(
+            &[(1000, None, None), (5, Some("drs_a"), None)],
+            &[],
+        );
+        let address = construct_address(&sign::gen_keypair().0);
+        let token_out = |amount| TxOut::new_token_amount(address.clone(), TokenAmount(amount));
+        let receipt_out = |amount| {
+            TxOut::new_asset(
+                address.clone(),
+                Asset::receipt(amount, Some("drs_a".to_owned()), None),
+            )
+        };
+        let policy = DustPolicy::standard();
+        let mut with_outputs = |outputs: Vec<TxOut>| {
+            tx.outputs = outputs;
+            (
+                tx_is_valid(&tx, |v| utxo.get(v)),
+                tx_is_standard(&tx, &policy, |v| utxo.get(v)),
+            )
+        };
+
+        let dust_tokens = 1000 - DUST_TOKEN_AMOUNT;
+        let mut burn_out = construct_burn_tx(vec![]).outputs.remove(0);
+        burn_out.value = Asset::Token(TokenAmount(1));
+
+        assert_eq!(
+            with_outputs(vec![token_out(1000), receipt_out(5)]),
+            (true, true)
+        );
+        assert_eq!(
+            with_outputs(vec![
+                token_out(dust_tokens),
+                token_out(DUST_TOKEN_AMOUNT),
+                receipt_out(5)
+            ]),
+            (true, true)
+        );
+        assert_eq!(
+            with_outputs(vec![
+                token_out(dust_tokens + 1),
+                token_out(DUST_TOKEN_AMOUNT - 1),
+                receipt_out(5)
+            ]),
+            (true, false)
+        );
+        assert_eq!(
+            with_outputs(vec![token_out(1000), receipt_out(5), token_out(0)]),
+            (true, false)
+        );
+        assert_eq!(
+            with_outputs(vec![token_out(999), receipt_out(5), burn_out]),
+            (true, true)
+        );
+
+        // Bundles are dust only if all of their components are
+        let bundle = |tokens, receipts: Vec<(&str, u64)>| {
+            let receipts = receipts
+                .into_iter()
+                .map(|(h, a)| (h.to_owned(), a))
+                .collect();
+            Asset::bundle(TokenAmount(tokens), receipts)
+        };
+        assert!(!policy.is_dust(&bundle(1, vec![("drs_a", 1)])));
+        assert!(policy.is_dust(&bundle(1, vec![])));
+        assert!(policy.is_dust(&bundle(0, vec![("drs_a", 0)])));
+
+        // Thresholds are configurable per asset type
+        let strict = DustPolicy {
+            min_receipt: 10,
+            ..DustPolicy::standard()
+        };
+        assert!(tx_outs_are_standard(&[receipt_out(5)], &policy));
+        assert!(!tx_outs_are_standard(&[receipt_out(5)], &strict));
+        assert!(tx_outs_are_standard(
+            &construct_burn_tx(vec![]).outputs,
+            &strict
+        ));
     }
 
     #[test]
@@ -2920,7 +4284,8 @@ mod tests {
         assert!(tx_has_valid_p2pkh_sig(
             &tx_ins[0].script_signature,
             &hash_to_sign,
-            &tx_out_pk
+            &tx_out_pk,
+            &mut Stack::new()
         ));
     }
 
@@ -2960,7 +4325,8 @@ mod tests {
         assert!(!tx_has_valid_p2pkh_sig(
             &tx_ins[0].script_signature,
             &hash_to_sign,
-            &tx_out_pk
+            &tx_out_pk,
+            &mut Stack::new()
         ));
     }
 
@@ -3014,7 +4380,8 @@ mod tests {
         assert!(!tx_has_valid_p2pkh_sig(
             &tx_ins[0].script_signature,
             &hash_to_sign,
-            &tx_out_pk
+            &tx_out_pk,
+            &mut Stack::new()
         ));
     }
 
@@ -3061,7 +4428,7 @@ mod tests {
             new_tx_in
                 .script_signature
                 .stack
-                .push(StackEntry::Bytes("".to_string()));
+                .push(StackEntry::Bytes(b"".to_vec()));
             new_tx_in.previous_out = Some(entry.previous_out);
 
             tx_ins.push(new_tx_in);
@@ -3072,7 +4439,8 @@ mod tests {
         assert!(!tx_has_valid_p2pkh_sig(
             &tx_ins[0].script_signature,
             &hash_to_sign,
-            &tx_out_pk
+            &tx_out_pk,
+            &mut Stack::new()
         ));
     }
 
@@ -3153,19 +4521,19 @@ mod tests {
             // 0. Happy case: valid test
             (
                 vec![
-                    StackEntry::Bytes(valid_bytes),
+                    StackEntry::bytes_from_hex(&valid_bytes).unwrap(),
                     StackEntry::Signature(valid_sig),
                     StackEntry::PubKey(pk),
                     StackEntry::Op(OpCodes::OP_DUP),
                     StackEntry::Op(op_hash256),
-                    StackEntry::PubKeyHash(script_public_key),
+                    StackEntry::pub_key_hash_from_hex(&script_public_key).unwrap(),
                     StackEntry::Op(OpCodes::OP_EQUALVERIFY),
                     StackEntry::Op(OpCodes::OP_CHECKSIG),
                 ],
                 true,
             ),
             // 2. Empty script
-            (vec![StackEntry::Bytes("".to_string())], false),
+            (vec![StackEntry::Bytes(b"".to_vec())], false),
         ];
 
         //
@@ -3201,6 +4569,50 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Checks that a P2SH output is spent under the rule set of its script version
+    fn test_tx_is_valid_p2sh_script_version() {
+        //
+        // Arrange
+        //
+        let invalid_hash = sha3_256::digest(b"world").to_vec();
+        let script = create_sha3_preimage_script(invalid_hash);
+        let tx_outpoint = OutPoint::new(hex::encode(vec![0, 0, 0]), 0);
+        let tx_ins = vec![TxIn::new_from_input(tx_outpoint.clone(), script.clone())];
+        let tx = construct_payment_tx(
+            tx_ins,
+            hex::encode(vec![0; 32]),
+            None,
+            Asset::token_u64(5),
+            0,
+        );
+        let tx_ins_previous_outs: Vec<TxOut> = vec![SCRIPT_VERSION_V0, SCRIPT_VERSION_V1]
+            .into_iter()
+            .map(|script_version| TxOut {
+                value: Asset::token_u64(5),
+                script_public_key: construct_p2sh_address_for(&script, script_version),
+                ..Default::default()
+            })
+            .collect();
+
+        //
+        // Act
+        //
+        let actual_result: Vec<bool> = tx_ins_previous_outs
+            .iter()
+            .map(|tx_in_previous_out| {
+                tx_is_valid(&tx, |v| {
+                    Some(tx_in_previous_out).filter(|_| v == &tx_outpoint)
+                })
+            })
+            .collect();
+
+        //
+        // Assert
+        //
+        assert_eq!(actual_result, vec![true, false]);
+    }
+
     #[test]
     /// ### Test Case 1
     ///
@@ -3334,6 +4746,100 @@ mod tests {
         );
     }
 
+    #[test]
+    /// ### Test Case 8
+    ///
+    ///  - *Tokens only*
+    /// -  *Failure*
+    ///
+    /// 1. Inputs contain two `TxIn`s for `Token`s whose sum overflows
+    /// 2. Outputs contain the same `TxOut`s
+    fn test_tx_drs_tokens_only_failure_overflow() {
+        test_tx_drs_common(
+            &[(u64::MAX, None, None), (1, None, None)],
+            &[(u64::MAX, None), (1, None)],
+            false,
+        );
+    }
+
+    #[test]
+    /// ### Test Case 9
+    ///
+    ///  - *Receipts only*
+    /// -  *Failure*
+    ///
+    /// 1. Inputs contain two `TxIn`s for `Receipt`s of the same DRS whose sum overflows
+    /// 2. Outputs contain the same `TxOut`s
+    fn test_tx_drs_receipts_only_failure_overflow() {
+        test_tx_drs_common(
+            &[
+                (u64::MAX, Some("drs_tx_hash"), None),
+                (1, Some("drs_tx_hash"), None),
+            ],
+            &[(u64::MAX, Some("drs_tx_hash")), (1, Some("drs_tx_hash"))],
+            false,
+        );
+    }
+
+    #[test]
+    /// ### Test Case 10
+    ///
+    ///  - *Tokens only*
+    /// -  *Success* at the token supply, *Failure* above it
+    ///
+    /// 1. Inputs and outputs contain `Token`s summing to `TOTAL_TOKENS`, then one more
+    fn test_tx_drs_tokens_only_total_tokens_bound() {
+        test_tx_drs_common(
+            &[(TOTAL_TOKENS - 1, None, None), (1, None, None)],
+            &[(TOTAL_TOKENS - 1, None), (1, None)],
+            true,
+        );
+        test_tx_drs_common(
+            &[(TOTAL_TOKENS, None, None), (1, None, None)],
+            &[(TOTAL_TOKENS, None), (1, None)],
+            false,
+        );
+    }
+
+    #[test]
+    /// Checks that running totals reject overflow and underflow without being modified
+    fn test_asset_values_checked_updates() {
+        let receipt = |amount| Asset::receipt(amount, Some("drs_tx_hash".to_owned()), None);
+        let mut values = AssetValues::token_u64(u64::MAX);
+        values.update_add(&receipt(u64::MAX)).unwrap();
+        let before = values.clone();
+
+        assert_eq!(
+            values.update_add(&Asset::token_u64(1)),
+            Err(AssetValuesError::TokenOverflow)
+        );
+        assert_eq!(
+            values.update_add(&receipt(1)),
+            Err(AssetValuesError::ReceiptOverflow("drs_tx_hash".to_owned()))
+        );
+        assert_eq!(values, before);
+
+        let mut values = AssetValues::default();
+        assert_eq!(
+            values.update_sub(&Asset::token_u64(1)),
+            Err(AssetValuesError::TokenUnderflow)
+        );
+        assert_eq!(
+            values.update_sub(&receipt(1)),
+            Err(AssetValuesError::ReceiptUnderflow("drs_tx_hash".to_owned()))
+        );
+        assert_eq!(values, AssetValues::default());
+
+        let mut token = Asset::token_u64(u64::MAX);
+        assert!(!token.add_assign(&Asset::token_u64(1)));
+        assert_eq!(token, Asset::token_u64(u64::MAX));
+        assert_eq!(
+            TokenAmount(u64::MAX).saturating_add(TokenAmount(1)).0,
+            u64::MAX
+        );
+        assert_eq!(TokenAmount(0).saturating_sub(TokenAmount(1)).0, 0);
+    }
+
     /// Test transaction validation with multiple different DRS
     /// configurations for `TxIn` and `TxOut` values
     fn test_tx_drs_common(
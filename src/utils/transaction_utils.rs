@@ -1,11 +1,19 @@
 use crate::constants::*;
+use crate::crypto::sealedbox_x25519_chacha20_poly1305 as sealedbox;
 use crate::crypto::sha3_256;
 use crate::crypto::sign_ed25519::{self as sign, PublicKey, SecretKey};
-use crate::primitives::asset::{Asset, DataAsset, TokenAmount};
-use crate::primitives::druid::{DdeValues, DruidExpectation};
+use crate::primitives::asset::{
+    Asset, AssetValues, CollectionDefinition, DataAsset, IssuanceDefinition, ReceiptSupply,
+    SupplyRule, TokenAmount,
+};
+use crate::primitives::data_manifest::DataManifest;
+use crate::primitives::druid::{
+    DdeFlow, DdeParty, DdeTrade, DdeTradeError, DdeValues, Druid, DruidExpectation,
+};
 use crate::primitives::transaction::*;
 use crate::script::lang::Script;
 use crate::script::{OpCodes, StackEntry};
+use crate::utils::druid_utils::validate_druid_txs;
 use bincode::serialize;
 use std::collections::BTreeMap;
 
@@ -25,6 +33,52 @@ pub fn construct_p2sh_address(script: &Script) -> String {
     addr
 }
 
+/// Builds a P2SH address whose script is run under the rule set of a script version.
+/// Version 0 addresses are those of `construct_p2sh_address`, while later versions
+/// replace the start of the hash with a marker and the version, keeping the length
+///
+/// ### Arguments
+///
+/// * `script`          - Script to build address for
+/// * `script_version`  - Script version to run the script under, up to `MAX_SCRIPT_VERSION`
+pub fn construct_p2sh_address_for(script: &Script, script_version: u64) -> Option<String> {
+    let addr = construct_p2sh_address(script);
+    match script_version {
+        SCRIPT_VERSION_V0 => Some(addr),
+        v if v > MAX_SCRIPT_VERSION => None,
+        v => {
+            let mut versioned = format!(
+                "{}{}{:02x}",
+                P2SH_PREPEND as char, P2SH_VERSION_MARKER as char, v
+            );
+            versioned.push_str(&addr[1..]);
+            versioned.truncate(STANDARD_ADDRESS_LENGTH);
+            Some(versioned)
+        }
+    }
+}
+
+/// Gets the script version encoded in a P2SH address, or `None` if the address
+/// is not a P2SH address or its version is not canonically encoded
+///
+/// ### Arguments
+///
+/// * `address` - P2SH address to get the script version of
+pub fn p2sh_address_script_version(address: &str) -> Option<u64> {
+    let bytes = address.as_bytes();
+    if bytes.first() != Some(&P2SH_PREPEND) {
+        return None;
+    }
+    if bytes.get(1) != Some(&P2SH_VERSION_MARKER) {
+        return Some(SCRIPT_VERSION_V0);
+    }
+
+    let digits = address.get(2..4)?;
+    let version = u64::from_str_radix(digits, 16).ok()?;
+    let is_canonical = digits == format!("{version:02x}") && version != SCRIPT_VERSION_V0;
+    is_canonical.then_some(version)
+}
+
 /// Builds an address from a public key and a specified network version
 ///
 /// ### Arguments
@@ -119,12 +173,14 @@ pub fn construct_tx_in_signable_hash(previous_out: &OutPoint) -> String {
     ))
 }
 
-/// Constructs signable string for an Asset
+/// Constructs signable string for an Asset. Receipt metadata is only part of
+/// the string for transactions after `NETWORK_VERSION_LEGACY_RECEIPT_METADATA`
 ///
 /// ### Arguments
 ///
-/// * `asset`   - Asset to sign
-pub fn get_asset_signable_string(asset: &Asset) -> String {
+/// * `asset`       - Asset to sign
+/// * `tx_version`  - Version of the transaction creating the asset
+pub fn get_asset_signable_string(asset: &Asset, tx_version: usize) -> String {
     match asset {
         Asset::Token(token_amount) => format!("Token:{}", token_amount.0),
         Asset::Data(data_asset) => format!(
@@ -132,7 +188,49 @@ pub fn get_asset_signable_string(asset: &Asset) -> String {
             hex::encode(&data_asset.data),
             data_asset.amount
         ),
-        Asset::Receipt(receipt) => format!("Receipt:{}", receipt.amount),
+        Asset::Receipt(receipt) => match &receipt.metadata {
+            Some(metadata) if tx_version > NETWORK_VERSION_LEGACY_RECEIPT_METADATA => {
+                format!("Receipt:{}-{}", receipt.amount, metadata)
+            }
+            _ => format!("Receipt:{}", receipt.amount),
+        },
+        Asset::Issued(issued) => match (&issued.genesis_hash, &issued.definition) {
+            (_, Some(definition)) => format!(
+                "Issued:{}-{}-{}-{}",
+                issued.amount,
+                hex::encode(definition.issuer.as_ref()),
+                definition.decimals,
+                match definition.supply {
+                    SupplyRule::Fixed => "Fixed".to_owned(),
+                    SupplyRule::Max(max) => format!("Max:{max}"),
+                }
+            ),
+            (Some(genesis_hash), None) => format!("Issued:{}-{}", issued.amount, genesis_hash),
+            (None, None) => format!("Issued:{}", issued.amount),
+        },
+        Asset::Item(item) => {
+            let mut signable = format!("Item:{}", item.serial);
+            if let Some(collection_hash) = &item.collection_hash {
+                signable.push_str(&format!("-{collection_hash}"));
+            }
+            if let Some(definition) = &item.definition {
+                signable.push_str(&format!("-{}", hex::encode(definition.creator.as_ref())));
+                if let Some(max_items) = definition.max_items {
+                    signable.push_str(&format!("-Max:{max_items}"));
+                }
+            }
+            if let Some(metadata) = &item.metadata {
+                signable.push_str(&format!("-{metadata}"));
+            }
+            signable
+        }
+        Asset::Bundle(bundle) => {
+            let mut signable = format!("Bundle:{}", bundle.tokens.0);
+            for (drs_tx_hash, amount) in &bundle.receipts {
+                signable.push_str(&format!("-{drs_tx_hash}:{amount}"));
+            }
+            signable
+        }
     }
 }
 
@@ -140,10 +238,11 @@ pub fn get_asset_signable_string(asset: &Asset) -> String {
 ///
 /// ### Arguments
 ///
-/// * `asset`   - Asset to sign
-pub fn construct_tx_in_signable_asset_hash(asset: &Asset) -> String {
+/// * `asset`       - Asset to sign
+/// * `tx_version`  - Version of the transaction creating the asset
+pub fn construct_tx_in_signable_asset_hash(asset: &Asset, tx_version: usize) -> String {
     hex::encode(sha3_256::digest(
-        get_asset_signable_string(asset).as_bytes(),
+        get_asset_signable_string(asset, tx_version).as_bytes(),
     ))
 }
 
@@ -159,9 +258,13 @@ pub fn get_stack_entry_signable_string(entry: &StackEntry) -> String {
             format!("Signature:{}", hex::encode(signature.as_ref()))
         }
         StackEntry::PubKey(pub_key) => format!("PubKey:{}", hex::encode(pub_key.as_ref())),
-        StackEntry::PubKeyHash(pub_key_hash) => format!("PubKeyHash:{pub_key_hash}"),
+        StackEntry::PubKeyHash(pub_key_hash) => {
+            format!("PubKeyHash:{}", hex::encode(pub_key_hash))
+        }
         StackEntry::Num(num) => format!("Num:{num}"),
-        StackEntry::Bytes(bytes) => format!("Bytes:{bytes}"),
+        StackEntry::Bytes(bytes) => format!("Bytes:{}", hex::encode(bytes)),
+        StackEntry::LegacyPubKeyHash(pub_key_hash) => format!("PubKeyHash:{pub_key_hash}"),
+        StackEntry::LegacyBytes(bytes) => format!("Bytes:{bytes}"),
     }
 }
 
@@ -298,7 +401,7 @@ pub fn construct_tx_hash(tx: &Transaction) -> String {
     hash
 }
 
-/// Construct a valid TxIn for a new create asset transaction
+/// Construct a valid TxIn for a new create asset transaction of the current network version
 ///
 /// ### Arguments
 ///
@@ -312,7 +415,7 @@ pub fn construct_create_tx_in(
     public_key: PublicKey,
     secret_key: &SecretKey,
 ) -> Vec<TxIn> {
-    let asset_hash = construct_tx_in_signable_asset_hash(asset);
+    let asset_hash = construct_tx_in_signable_asset_hash(asset, NETWORK_VERSION as usize);
     let signature = sign::sign_detached(asset_hash.as_bytes(), secret_key);
 
     vec![TxIn {
@@ -350,6 +453,98 @@ pub fn construct_create_tx(
     construct_tx_core(tx_ins, vec![tx_out])
 }
 
+/// Constructs a transaction for the creation of a data asset sealed for a recipient
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `plain_text`          - Payload to seal
+/// * `recipient`           - Sealed box public key of the recipient
+/// * `public_key`          - Public key for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+/// * `amount`              - Amount of the asset to generate
+pub fn construct_encrypted_create_tx(
+    block_num: u64,
+    plain_text: Vec<u8>,
+    recipient: &sealedbox::PublicKey,
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+    amount: u64,
+) -> Option<Transaction> {
+    let asset = Asset::Data(DataAsset::encrypted(plain_text, recipient, amount)?);
+    let receiver_address = construct_address(&public_key);
+
+    let tx_ins = construct_create_tx_in(block_num, &asset, public_key, secret_key);
+    let tx_out = TxOut {
+        value: asset,
+        script_public_key: Some(receiver_address),
+        ..Default::default()
+    };
+
+    Some(construct_tx_core(tx_ins, vec![tx_out]))
+}
+
+/// Constructs a transaction transferring a sealed data asset to a new owner,
+/// sealing its payload again so only the new owner can read it
+///
+/// ### Arguments
+///
+/// * `tx_ins`              - Input/s spending the data asset
+/// * `asset`               - Sealed data asset to transfer
+/// * `owner_secret_key`    - Secret key of the current owner
+/// * `receiver_address`    - Address to send to
+/// * `recipient`           - Sealed box public key of the new owner
+/// * `drs_block_hash`      - Hash of the block containing the original DRS
+pub fn construct_encrypted_transfer_tx(
+    tx_ins: Vec<TxIn>,
+    asset: &DataAsset,
+    owner_secret_key: &SecretKey,
+    receiver_address: String,
+    recipient: &sealedbox::PublicKey,
+    drs_block_hash: Option<String>,
+) -> Option<Transaction> {
+    let asset = Asset::Data(asset.reencrypt(owner_secret_key, recipient)?);
+    Some(construct_payment_tx(
+        tx_ins,
+        receiver_address,
+        drs_block_hash,
+        asset,
+        0,
+    ))
+}
+
+/// Constructs the transactions creating a data asset too large for a single output.
+/// The payload is split into chunks of `DATA_CHUNK_SIZE` bytes, each created by its own
+/// transaction, followed by a transaction creating the manifest that ties them together
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `data`                - Payload to create
+/// * `public_key`          - Public key for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+pub fn construct_chunked_create_txs(
+    block_num: u64,
+    data: &[u8],
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+) -> (DataManifest, Vec<Transaction>) {
+    let (manifest, chunks) = DataManifest::split(data, DATA_CHUNK_SIZE);
+    let mut txs: Vec<Transaction> = chunks
+        .into_iter()
+        .map(|chunk| construct_create_tx(block_num, chunk, public_key, secret_key, 1))
+        .collect();
+    txs.push(construct_create_tx(
+        block_num,
+        manifest.encode(),
+        public_key,
+        secret_key,
+        1,
+    ));
+
+    (manifest, txs)
+}
+
 /// Constructs a receipt data asset for use in accepting payments
 /// TODO: On compute, figure out a way to ease flow of receipts without issue for users
 ///
@@ -359,6 +554,8 @@ pub fn construct_create_tx(
 /// * `public_key`          - Public key for the output address
 /// * `secret_key`          - Corresponding secret key for signing data
 /// * `amount`              - Amount of receipt assets to create
+/// * `drs_tx_hash_spec`    - Specification of the DRS transaction hash
+/// * `metadata`            - Canonically encoded `ReceiptMetadata`, if any
 pub fn construct_receipt_create_tx(
     block_num: u64,
     public_key: PublicKey,
@@ -381,6 +578,191 @@ pub fn construct_receipt_create_tx(
     construct_tx_core(tx_ins, vec![tx_out])
 }
 
+/// Constructs the genesis transaction of an issuer-controlled fungible token.
+/// The public key signing the transaction becomes the token's issuer
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `public_key`          - Issuer public key, also used for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+/// * `amount`              - Amount of tokens to issue at genesis
+/// * `decimals`            - Number of decimal places the token is displayed with
+/// * `supply`              - Supply rule governing any later re-issuance
+pub fn construct_issued_token_create_tx(
+    block_num: u64,
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+    amount: u64,
+    decimals: u8,
+    supply: SupplyRule,
+) -> Transaction {
+    let definition = IssuanceDefinition {
+        issuer: public_key,
+        decimals,
+        supply,
+    };
+    let asset = Asset::issued(amount, None, Some(definition));
+    let receiver_address = construct_address(&public_key);
+
+    let tx_ins = construct_create_tx_in(block_num, &asset, public_key, secret_key);
+    let tx_out = TxOut {
+        value: asset,
+        script_public_key: Some(receiver_address),
+        ..Default::default()
+    };
+
+    construct_tx_core(tx_ins, vec![tx_out])
+}
+
+/// Constructs a transaction re-issuing more of an existing issued token.
+/// Only valid when signed by the token's issuer and within its supply rule
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `genesis_hash`        - Hash of the token's genesis transaction
+/// * `public_key`          - Issuer public key, also used for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+/// * `amount`              - Amount of tokens to re-issue
+pub fn construct_reissuance_tx(
+    block_num: u64,
+    genesis_hash: String,
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+    amount: u64,
+) -> Transaction {
+    let asset = Asset::issued(amount, Some(genesis_hash), None);
+    let receiver_address = construct_address(&public_key);
+
+    let tx_ins = construct_create_tx_in(block_num, &asset, public_key, secret_key);
+    let tx_out = TxOut {
+        value: asset,
+        script_public_key: Some(receiver_address),
+        ..Default::default()
+    };
+
+    construct_tx_core(tx_ins, vec![tx_out])
+}
+
+/// Constructs the genesis transaction of a non-fungible item collection, which mints
+/// the item with serial 0. The public key signing the transaction becomes the creator
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `public_key`          - Creator public key, also used for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+/// * `max_items`           - Maximum number of items in the collection, if limited
+/// * `metadata`            - Canonically encoded `ReceiptMetadata` of the genesis item, if any
+pub fn construct_collection_create_tx(
+    block_num: u64,
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+    max_items: Option<u64>,
+    metadata: Option<String>,
+) -> Transaction {
+    let definition = CollectionDefinition {
+        creator: public_key,
+        max_items,
+    };
+    let asset = Asset::item(None, 0, metadata, Some(definition));
+    let receiver_address = construct_address(&public_key);
+
+    let tx_ins = construct_create_tx_in(block_num, &asset, public_key, secret_key);
+    let tx_out = TxOut {
+        value: asset,
+        script_public_key: Some(receiver_address),
+        ..Default::default()
+    };
+
+    construct_tx_core(tx_ins, vec![tx_out])
+}
+
+/// Constructs a transaction minting a new item into an existing collection.
+/// Only valid when signed by the collection's creator with an unused serial
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `collection_hash`     - Hash of the collection's genesis transaction
+/// * `serial`              - Serial of the new item
+/// * `public_key`          - Creator public key, also used for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+/// * `metadata`            - Canonically encoded `ReceiptMetadata` of the item, if any
+pub fn construct_item_mint_tx(
+    block_num: u64,
+    collection_hash: String,
+    serial: u64,
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+    metadata: Option<String>,
+) -> Transaction {
+    let asset = Asset::item(Some(collection_hash), serial, metadata, None);
+    let receiver_address = construct_address(&public_key);
+
+    let tx_ins = construct_create_tx_in(block_num, &asset, public_key, secret_key);
+    let tx_out = TxOut {
+        value: asset,
+        script_public_key: Some(receiver_address),
+        ..Default::default()
+    };
+
+    construct_tx_core(tx_ins, vec![tx_out])
+}
+
+/// Traces the outputs that held an item, in order from the output that minted it
+/// to the one holding it last. Each output's `script_public_key` is the owner at that point
+///
+/// ### Arguments
+///
+/// * `collection_hash` - Hash of the collection's genesis transaction
+/// * `serial`          - Serial of the item
+/// * `txs`             - Transactions to search, keyed by transaction hash
+pub fn get_item_owner_history<'a>(
+    collection_hash: &str,
+    serial: u64,
+    txs: &'a BTreeMap<String, Transaction>,
+) -> Vec<(OutPoint, &'a TxOut)> {
+    let holds_item = |out_point: &OutPoint, tx_out: &TxOut| {
+        matches!(
+            tx_out.value.clone().with_fixed_hash(out_point),
+            Asset::Item(item) if item.serial == serial
+                && item.collection_hash.as_deref() == Some(collection_hash)
+        )
+    };
+    let spent_by: BTreeMap<&OutPoint, &String> = txs
+        .iter()
+        .flat_map(|(hash, tx)| tx.inputs.iter().map(move |tx_in| (tx_in, hash)))
+        .filter_map(|(tx_in, hash)| tx_in.previous_out.as_ref().map(|op| (op, hash)))
+        .collect();
+    let find_in = |hash: &String| {
+        txs.get(hash).and_then(|tx| {
+            tx.outputs
+                .iter()
+                .enumerate()
+                .map(|(n, tx_out)| (OutPoint::new(hash.clone(), n as i32), tx_out))
+                .find(|(out_point, tx_out)| holds_item(out_point, tx_out))
+        })
+    };
+
+    let mint = txs
+        .iter()
+        .filter(|(_, tx)| tx.is_create_tx())
+        .find_map(|(hash, _)| find_in(hash));
+
+    // Each transaction can hold the item at most once, which bounds malformed cycles
+    let mut history = Vec::new();
+    let mut current = mint;
+    while let Some((out_point, tx_out)) = current.take() {
+        if history.len() < txs.len() {
+            current = spent_by.get(&out_point).and_then(|hash| find_in(hash));
+        }
+        history.push((out_point, tx_out));
+    }
+    history
+}
+
 /// Constructs a transaction to pay a receiver
 ///
 /// TODO: Check whether the `amount` is valid in the TxIns
@@ -438,24 +820,160 @@ pub fn construct_p2sh_tx(
     construct_tx_core(tx_ins, vec![tx_out])
 }
 
+/// Constructs the P2SH address of the `OP_BURN` script, which can never be spent
+pub fn construct_burn_address() -> String {
+    let script = Script::from(vec![StackEntry::Op(OpCodes::OP_BURN)]);
+    construct_p2sh_address(&script)
+}
+
+/// Returns whether an output is sent to the burn address and is therefore destroyed
+///
+/// ### Arguments
+///
+/// * `tx_out`  - Output to check
+pub fn is_burn_tx_out(tx_out: &TxOut) -> bool {
+    tx_out.script_public_key.as_deref() == Some(construct_burn_address().as_str())
+}
+
 /// Constructs a P2SH transaction to burn tokens
 ///
 /// ### Arguments
 ///
 /// * `tx_ins`  - Input/s to pay from
 pub fn construct_burn_tx(tx_ins: Vec<TxIn>) -> Transaction {
-    let s = vec![StackEntry::Op(OpCodes::OP_BURN)];
-    let script = Script::from(s);
-    let script_hash = construct_p2sh_address(&script);
-
     let tx_out = TxOut {
-        script_public_key: Some(script_hash),
+        script_public_key: Some(construct_burn_address()),
         ..Default::default()
     };
 
     construct_tx_core(tx_ins, vec![tx_out])
 }
 
+/// Constructs a transaction that burns an amount of a receipt, e.g. when a voucher
+/// is redeemed. Any receipts or tokens in the inputs that are not burned must be
+/// returned in `change_tx_outs`
+///
+/// ### Arguments
+///
+/// * `tx_ins`          - Input/s holding the receipts to burn
+/// * `drs_tx_hash`     - DRS transaction hash of the receipt to burn
+/// * `amount`          - Amount of the receipt to burn
+/// * `change_tx_outs`  - Output/s returning the remainder of the inputs
+pub fn construct_receipt_burn_tx(
+    tx_ins: Vec<TxIn>,
+    drs_tx_hash: String,
+    amount: u64,
+    change_tx_outs: Vec<TxOut>,
+) -> Transaction {
+    let tx_out = TxOut {
+        value: Asset::receipt(amount, Some(drs_tx_hash), None),
+        script_public_key: Some(construct_burn_address()),
+        ..Default::default()
+    };
+
+    let mut tx_outs = vec![tx_out];
+    tx_outs.extend(change_tx_outs);
+    construct_tx_core(tx_ins, tx_outs)
+}
+
+/// Updates the supply of each receipt with the receipts created and burned by a transaction,
+/// including receipts burned within bundles
+///
+/// ### Arguments
+///
+/// * `supply`  - Supply per `drs_tx_hash` to update
+/// * `tx_hash` - Hash of the transaction
+/// * `tx`      - Transaction to account for
+pub fn update_receipt_supply(
+    supply: &mut BTreeMap<String, ReceiptSupply>,
+    tx_hash: &str,
+    tx: &Transaction,
+) {
+    if tx.is_create_tx() {
+        if let Some(Asset::Receipt(receipt)) = tx.get_create_asset() {
+            let drs_tx_hash = receipt.drs_tx_hash.as_deref().unwrap_or(tx_hash);
+            let entry = supply.entry(drs_tx_hash.to_owned()).or_default();
+            entry.created = entry.created.saturating_add(receipt.amount);
+        }
+        return;
+    }
+
+    for burned in burned_assets(tx) {
+        if let Asset::Receipt(receipt) = burned {
+            if let Some(drs_tx_hash) = receipt.drs_tx_hash {
+                let entry = supply.entry(drs_tx_hash).or_default();
+                entry.burned = entry.burned.saturating_add(receipt.amount);
+            }
+        }
+    }
+}
+
+/// Updates the supply of each asset with the units created and burned by a transaction.
+/// Receipts are accounted as in `update_receipt_supply`. Data assets, issued tokens and
+/// item collections are keyed by their genesis transaction hash, with collections
+/// counting items rather than units. Burned data assets carry no genesis hash and
+/// cannot be accounted, while burned bundles are accounted as their contents
+///
+/// ### Arguments
+///
+/// * `supply`  - Supply per genesis transaction hash to update
+/// * `tx_hash` - Hash of the transaction
+/// * `tx`      - Transaction to account for
+pub fn update_asset_supply(
+    supply: &mut BTreeMap<String, ReceiptSupply>,
+    tx_hash: &str,
+    tx: &Transaction,
+) {
+    update_receipt_supply(supply, tx_hash, tx);
+
+    if tx.is_create_tx() {
+        let created = match tx.get_create_asset() {
+            Some(Asset::Data(data)) => Some((None, data.amount)),
+            Some(Asset::Issued(issued)) => Some((issued.genesis_hash.as_deref(), issued.amount)),
+            Some(Asset::Item(item)) => Some((item.collection_hash.as_deref(), 1)),
+            _ => None,
+        };
+        if let Some((genesis_hash, amount)) = created {
+            let entry = supply
+                .entry(genesis_hash.unwrap_or(tx_hash).to_owned())
+                .or_default();
+            entry.created = entry.created.saturating_add(amount);
+        }
+        return;
+    }
+
+    for burned in burned_assets(tx) {
+        let burned = match burned {
+            Asset::Issued(issued) => {
+                let amount = issued.amount;
+                issued.genesis_hash.map(|h| (h, amount))
+            }
+            Asset::Item(item) => item.collection_hash.map(|h| (h, 1)),
+            _ => None,
+        };
+        if let Some((genesis_hash, amount)) = burned {
+            let entry = supply.entry(genesis_hash).or_default();
+            entry.burned = entry.burned.saturating_add(amount);
+        }
+    }
+}
+
+/// Gets the assets burned by a transaction, with the contents of burned bundles
+/// in place of the bundles themselves
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to get the burned assets of
+fn burned_assets(tx: &Transaction) -> impl Iterator<Item = Asset> + '_ {
+    tx.outputs
+        .iter()
+        .filter(|tx_out| is_burn_tx_out(tx_out))
+        .flat_map(|tx_out| match &tx_out.value {
+            Asset::Bundle(bundle) => bundle.components().collect(),
+            asset => vec![asset.clone()],
+        })
+}
+
 /// Constructs a transaction to pay a receivers
 /// If TxIn collection does not add up to the exact amount to pay,
 /// payer will always need to provide a return payment in tx_outs,
@@ -497,6 +1015,7 @@ pub fn construct_rb_tx_core(
         druid,
         participants: 2,
         expectations: druid_expectation,
+        expiry_height: None,
     });
 
     tx
@@ -629,20 +1148,234 @@ pub fn construct_dde_tx(
         druid,
         participants,
         expectations,
+        expiry_height: None,
     });
 
     tx
 }
 
+/// Constructs a dual double entry tx that can only be matched with the other
+/// parties' transactions below a block height
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID value to match with the other party
+/// * `tx_ins`          - Addresses to pay from
+/// * `tx_outs`         - Outputs paying the other parties
+/// * `participants`    - Participants in trade
+/// * `expectations`    - Expectations of all participants
+/// * `expiry_height`   - Block height from which the trade can no longer complete
+pub fn construct_expiring_dde_tx(
+    druid: String,
+    tx_ins: Vec<TxIn>,
+    tx_outs: Vec<TxOut>,
+    participants: usize,
+    expectations: Vec<DruidExpectation>,
+    expiry_height: u64,
+) -> Transaction {
+    let mut tx = construct_dde_tx(druid, tx_ins, tx_outs, participants, expectations);
+    if let Some(druid_info) = &mut tx.druid_info {
+        druid_info.expiry_height = Some(expiry_height);
+    }
+
+    tx
+}
+
+/// Constructs an N-party DDE trade from each party's committed inputs and the
+/// assets they send. Each party's transaction pays its flows and returns the rest
+/// of its inputs to its change address; its expectations are the flows of all parties.
+///
+/// Input signatures only commit to the spent outputs, so the transactions need no
+/// further signing once built. The trade is checked to be balanced and to meet all
+/// its expectations before being returned. Data assets cannot be accounted for in
+/// change, so parties can neither send nor commit them
+///
+/// ### Arguments
+///
+/// * `parties`         - Parties of the trade, with the inputs they commit
+/// * `flows`           - Assets sent by each party
+/// * `expiry_height`   - Block height from which the trade can no longer complete, if any
+/// * `is_in_utxo`      - UTXO view to look up the committed inputs in
+pub fn construct_dde_trade<'a>(
+    parties: &[DdeParty],
+    flows: &[DdeFlow],
+    expiry_height: Option<u64>,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+) -> Result<DdeTrade, DdeTradeError> {
+    if parties.len() < 2 {
+        return Err(DdeTradeError::TooFewParties);
+    }
+    if let Some(flow) = flows.iter().find(|flow| flow.from >= parties.len()) {
+        return Err(DdeTradeError::UnknownParty(flow.from));
+    }
+    if let Some(flow) = flows.iter().find(|flow| flow.asset.is_data()) {
+        return Err(DdeTradeError::UnsupportedAsset(flow.from));
+    }
+
+    let addresses: Vec<String> = parties
+        .iter()
+        .map(|party| construct_tx_ins_address(&party.tx_ins))
+        .collect();
+    let expectations: Vec<DruidExpectation> = flows
+        .iter()
+        .map(|flow| DruidExpectation {
+            from: addresses[flow.from].clone(),
+            to: flow.to.clone(),
+            asset: flow.asset.clone(),
+            ..Default::default()
+        })
+        .collect();
+    let druid = Druid::generate(&addresses, &expectations).to_string();
+
+    let mut transactions = Vec::with_capacity(parties.len());
+    for (index, party) in parties.iter().enumerate() {
+        if party.tx_ins.is_empty() {
+            return Err(DdeTradeError::NoInputs(index));
+        }
+        let party_flows: Vec<&DdeFlow> = flows.iter().filter(|f| f.from == index).collect();
+        if party_flows.is_empty() {
+            return Err(DdeTradeError::NoFlows(index));
+        }
+
+        let mut tx_ins_spent = AssetValues::default();
+        for out_point in party.tx_ins.iter().filter_map(|i| i.previous_out.as_ref()) {
+            let tx_out = is_in_utxo(out_point)
+                .ok_or_else(|| DdeTradeError::MissingInput(index, out_point.clone()))?;
+            if tx_out.value.is_data() {
+                return Err(DdeTradeError::UnsupportedAsset(index));
+            }
+            let asset = tx_out.value.clone().with_fixed_hash(out_point);
+            tx_ins_spent
+                .update_add(&asset)
+                .map_err(|e| DdeTradeError::InsufficientInputs(index, e))?;
+        }
+        let mut change = tx_ins_spent.clone();
+        for flow in &party_flows {
+            change
+                .update_sub(&flow.asset)
+                .map_err(|e| DdeTradeError::InsufficientInputs(index, e))?;
+        }
+
+        let mut tx_outs: Vec<TxOut> = party_flows
+            .iter()
+            .map(|flow| TxOut::new_asset(flow.to.clone(), flow.asset.clone()))
+            .collect();
+        let change_assets = change.to_assets();
+        if !change_assets.is_empty() {
+            let change_address = party
+                .change_address
+                .as_ref()
+                .ok_or(DdeTradeError::MissingChangeAddress(index))?;
+            tx_outs.extend(
+                change_assets
+                    .into_iter()
+                    .map(|asset| TxOut::new_asset(change_address.clone(), asset)),
+            );
+        }
+
+        let tx_ins = party.tx_ins.clone();
+        let tx = match expiry_height {
+            Some(expiry_height) => construct_expiring_dde_tx(
+                druid.clone(),
+                tx_ins,
+                tx_outs,
+                parties.len(),
+                expectations.clone(),
+                expiry_height,
+            ),
+            None => construct_dde_tx(
+                druid.clone(),
+                tx_ins,
+                tx_outs,
+                parties.len(),
+                expectations.clone(),
+            ),
+        };
+
+        let mut tx_outs_spent = AssetValues::default();
+        for tx_out in &tx.outputs {
+            tx_outs_spent
+                .update_add(&tx_out.value)
+                .map_err(|_| DdeTradeError::Unbalanced(index))?;
+        }
+        if !tx_outs_spent.is_equal(&tx_ins_spent) {
+            return Err(DdeTradeError::Unbalanced(index));
+        }
+        transactions.push(tx);
+    }
+
+    validate_druid_txs(&druid, transactions.iter()).map_err(DdeTradeError::Unsatisfiable)?;
+    Ok(DdeTrade {
+        druid,
+        expectations,
+        transactions,
+    })
+}
+
+/// Constructs the refund of a DDE transaction whose DRUID has expired, spending the
+/// same committed inputs back to the address they spent from. Returns `None` if the
+/// transaction has no expiry, has not expired at the given height, or its inputs do
+/// not all spend from the same P2PKH address
+///
+/// Input signatures only commit to the outpoints they spend, so anyone holding the
+/// DDE transaction can build this refund. The assets are therefore only ever returned
+/// to the address the inputs spent from, never to an address chosen by the caller
+///
+/// ### Arguments
+///
+/// * `dde_tx`          - Expired DDE transaction to refund
+/// * `block_height`    - Height of the block the refund would be included in
+pub fn construct_dde_refund_tx(dde_tx: &Transaction, block_height: u64) -> Option<Transaction> {
+    let druid_info = dde_tx.druid_info.as_ref()?;
+    if druid_info.expiry_height.is_none() || !druid_info.is_expired_at(block_height) {
+        return None;
+    }
+
+    let mut addresses = dde_tx.inputs.iter().map(get_tx_in_p2pkh_address);
+    let refund_address = addresses.next()??;
+    if addresses.any(|address| address.as_ref() != Some(&refund_address)) {
+        return None;
+    }
+
+    let tx_outs = dde_tx
+        .outputs
+        .iter()
+        .map(|tx_out| TxOut {
+            locktime: 0,
+            script_public_key: Some(refund_address.clone()),
+            ..tx_out.clone()
+        })
+        .collect();
+
+    Some(construct_tx_core(dde_tx.inputs.clone(), tx_outs))
+}
+
+/// Gets the address a P2PKH input spends from, as committed to by its script
+///
+/// ### Arguments
+///
+/// * `tx_in`   - Input to get the address of
+fn get_tx_in_p2pkh_address(tx_in: &TxIn) -> Option<String> {
+    let script = &tx_in.script_signature.stack;
+    match script.get(5)? {
+        StackEntry::PubKeyHash(hash) if script.len() == 8 => Some(hex::encode(hash)),
+        StackEntry::LegacyPubKeyHash(hash) if script.len() == 8 => Some(hash.clone()),
+        _ => None,
+    }
+}
+
 /*---- TESTS ----*/
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::crypto::sign_ed25519::{self as sign, Signature};
-    use crate::primitives::asset::{AssetValues, ReceiptAsset};
+    use crate::primitives::asset::{AssetValuesError, ReceiptAsset};
+    use crate::primitives::druid::ExpectationComparison;
     use crate::script::OpCodes;
-    use crate::utils::script_utils::{tx_has_valid_p2sh_script, tx_outs_are_valid};
+    use crate::utils::druid_utils::{druid_is_valid, validate_druid_txs};
+    use crate::utils::script_utils::{tx_has_valid_p2sh_script, tx_is_valid, tx_outs_are_valid};
+    use crate::utils::test_utils::generate_tx_with_ins_and_outs_assets;
 
     #[test]
     // Creates a valid creation transaction
@@ -747,6 +1480,53 @@ mod tests {
         // TODO: Add assertion for full tx validity
     }
 
+    #[test]
+    /// Checks that script versions round trip through P2SH addresses, leaving
+    /// version 0 addresses and the serialized outputs holding them unchanged
+    fn test_p2sh_address_script_version() {
+        let script = Script::from(vec![StackEntry::Op(OpCodes::OP_1)]);
+        let legacy = construct_p2sh_address(&script);
+        let v1 = construct_p2sh_address_for(&script, SCRIPT_VERSION_V1).unwrap();
+        let legacy_tx_out = TxOut::new_token_amount(legacy.clone(), TokenAmount(1));
+
+        assert_eq!(
+            construct_p2sh_address_for(&script, SCRIPT_VERSION_V0),
+            Some(legacy.clone())
+        );
+        assert_eq!(v1.len(), STANDARD_ADDRESS_LENGTH);
+        assert_eq!(&v1[..4], "Hv01");
+        assert_eq!(
+            p2sh_address_script_version(&legacy),
+            Some(SCRIPT_VERSION_V0)
+        );
+        assert_eq!(p2sh_address_script_version(&v1), Some(SCRIPT_VERSION_V1));
+        assert_eq!(
+            construct_p2sh_address_for(&script, MAX_SCRIPT_VERSION + 1),
+            None
+        );
+
+        // Non canonical versions and non P2SH addresses have no script version
+        assert_eq!(
+            p2sh_address_script_version(&v1.replace("Hv01", "Hv00")),
+            None
+        );
+        assert_eq!(
+            p2sh_address_script_version(&v1.replace("Hv01", "HvA1")),
+            None
+        );
+        assert_eq!(p2sh_address_script_version(&hex::encode([0; 32])), None);
+
+        // The four field layout of outputs predating script versions
+        let legacy_bytes = serialize(&(
+            &legacy_tx_out.value,
+            legacy_tx_out.locktime,
+            &legacy_tx_out.drs_block_hash,
+            &legacy_tx_out.script_public_key,
+        ))
+        .unwrap();
+        assert_eq!(serialize(&legacy_tx_out).unwrap(), legacy_bytes);
+    }
+
     #[test]
     fn test_construct_a_valid_burn_tx() {
         let token_amount = TokenAmount(400000);
@@ -914,6 +1694,69 @@ mod tests {
         assert_ne!(btree.get(&tx_2_out_p), None);
     }
 
+    #[test]
+    /// Checks that DDE values keep their legacy layout in transactions up to
+    /// `NETWORK_VERSION_LEGACY_DDE`, which can only hold exact, unexpiring values
+    fn test_dde_values_versioned_layout() {
+        let expectation = DruidExpectation {
+            from: "alice".to_owned(),
+            to: "bob".to_owned(),
+            asset: Asset::token_u64(10),
+            ..Default::default()
+        };
+        let mut legacy_tx = construct_dde_tx(
+            "druid".to_owned(),
+            vec![],
+            vec![],
+            2,
+            vec![expectation.clone()],
+        );
+        legacy_tx.version = NETWORK_VERSION_LEGACY_DDE;
+
+        // The layout of DDE values predating comparisons and expiry heights
+        let legacy_bytes = serialize(&(
+            &legacy_tx.inputs,
+            &legacy_tx.outputs,
+            legacy_tx.version,
+            Some((
+                "druid",
+                2_usize,
+                vec![(&expectation.from, &expectation.to, &expectation.asset)],
+            )),
+        ))
+        .unwrap();
+        let decoded: Transaction = bincode::deserialize(&legacy_bytes).unwrap();
+
+        assert_eq!(serialize(&legacy_tx).unwrap(), legacy_bytes);
+        assert_eq!(decoded, legacy_tx);
+        assert!(tx_is_valid(&legacy_tx, |_| None));
+
+        let mut ranged_tx = legacy_tx.clone();
+        ranged_tx.druid_info.as_mut().unwrap().expectations[0].comparison =
+            ExpectationComparison::AtLeast;
+        let mut expiring_tx = legacy_tx;
+        expiring_tx.druid_info.as_mut().unwrap().expiry_height = Some(10);
+
+        assert!(serialize(&ranged_tx).is_err());
+        assert!(serialize(&expiring_tx).is_err());
+        assert!(!tx_is_valid(&ranged_tx, |_| None));
+        assert!(!tx_is_valid(&expiring_tx, |_| None));
+
+        ranged_tx.version = NETWORK_VERSION as usize;
+        let bytes = serialize(&ranged_tx).unwrap();
+        let json = serde_json::to_string(&ranged_tx).unwrap();
+
+        assert!(tx_is_valid(&ranged_tx, |_| None));
+        assert_eq!(
+            bincode::deserialize::<Transaction>(&bytes).unwrap(),
+            ranged_tx
+        );
+        assert_eq!(
+            serde_json::from_str::<Transaction>(&json).unwrap(),
+            ranged_tx
+        );
+    }
+
     #[test]
     // Creates a valid DDE transaction
     fn test_construct_a_valid_dde_tx() {
@@ -971,6 +1814,7 @@ mod tests {
             from: from_addr,
             to: to_asset,
             asset: data.clone(),
+            ..Default::default()
         }];
 
         // Actual DDE
@@ -1005,12 +1849,16 @@ mod tests {
                 // constructors with enough money for amount and excess, caller responsibility.
                 construct_payment_tx_ins(vec![])
             };
-            let excess_tx_out = TxOut::new_token_amount(sender_address_excess, amount - payment);
+            let excess_tx_out = TxOut::new_token_amount(
+                sender_address_excess,
+                amount.checked_sub(payment).unwrap(),
+            );
 
             let expectation = DruidExpectation {
                 from: from_addr.clone(),
                 to: alice_addr.clone(),
                 asset: Asset::receipt(1, Some("drs_tx_hash".to_owned()), None),
+                ..Default::default()
             };
 
             let mut tx = construct_rb_payments_send_tx(
@@ -1038,6 +1886,7 @@ mod tests {
                 from: from_addr,
                 to: bob_addr,
                 asset: Asset::Token(payment),
+                ..Default::default()
             };
 
             // create the sender that match the receiver.
@@ -1187,7 +2036,7 @@ mod tests {
         //
         let actual: Vec<String> = assets
             .iter()
-            .map(construct_tx_in_signable_asset_hash)
+            .map(|asset| construct_tx_in_signable_asset_hash(asset, NETWORK_VERSION as usize))
             .collect();
 
         let expected: Vec<String> = vec![
@@ -1258,4 +2107,356 @@ mod tests {
         //
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    // Traces the owners of an item from its mint through later payments
+    fn test_get_item_owner_history() {
+        //
+        // Arrange
+        //
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, _) = sign::gen_keypair();
+        let genesis = construct_collection_create_tx(0, pk, &sk, None, None);
+        let collection_hash = construct_tx_hash(&genesis);
+        let mint = construct_item_mint_tx(0, collection_hash.clone(), 1, pk, &sk, None);
+        let mint_hash = construct_tx_hash(&mint);
+
+        let item = Asset::item(Some(collection_hash.clone()), 1, None, None);
+        let pay = |previous_out: OutPoint, to: &PublicKey| {
+            let signable_hash = construct_tx_in_signable_hash(&previous_out);
+            let signature = sign::sign_detached(signable_hash.as_bytes(), &sk);
+            let tx_ins = construct_payment_tx_ins(vec![TxConstructor {
+                previous_out,
+                signatures: vec![signature],
+                pub_keys: vec![pk],
+                address_version: None,
+            }]);
+            construct_payment_tx(tx_ins, construct_address(to), None, item.clone(), 0)
+        };
+        let first = pay(OutPoint::new(mint_hash.clone(), 0), &other_pk);
+        let first_hash = construct_tx_hash(&first);
+        let second = pay(OutPoint::new(first_hash.clone(), 0), &pk);
+        let second_hash = construct_tx_hash(&second);
+
+        let txs: BTreeMap<String, Transaction> = vec![
+            (collection_hash.clone(), genesis),
+            (mint_hash.clone(), mint),
+            (first_hash.clone(), first),
+            (second_hash.clone(), second),
+        ]
+        .into_iter()
+        .collect();
+
+        //
+        // Act
+        //
+        let history = get_item_owner_history(&collection_hash, 1, &txs);
+        let genesis_history = get_item_owner_history(&collection_hash, 0, &txs);
+        let missing_history = get_item_owner_history(&collection_hash, 2, &txs);
+
+        //
+        // Assert
+        //
+        let owners: Vec<(String, Option<String>)> = history
+            .into_iter()
+            .map(|(out_point, tx_out)| (out_point.t_hash, tx_out.script_public_key.clone()))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![
+                (mint_hash, Some(construct_address(&pk))),
+                (first_hash, Some(construct_address(&other_pk))),
+                (second_hash, Some(construct_address(&pk))),
+            ]
+        );
+        assert_eq!(genesis_history.len(), 1);
+        assert!(missing_history.is_empty());
+    }
+
+    #[test]
+    // Creates a large data asset in chunks and assembles it back from the created outputs
+    fn test_construct_chunked_create_txs() {
+        let (pk, sk) = sign::gen_keypair();
+        let data: Vec<u8> = (0..(DATA_CHUNK_SIZE * 2 + 10)).map(|i| i as u8).collect();
+
+        let (manifest, txs) = construct_chunked_create_txs(0, &data, pk, &sk);
+
+        let assets: Vec<&DataAsset> = txs
+            .iter()
+            .filter_map(|tx| match &tx.outputs[0].value {
+                Asset::Data(data_asset) => Some(data_asset),
+                _ => None,
+            })
+            .collect();
+        let stored_manifest = DataManifest::decode(&assets[assets.len() - 1].data).unwrap();
+
+        assert_eq!(txs.len(), 4);
+        assert!(txs.iter().all(|tx| tx.is_create_tx()));
+        assert_eq!(stored_manifest, manifest);
+        assert_eq!(manifest.assemble_from_assets(assets.into_iter()), Ok(data));
+    }
+
+    #[test]
+    // Creates a sealed data asset and transfers it to a new owner who can read it
+    fn test_construct_encrypted_create_and_transfer_tx() {
+        let (pk, sk) = sign::gen_keypair();
+        let (new_pk, new_sk) = sign::gen_keypair();
+        let recipient = sealedbox::public_key_from_secret(&sk).unwrap();
+        let new_recipient = sealedbox::public_key_from_secret(&new_sk).unwrap();
+        let plain_text = b"confidential document".to_vec();
+
+        let create_tx =
+            construct_encrypted_create_tx(0, plain_text.clone(), &recipient, pk, &sk, 1).unwrap();
+        let asset = match &create_tx.outputs[0].value {
+            Asset::Data(data_asset) => data_asset.clone(),
+            _ => panic!("Expected a data asset"),
+        };
+
+        assert!(asset.is_encrypted());
+        assert!(!asset
+            .data
+            .windows(plain_text.len())
+            .any(|w| w == plain_text));
+        assert_eq!(asset.decrypt(&sk), Some(plain_text.clone()));
+        assert_eq!(asset.decrypt(&new_sk), None);
+
+        let tx_ins = construct_payment_tx_ins(vec![]);
+        let address = construct_address(&new_pk);
+        let transfer_tx =
+            construct_encrypted_transfer_tx(tx_ins, &asset, &sk, address, &new_recipient, None)
+                .unwrap();
+        let transferred = match &transfer_tx.outputs[0].value {
+            Asset::Data(data_asset) => data_asset.clone(),
+            _ => panic!("Expected a data asset"),
+        };
+
+        assert_eq!(transferred.decrypt(&new_sk), Some(plain_text));
+        assert_eq!(transferred.decrypt(&sk), None);
+        assert_eq!(
+            construct_encrypted_transfer_tx(
+                vec![],
+                &asset,
+                &new_sk,
+                String::new(),
+                &recipient,
+                None
+            ),
+            None
+        );
+
+        let low_order_recipient = sealedbox::PublicKey::from_slice(&[0; 32]).unwrap();
+        assert_eq!(
+            sealedbox::public_key_from_secret(&sk),
+            Some(recipient),
+            "static key derivation must be deterministic"
+        );
+        assert_eq!(
+            sealedbox::seal(b"data".to_vec(), &low_order_recipient),
+            None
+        );
+    }
+
+    #[test]
+    /// Checks that an expired DDE transaction is refunded from the same inputs
+    fn test_construct_dde_refund_tx() {
+        let (utxo, committed) =
+            generate_tx_with_ins_and_outs_assets(&[(10, None, None), (2, Some("drs"), None)], &[]);
+        let tx_outs = vec![
+            TxOut::new_token_amount("bob".to_owned(), TokenAmount(10)),
+            TxOut::new_asset(
+                "bob".to_owned(),
+                Asset::receipt(2, Some("drs".to_owned()), None),
+            ),
+        ];
+        let dde_tx = construct_expiring_dde_tx(
+            "VALUE".to_owned(),
+            committed.inputs,
+            tx_outs,
+            2,
+            Vec::new(),
+            100,
+        );
+        let never_expiring = construct_dde_tx(
+            "VALUE".to_owned(),
+            dde_tx.inputs.clone(),
+            dde_tx.outputs.clone(),
+            2,
+            Vec::new(),
+        );
+
+        let refund = construct_dde_refund_tx(&dde_tx, 100).unwrap();
+        let owner_address = utxo.values().next().unwrap().script_public_key.clone();
+
+        assert_eq!(dde_tx.druid_info.as_ref().unwrap().expiry_height, Some(100));
+        assert_eq!(construct_dde_refund_tx(&dde_tx, 99), None);
+        assert_eq!(construct_dde_refund_tx(&never_expiring, 1000), None);
+        assert_eq!(refund.inputs, dde_tx.inputs);
+        assert_eq!(refund.druid_info, None);
+        assert!(refund
+            .outputs
+            .iter()
+            .all(|tx_out| tx_out.script_public_key == owner_address));
+        assert!(tx_is_valid(&refund, |v| utxo.get(v)));
+
+        // Inputs spending from different addresses have no single owner to refund
+        let (_, other) = generate_tx_with_ins_and_outs_assets(&[(1, None, None)], &[]);
+        let mut mixed = dde_tx.clone();
+        mixed.inputs.extend(other.inputs);
+        assert_eq!(construct_dde_refund_tx(&mixed, 100), None);
+    }
+
+    /// Util function to create a DDE party committing inputs holding `assets`
+    fn create_dde_party(
+        name: &str,
+        assets: Vec<Asset>,
+        utxo: &mut BTreeMap<OutPoint, TxOut>,
+    ) -> (DdeParty, String) {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let tx_constructors = assets
+            .into_iter()
+            .enumerate()
+            .map(|(n, asset)| {
+                let previous_out = OutPoint::new(name.to_owned(), n as i32);
+                let signable_hash = construct_tx_in_signable_hash(&previous_out);
+                let tx_out = TxOut {
+                    value: asset,
+                    script_public_key: Some(address.clone()),
+                    ..Default::default()
+                };
+                utxo.insert(previous_out.clone(), tx_out);
+                TxConstructor {
+                    previous_out,
+                    signatures: vec![sign::sign_detached(signable_hash.as_bytes(), &sk)],
+                    pub_keys: vec![pk],
+                    address_version: None,
+                }
+            })
+            .collect();
+        let party = DdeParty {
+            tx_ins: construct_payment_tx_ins(tx_constructors),
+            change_address: Some(address.clone()),
+        };
+        (party, address)
+    }
+
+    #[test]
+    /// Checks that an N-party trade is built balanced and satisfiable
+    fn test_construct_dde_trade() {
+        //
+        // Arrange
+        //
+        let mut utxo = BTreeMap::new();
+        let receipt = |amount, drs: &str| Asset::receipt(amount, Some(drs.to_owned()), None);
+        let (alice, alice_addr) = create_dde_party("alice", vec![Asset::token_u64(100)], &mut utxo);
+        let (bob, bob_addr) = create_dde_party("bob", vec![receipt(5, "drs_b")], &mut utxo);
+        let (carol, carol_addr) = create_dde_party(
+            "carol",
+            vec![Asset::token_u64(50), receipt(2, "drs_c")],
+            &mut utxo,
+        );
+        let data = Asset::Data(DataAsset {
+            data: vec![1, 2, 3],
+            amount: 1,
+        });
+        let (erin, _) =
+            create_dde_party("erin", vec![Asset::token_u64(5), data.clone()], &mut utxo);
+        let parties = vec![alice, bob, carol];
+        let flow = |from, to: &String, asset| DdeFlow {
+            from,
+            to: to.clone(),
+            asset,
+        };
+        let flows = vec![
+            flow(0, &bob_addr, Asset::token_u64(60)),
+            flow(1, &carol_addr, receipt(5, "drs_b")),
+            flow(2, &alice_addr, receipt(2, "drs_c")),
+        ];
+
+        //
+        // Act
+        //
+        let trade = construct_dde_trade(&parties, &flows, Some(100), |o| utxo.get(o)).unwrap();
+        let build = |parties: &[DdeParty], flows: &[DdeFlow]| {
+            construct_dde_trade(parties, flows, None, |o| utxo.get(o)).map(|_| ())
+        };
+
+        let mut overspent = flows.clone();
+        overspent[0].asset = Asset::token_u64(101);
+        let mut unknown_party = flows.clone();
+        unknown_party[0].from = 3;
+        let mut no_change_address = parties.clone();
+        no_change_address[2].change_address = None;
+        let mut missing_input = parties.clone();
+        missing_input[1].tx_ins[0].previous_out = Some(OutPoint::new("dave".to_owned(), 0));
+        let mut data_flow = flows.clone();
+        data_flow[1].asset = data;
+        let mut data_input = parties.clone();
+        data_input[1] = erin;
+        data_input[1].tx_ins.extend(parties[1].tx_ins.clone());
+
+        //
+        // Assert
+        //
+        assert_eq!(trade.transactions.len(), 3);
+        assert_eq!(trade.expectations.len(), 3);
+        assert!(trade
+            .transactions
+            .iter()
+            .all(|tx| tx_is_valid(tx, |o| utxo.get(o))));
+        assert!(druid_is_valid(&trade.druid, trade.transactions.iter()));
+        assert_eq!(
+            validate_druid_txs(&trade.druid, trade.transactions.iter()),
+            Ok(())
+        );
+        assert_eq!(
+            trade.transactions[0].outputs[1],
+            TxOut::new_token_amount(alice_addr, TokenAmount(40))
+        );
+        assert_eq!(trade.transactions[1].outputs.len(), 1);
+        assert_eq!(
+            trade.transactions[2]
+                .druid_info
+                .as_ref()
+                .unwrap()
+                .expiry_height,
+            Some(100)
+        );
+
+        assert_eq!(
+            build(&parties[..1], &flows[..1]),
+            Err(DdeTradeError::TooFewParties)
+        );
+        assert_eq!(
+            build(&parties, &overspent),
+            Err(DdeTradeError::InsufficientInputs(
+                0,
+                AssetValuesError::TokenUnderflow
+            ))
+        );
+        assert_eq!(
+            build(&parties, &unknown_party),
+            Err(DdeTradeError::UnknownParty(3))
+        );
+        assert_eq!(build(&parties, &flows[..2]), Err(DdeTradeError::NoFlows(2)));
+        assert_eq!(
+            build(&no_change_address, &flows),
+            Err(DdeTradeError::MissingChangeAddress(2))
+        );
+        assert_eq!(
+            build(&missing_input, &flows),
+            Err(DdeTradeError::MissingInput(
+                1,
+                OutPoint::new("dave".to_owned(), 0)
+            ))
+        );
+        assert_eq!(
+            build(&parties, &data_flow),
+            Err(DdeTradeError::UnsupportedAsset(1))
+        );
+        assert_eq!(
+            build(&data_input, &flows),
+            Err(DdeTradeError::UnsupportedAsset(1))
+        );
+    }
 }